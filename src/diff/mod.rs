@@ -1,39 +1,336 @@
 // Copyright (C) 2024 SUSE LLC <petr.pavlu@suse.com>
 // SPDX-License-Identifier: GPL-2.0-or-later
 
+use std::borrow::Cow;
+use std::cmp::min;
+
+#[cfg(test)]
+mod tests;
+
+/// A single line of a diff, with its prefix and its positions in the old and new lines.
+///
+/// The text of the line is taken from the old or new lines, unless it is overridden by `marked`,
+/// which holds the line with its changed words highlighted.
+struct DiffLine {
+    prefix: char,
+    old: usize,
+    new: usize,
+    marked: Option<String>,
+}
+
 pub struct UniDiff<'a> {
     old: &'a Vec<String>,
     new: &'a Vec<String>,
-    output: Vec<String>,
+    lines: Vec<DiffLine>,
+    words: bool,
 }
 
 impl UniDiff<'_> {
-    fn push_output(&mut self, prefix: char, lines: &[String]) {
-        for line in lines.iter() {
-            let mut marked_line = String::new();
-            marked_line.push(prefix);
-            marked_line.push_str(line);
-            self.output.push(marked_line);
+    fn push_lines(&mut self, prefix: char, old: usize, new: usize, len: usize) {
+        for i in 0..len {
+            let (old, new) = match prefix {
+                ' ' => (old + i, new + i),
+                '-' => (old + i, new),
+                _ => (old, new + i),
+            };
+            self.lines.push(DiffLine {
+                prefix,
+                old,
+                new,
+                marked: None,
+            });
+        }
+    }
+
+    /// Groups the diff lines into hunks with up to `context` lines of unchanged context around the
+    /// changes and formats them, each starting with a standard `@@ -l,s +l,s @@` header.
+    fn format_hunks(&self, context: usize) -> Vec<String> {
+        let mut output = Vec::new();
+
+        let mut i = 0;
+        while i < self.lines.len() {
+            // Find the next change.
+            if self.lines[i].prefix == ' ' {
+                i += 1;
+                continue;
+            }
+
+            // Extend the hunk with subsequent changes which are separated by at most twice the
+            // number of context lines.
+            let start = i.saturating_sub(context);
+            let mut end = i;
+            let mut equal = 0;
+            while end < self.lines.len() {
+                if self.lines[end].prefix == ' ' {
+                    equal += 1;
+                } else {
+                    equal = 0;
+                }
+                end += 1;
+                if equal > 2 * context {
+                    break;
+                }
+            }
+            let end = end - equal + min(equal, context);
+
+            // Format the hunk.
+            let hunk = &self.lines[start..end];
+            let old_len = hunk.iter().filter(|line| line.prefix != '+').count();
+            let new_len = hunk.iter().filter(|line| line.prefix != '-').count();
+            output.push(format!(
+                "@@ -{} +{} @@",
+                format_range(hunk[0].old, old_len),
+                format_range(hunk[0].new, new_len)
+            ));
+            for line in hunk {
+                let text = match (&line.marked, line.prefix) {
+                    (Some(marked), _) => marked,
+                    (None, '+') => &self.new[line.new],
+                    (None, _) => &self.old[line.old],
+                };
+                let mut marked_line = String::new();
+                marked_line.push(line.prefix);
+                marked_line.push_str(text);
+                output.push(marked_line);
+            }
+
+            i = end;
         }
+        output
+    }
+}
+
+/// Formats a range of a hunk header, given the 0-based index of its first line and its length.
+///
+/// The format follows GNU diff: the length is omitted if it is 1 and an empty range refers to the
+/// line just before it.
+fn format_range(start: usize, len: usize) -> String {
+    match len {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, len),
     }
 }
 
-// TODO
 impl diffs::Diff for UniDiff<'_> {
     type Error = crate::Error;
 
-    fn equal(&mut self, old: usize, _new: usize, len: usize) -> Result<(), Self::Error> {
-        self.push_output(' ', &self.old[old..old + len]);
+    fn equal(&mut self, old: usize, new: usize, len: usize) -> Result<(), Self::Error> {
+        self.push_lines(' ', old, new, len);
+        Ok(())
+    }
+
+    fn delete(&mut self, old: usize, len: usize, new: usize) -> Result<(), Self::Error> {
+        self.push_lines('-', old, new, len);
+        Ok(())
+    }
+
+    fn insert(&mut self, old: usize, new: usize, new_len: usize) -> Result<(), Self::Error> {
+        self.push_lines('+', old, new, new_len);
+        Ok(())
+    }
+
+    fn replace(
+        &mut self,
+        old: usize,
+        old_len: usize,
+        new: usize,
+        new_len: usize,
+    ) -> Result<(), Self::Error> {
+        let first_old = self.lines.len();
+        self.push_lines('-', old, new, old_len);
+        let first_new = self.lines.len();
+        self.push_lines('+', old + old_len, new, new_len);
+
+        // Highlight the changed words in the pairs of replaced lines, if requested.
+        if self.words {
+            for i in 0..min(old_len, new_len) {
+                let (old_marked, new_marked) = mark_words(&self.old[old + i], &self.new[new + i])?;
+                self.lines[first_old + i].marked = Some(old_marked);
+                self.lines[first_new + i].marked = Some(new_marked);
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// Default number of context lines around changes in a unified diff.
+pub const DEFAULT_CONTEXT: usize = 3;
+
+/// Formats the differences between two line vectors as a unified diff, with the default number of
+/// context lines.
+pub fn unified(old: &Vec<String>, new: &Vec<String>) -> Result<Vec<String>, crate::Error> {
+    unified_with(old, new, DEFAULT_CONTEXT)
+}
+
+/// Formats the differences between two line vectors as a unified diff, with up to `context` lines
+/// of unchanged context around each change.
+///
+/// The output is split into hunks, each starting with a `@@ -l,s +l,s @@` header. Unchanged lines
+/// which are not within the context of any change are omitted. No output is produced if the line
+/// vectors are equal.
+pub fn unified_with(
+    old: &Vec<String>,
+    new: &Vec<String>,
+    context: usize,
+) -> Result<Vec<String>, crate::Error> {
+    let mut diff = UniDiff {
+        old,
+        new,
+        lines: Vec::new(),
+        words: false,
+    };
+    diffs::myers::diff(&mut diff, old, 0, old.len(), new, 0, new.len())?;
+    Ok(diff.format_hunks(context))
+}
+
+/// Formats the differences between two line vectors as a unified diff, with up to `context` lines
+/// of unchanged context around each change, and highlights the changed words in replaced lines.
+///
+/// A removed line which is directly replaced by an added line has its removed words enclosed in
+/// `[-` and `-]`, and the added line has its added words enclosed in `{+` and `+}`, in the style of
+/// `git diff --word-diff=plain`.
+pub fn unified_words_with(
+    old: &Vec<String>,
+    new: &Vec<String>,
+    context: usize,
+) -> Result<Vec<String>, crate::Error> {
+    // Use the Replace adapter so that deletions directly followed by insertions are reported as
+    // replacements and their lines can be compared word by word.
+    let mut diff = diffs::Replace::new(UniDiff {
+        old,
+        new,
+        lines: Vec::new(),
+        words: true,
+    });
+    diffs::myers::diff(&mut diff, old, 0, old.len(), new, 0, new.len())?;
+    Ok(diff.into_inner().format_hunks(context))
+}
+
+/// Splits a line into alternating runs of whitespace and other characters.
+fn split_words(line: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    let mut start = 0;
+    let mut in_space = None;
+    for (i, ch) in line.char_indices() {
+        let is_space = ch.is_whitespace();
+        if in_space.is_some_and(|in_space| in_space != is_space) {
+            words.push(&line[start..i]);
+            start = i;
+        }
+        in_space = Some(is_space);
+    }
+    if start < line.len() {
+        words.push(&line[start..]);
+    }
+    words
+}
+
+/// Builder of two lines with their differing words highlighted.
+struct WordDiff<'a> {
+    old: &'a [&'a str],
+    new: &'a [&'a str],
+    old_marked: String,
+    new_marked: String,
+}
+
+impl diffs::Diff for WordDiff<'_> {
+    type Error = crate::Error;
+
+    fn equal(&mut self, old: usize, new: usize, len: usize) -> Result<(), Self::Error> {
+        self.old_marked.push_str(&self.old[old..old + len].concat());
+        self.new_marked.push_str(&self.new[new..new + len].concat());
+        Ok(())
+    }
+
+    fn delete(&mut self, old: usize, len: usize, _new: usize) -> Result<(), Self::Error> {
+        self.old_marked.push_str("[-");
+        self.old_marked.push_str(&self.old[old..old + len].concat());
+        self.old_marked.push_str("-]");
+        Ok(())
+    }
+
+    fn insert(&mut self, _old: usize, new: usize, new_len: usize) -> Result<(), Self::Error> {
+        self.new_marked.push_str("{+");
+        self.new_marked
+            .push_str(&self.new[new..new + new_len].concat());
+        self.new_marked.push_str("+}");
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// Compares two lines word by word and returns them with the removed words marked in the old line
+/// and the added words marked in the new line.
+fn mark_words(old: &str, new: &str) -> Result<(String, String), crate::Error> {
+    let old_words = split_words(old);
+    let new_words = split_words(new);
+    let mut diff = WordDiff {
+        old: &old_words,
+        new: &new_words,
+        old_marked: String::new(),
+        new_marked: String::new(),
+    };
+    diffs::myers::diff(
+        &mut diff,
+        &old_words,
+        0,
+        old_words.len(),
+        &new_words,
+        0,
+        new_words.len(),
+    )?;
+    Ok((diff.old_marked, diff.new_marked))
+}
+
+/// A side-by-side row, with the old line, a marker describing the change and the new line.
+type SideBySideRow = (String, char, String);
+
+pub struct SideBySideDiff<'a> {
+    old: &'a Vec<String>,
+    new: &'a Vec<String>,
+    rows: Vec<SideBySideRow>,
+}
+
+impl SideBySideDiff<'_> {
+    fn push_row(&mut self, old: Option<&String>, marker: char, new: Option<&String>) {
+        // Expand tabs so the columns can be aligned.
+        let expand = |line: Option<&String>| match line {
+            Some(line) => line.replace('\t', "    "),
+            None => String::new(),
+        };
+        self.rows.push((expand(old), marker, expand(new)));
+    }
+}
+
+impl diffs::Diff for SideBySideDiff<'_> {
+    type Error = crate::Error;
+
+    fn equal(&mut self, old: usize, new: usize, len: usize) -> Result<(), Self::Error> {
+        for i in 0..len {
+            self.push_row(Some(&self.old[old + i]), ' ', Some(&self.new[new + i]));
+        }
         Ok(())
     }
 
     fn delete(&mut self, old: usize, len: usize, _new: usize) -> Result<(), Self::Error> {
-        self.push_output('-', &self.old[old..old + len]);
+        for i in 0..len {
+            self.push_row(Some(&self.old[old + i]), '<', None);
+        }
         Ok(())
     }
 
     fn insert(&mut self, _old: usize, new: usize, new_len: usize) -> Result<(), Self::Error> {
-        self.push_output('+', &self.new[new..new + new_len]);
+        for i in 0..new_len {
+            self.push_row(None, '>', Some(&self.new[new + i]));
+        }
         Ok(())
     }
 
@@ -44,8 +341,24 @@ impl diffs::Diff for UniDiff<'_> {
         new: usize,
         new_len: usize,
     ) -> Result<(), Self::Error> {
-        self.push_output('-', &self.old[old..old + old_len]);
-        self.push_output('+', &self.new[new..new + new_len]);
+        for i in 0..std::cmp::max(old_len, new_len) {
+            let old_line = if i < old_len {
+                Some(&self.old[old + i])
+            } else {
+                None
+            };
+            let new_line = if i < new_len {
+                Some(&self.new[new + i])
+            } else {
+                None
+            };
+            let marker = match (old_line, new_line) {
+                (Some(_), Some(_)) => '|',
+                (Some(_), None) => '<',
+                _ => '>',
+            };
+            self.push_row(old_line, marker, new_line);
+        }
         Ok(())
     }
 
@@ -54,13 +367,56 @@ impl diffs::Diff for UniDiff<'_> {
     }
 }
 
-pub fn unified(old: &Vec<String>, new: &Vec<String>) -> Vec<String> {
-    let mut diff = UniDiff {
-        old: old,
-        new: new,
-        output: Vec::new(),
+/// Formats the differences between two line vectors side by side, in the style of `diff -y`.
+///
+/// Each output line contains the old line, padded to the width of the longest old line, a marker
+/// (` ` for equal lines, `|` for changed, `<` for deleted and `>` for inserted lines) and the new
+/// line.
+pub fn side_by_side(old: &Vec<String>, new: &Vec<String>) -> Result<Vec<String>, crate::Error> {
+    // Use the Replace adapter so that deletions directly followed by insertions are reported as
+    // replacements and can be shown on the same rows.
+    let mut diff = diffs::Replace::new(SideBySideDiff {
+        old,
+        new,
+        rows: Vec::new(),
+    });
+    diffs::myers::diff(&mut diff, old, 0, old.len(), new, 0, new.len())?;
+    let diff = diff.into_inner();
+
+    let width = diff
+        .rows
+        .iter()
+        .map(|(old_line, _, _)| old_line.chars().count())
+        .max()
+        .unwrap_or(0);
+    Ok(diff
+        .rows
+        .into_iter()
+        .map(|(old_line, marker, new_line)| {
+            format!("{:<width$} {} {}", old_line, marker, new_line)
+                .trim_end()
+                .to_string()
+        })
+        .collect())
+}
+
+/// Colors a line of a unified diff with ANSI escape codes according to its prefix, in the style of
+/// `git diff`, if `enabled` is set.
+///
+/// Removed lines are colored red, added lines green and hunk headers cyan. Other lines are
+/// returned unchanged.
+pub fn paint(line: &str, enabled: bool) -> Cow<'_, str> {
+    if !enabled {
+        return Cow::Borrowed(line);
+    }
+    let color = if line.starts_with("@@") {
+        "\x1b[36m"
+    } else if line.starts_with('-') {
+        "\x1b[31m"
+    } else if line.starts_with('+') {
+        "\x1b[32m"
+    } else {
+        return Cow::Borrowed(line);
     };
-    // TODO Check the result.
-    diffs::myers::diff(&mut diff, old, 0, old.len(), new, 0, new.len());
-    diff.output
+    Cow::Owned(format!("{}{}\x1b[0m", color, line))
 }
@@ -0,0 +1,118 @@
+// Copyright (C) 2024 SUSE LLC <petr.pavlu@suse.com>
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use super::*;
+
+#[test]
+fn unified_hunks() {
+    // Check that changes far apart are placed in separate hunks and the equal lines between them
+    // are collapsed.
+    let old = (1..=20).map(|i| format!("line{}", i)).collect::<Vec<_>>();
+    let mut new = old.clone();
+    new[1] = "changed2".to_string();
+    new.remove(15);
+    let diff = unified(&old, &new).unwrap();
+    assert_eq!(
+        diff,
+        crate::string_vec!(
+            "@@ -1,5 +1,5 @@",
+            " line1",
+            "-line2",
+            "+changed2",
+            " line3",
+            " line4",
+            " line5",
+            "@@ -13,7 +13,6 @@",
+            " line13",
+            " line14",
+            " line15",
+            "-line16",
+            " line17",
+            " line18",
+            " line19" //
+        )
+    );
+}
+
+#[test]
+fn unified_merged_hunks() {
+    // Check that changes separated by at most twice the number of context lines are placed in one
+    // hunk.
+    let old = (1..=10).map(|i| format!("line{}", i)).collect::<Vec<_>>();
+    let mut new = old.clone();
+    new[2] = "changed3".to_string();
+    new[5] = "changed6".to_string();
+    let diff = unified_with(&old, &new, 1).unwrap();
+    assert_eq!(
+        diff,
+        crate::string_vec!(
+            "@@ -2,6 +2,6 @@",
+            " line2",
+            "-line3",
+            "+changed3",
+            " line4",
+            " line5",
+            "-line6",
+            "+changed6",
+            " line7" //
+        )
+    );
+
+    // Check that no output is produced for equal inputs.
+    assert!(unified(&old, &old).unwrap().is_empty());
+}
+
+#[test]
+fn unified_empty_ranges() {
+    // Check that an empty range in a hunk header refers to the line just before it.
+    let old = crate::string_vec!("line1", "line2");
+    let new = crate::string_vec!("line1", "new", "line2");
+    assert_eq!(
+        unified_with(&old, &new, 0).unwrap(),
+        crate::string_vec!("@@ -1,0 +2 @@", "+new")
+    );
+    assert_eq!(
+        unified_with(&new, &old, 0).unwrap(),
+        crate::string_vec!("@@ -2 +1,0 @@", "-new")
+    );
+}
+
+#[test]
+fn unified_degenerate() {
+    // Check that degenerate inputs, with one or both sides empty, are diffed successfully.
+    let empty = Vec::new();
+    let lines = crate::string_vec!("line1", "line2");
+    assert_eq!(unified(&empty, &empty).unwrap(), Vec::<String>::new());
+    assert_eq!(
+        unified(&empty, &lines).unwrap(),
+        crate::string_vec!("@@ -0,0 +1,2 @@", "+line1", "+line2")
+    );
+    assert_eq!(
+        unified(&lines, &empty).unwrap(),
+        crate::string_vec!("@@ -1,2 +0,0 @@", "-line1", "-line2")
+    );
+}
+
+#[test]
+fn paint_lines() {
+    // Check that diff lines are colored only if enabled.
+    let old = crate::string_vec!("line1", "line2");
+    let new = crate::string_vec!("line1", "line3");
+    let diff = unified(&old, &new).unwrap();
+    for line in &diff {
+        assert!(!paint(line, false).contains('\x1b'));
+    }
+    let painted = diff
+        .iter()
+        .map(|line| paint(line, true).into_owned())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        painted,
+        crate::string_vec!(
+            "\x1b[36m@@ -1,2 +1,2 @@\x1b[0m",
+            " line1",
+            "\x1b[31m-line2\x1b[0m",
+            "\x1b[32m+line3\x1b[0m" //
+        )
+    );
+}
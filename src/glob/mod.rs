@@ -0,0 +1,60 @@
+// Copyright (C) 2024 SUSE LLC <petr.pavlu@suse.com>
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Minimal matching of paths against glob patterns.
+
+#[cfg(test)]
+mod tests;
+
+/// Returns whether a specified path, with components separated by forward slashes, matches a
+/// glob pattern.
+///
+/// The pattern supports `*`, which matches any sequence of characters within one path component,
+/// `?`, which matches any single character other than `/`, and `**`, which matches any sequence
+/// of characters including `/`. A pattern `**/` also matches no directory at all, so `**/foo`
+/// matches `foo`. Other characters match themselves.
+pub fn matches(pattern: &str, path: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let path = path.chars().collect::<Vec<_>>();
+    matches_from(&pattern, &path)
+}
+
+/// Returns whether a specified string contains any glob wildcard characters, as opposed to being a
+/// plain name which matches only itself.
+pub fn has_wildcards(pattern: &str) -> bool {
+    pattern.contains(['*', '?'])
+}
+
+/// Matches the remaining part of a path against the remaining part of a pattern.
+fn matches_from(pattern: &[char], path: &[char]) -> bool {
+    match pattern {
+        [] => path.is_empty(),
+        ['*', '*', rest @ ..] => {
+            if let ['/', dir_rest @ ..] = rest {
+                if matches_from(dir_rest, path) {
+                    return true;
+                }
+            }
+            (0..=path.len()).any(|i| matches_from(rest, &path[i..]))
+        }
+        ['*', rest @ ..] => {
+            for i in 0..=path.len() {
+                if matches_from(rest, &path[i..]) {
+                    return true;
+                }
+                if i < path.len() && path[i] == '/' {
+                    break;
+                }
+            }
+            false
+        }
+        ['?', rest @ ..] => match path {
+            [ch, path_rest @ ..] if *ch != '/' => matches_from(rest, path_rest),
+            _ => false,
+        },
+        [ch, rest @ ..] => match path {
+            [path_ch, path_rest @ ..] if path_ch == ch => matches_from(rest, path_rest),
+            _ => false,
+        },
+    }
+}
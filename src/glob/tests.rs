@@ -0,0 +1,27 @@
+// Copyright (C) 2024 SUSE LLC <petr.pavlu@suse.com>
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use super::*;
+
+#[test]
+fn match_star() {
+    // Check that a single star matches within one path component.
+    assert!(matches("*.symtypes", "foo.symtypes"));
+    assert!(matches("drivers/*.symtypes", "drivers/foo.symtypes"));
+    assert!(!matches("*.symtypes", "drivers/foo.symtypes"));
+    assert!(!matches("*.symtypes", "foo.symtypes.gz"));
+    assert!(matches("f?o", "foo"));
+    assert!(!matches("f?o", "f/o"));
+}
+
+#[test]
+fn match_double_star() {
+    // Check that a double star matches across path components, including no directory at all.
+    assert!(matches("drivers/gpu/**", "drivers/gpu/drm/foo.symtypes"));
+    assert!(!matches("drivers/gpu/**", "drivers/net/foo.symtypes"));
+    assert!(matches("**/foo.symtypes", "foo.symtypes"));
+    assert!(matches("**/foo.symtypes", "fs/ext4/foo.symtypes"));
+    assert!(matches("fs/**/*.symtypes", "fs/inode.symtypes"));
+    assert!(matches("fs/**/*.symtypes", "fs/ext4/inode.symtypes"));
+    assert!(!matches("fs/**/*.symtypes", "kernel/fs/inode.symtypes"));
+}
@@ -2,6 +2,7 @@
 // SPDX-License-Identifier: GPL-2.0-or-later
 
 pub mod diff;
+pub mod glob;
 pub mod sym;
 
 #[derive(Debug)]
@@ -1,11 +1,18 @@
 // Copyright (C) 2024 SUSE LLC <petr.pavlu@suse.com>
 // SPDX-License-Identifier: GPL-2.0-or-later
 
-use ksymtypes::sym::SymCorpus;
+use ksymtypes::sym::{
+    Change, ChangeSeverity, CompareOptions, CompareSummary, ConsolidateOptions, ConsolidatedFile,
+    Diagnostic, DiffRenderer, InputFormat, LoadOptions, Severity, SideBySideRenderer, SortOrder,
+    SymCorpus, TypeKind, UnifiedRenderer,
+};
+use ksymtypes::{diff, glob};
 use log::debug;
-use std::path::Path;
-use std::time::Instant;
-use std::{env, process};
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use std::{env, fs, mem, process, thread};
 
 /// A type to measure elapsed time for some operation.
 ///
@@ -48,10 +55,21 @@ fn print_usage(program: &str) {
             "\n",
             "Options:\n",
             "  -h, --help            print this help\n",
+            "  -j, --jobs=NUM        use NUM workers by default in all commands, instead of the\n",
+            "                        number of available CPUs\n",
             "\n",
             "Commands:\n",
             "  consolidate           consolidate symtypes into a single file\n",
             "  compare               show differences between two symtypes corpuses\n",
+            "  stats                 show statistics about a symtypes corpus\n",
+            "  verify                check that a consolidated file reads back identically\n",
+            "  text-diff             show a text diff of two corpuses in the consolidated form\n",
+            "  export-diff           list exports added or removed between two corpuses\n",
+            "  check                 run integrity checks of a symtypes corpus\n",
+            "  split                 expand a symtypes corpus into per-file symtypes\n",
+            "  list                  list exports or types of a symtypes corpus\n",
+            "  show                  show the definition of a symbol and all types it uses\n",
+            "  duplicates            list differently named types with the same declaration\n",
         ),
         program
     );
@@ -61,13 +79,49 @@ fn print_usage(program: &str) {
 fn print_consolidate_usage(program: &str) {
     print!(
         concat!(
-            "Usage: {} consolidate [OPTION...] PATH\n",
+            "Usage: {} consolidate [OPTION...] {{PATH... | --manifest=FILE}}\n",
             "Consolidate symtypes into a single file.\n",
             "\n",
             "Options:\n",
             "  -h, --help            print this help\n",
             "  -j, --jobs=NUM        use NUM workers to perform the operation simultaneously\n",
+            "  --stdin-format=FORMAT interpret standard input as auto (default), single or\n",
+            "                        consolidated\n",
             "  -o, --output=FILE     write the result in a specified file, instead of stdout\n",
+            "  --manifest=FILE       read symtypes fragments described by a manifest FILE\n",
+            "  --manifest-out=FILE   write a JSON description of the written file records to FILE\n",
+            "  --include-orphans     write also types not reachable from any export\n",
+            "  --max-variants=NUM    fail if any type has more than NUM variants\n",
+            "  --single-variant      fail if any type differs between files\n",
+            "  --check-refs          check that all type references resolve after loading\n",
+            "  --check-brackets      warn about records with unbalanced brackets\n",
+            "  --strict              fail on records with unbalanced brackets\n",
+            "  --allow-duplicate-exports\n",
+            "                        warn about exports declared in multiple files instead of\n",
+            "                        failing\n",
+            "  --merge-file-records  merge F# records of the same file instead of failing\n",
+            "  --skip-errors         warn about unreadable directory entries instead of failing\n",
+            "  --ext=EXT             collect files with extension EXT from directories, instead of\n",
+            "                        symtypes\n",
+            "  --include=PATTERN     collect only files matching PATTERN from directories\n",
+            "  --exclude=PATTERN     skip files matching PATTERN in directories\n",
+            "  --cache=DIR           reuse parsed records of unchanged files cached in DIR\n",
+            "  --progress            show progress of loading if stderr is a terminal\n",
+            "  --lenient             skip references to unknown types with a warning\n",
+            "  --canonical           number type variants by their content also in the dumped\n",
+            "                        records\n",
+            "  --first-use-numbering number type variants in the order of their first use\n",
+            "  --explicit-variants   suffix all types with their variant and list them in F#\n",
+            "                        records\n",
+            "  --wrap=NUM            continue F# records on a new line after every NUM references\n",
+            "  --sort=ORDER          order records by name (default), file or none\n",
+            "  --preserve-formatting write records with their original spacing\n",
+            "  --exclude-kind=KIND[,KIND...]\n",
+            "                        omit records of types of the given kinds (enum, struct,\n",
+            "                        typedef, union)\n",
+            "  --dump-records=FILE   write records of all loaded files as JSON to FILE\n",
+            "  --stats               print statistics of the deduplication to stderr\n",
+            "  --max-memory=MB       fail if the resident memory exceeds MB megabytes during load\n",
         ),
         program
     );
@@ -77,33 +131,243 @@ fn print_consolidate_usage(program: &str) {
 fn print_compare_usage(program: &str) {
     print!(
         concat!(
-            "Usage: {} compare [OPTION...] PATH1 PATH2\n",
+            "Usage: {} compare [OPTION...] {{PATH1 PATH2 | --expected-dir=DIR PATH}}\n",
             "Show differences between two symtypes corpuses.\n",
             "\n",
             "Options:\n",
             "  -h, --help            print this help\n",
             "  -j, --jobs=NUM        use NUM workers to perform the operation simultaneously\n",
+            "  --stdin-format=FORMAT interpret standard input as auto (default), single or\n",
+            "                        consolidated\n",
+            "  --color=WHEN          color differences: auto, always or never (default)\n",
+            "  --expand-typedefs     resolve typedefs to their targets before comparing\n",
+            "  --allow-tail-append   treat structs with only appended members as compatible\n",
+            "  --ptr-array-equiv     treat a trailing flexible array member as a pointer\n",
+            "  --ignore-reordering   ignore the order of enumerators with explicit values\n",
+            "  --decls-only          compare types only by their names, ignoring their bodies\n",
+            "  --shallow             compare only export signatures and their directly referenced\n",
+            "                        types\n",
+            "  --format=FORMAT       show differences in FORMAT: unified (default), side-by-side,\n",
+            "                        json\n",
+            "  -U, --unified=NUM     show NUM lines of context in unified differences (default 3)\n",
+            "  --word-diff           highlight changed words in unified differences\n",
+            "  --ignore-anon-names   ignore numeric suffixes of generated anonymous type names\n",
+            "  --anon-prefix=PREFIX  treat PREFIX followed by a number as an anonymous type name\n",
+            "  --check-refs          check that all type references resolve after loading\n",
+            "  --check-brackets      warn about records with unbalanced brackets\n",
+            "  --strict              fail on records with unbalanced brackets\n",
+            "  --allow-duplicate-exports\n",
+            "                        warn about exports declared in multiple files instead of\n",
+            "                        failing\n",
+            "  --merge-file-records  merge F# records of the same file instead of failing\n",
+            "  --skip-errors         warn about unreadable directory entries instead of failing\n",
+            "  --ext=EXT             collect files with extension EXT from directories, instead of\n",
+            "                        symtypes\n",
+            "  --include=PATTERN     collect only files matching PATTERN from directories\n",
+            "  --exclude=PATTERN     skip files matching PATTERN in directories\n",
+            "  --cache=DIR           reuse parsed records of unchanged files cached in DIR\n",
+            "  --lenient             skip references to unknown types with a warning\n",
+            "  --group-by-file       group the differences by the files that they affect\n",
+            "  --name-only           show only names of the changed exports and types\n",
+            "  --ignore=FILE         do not report removals and changes of symbols listed in FILE\n",
+            "  --progress            show progress of loading and comparison if stderr is a\n",
+            "                        terminal\n",
+            "  -q, --quiet           do not print the summary counts of the differences\n",
+            "  --streaming           compare two sorted consolidated files without loading them\n",
+            "  --min-severity=LEVEL  show only changes of at least LEVEL: info (default),\n",
+            "                        compatible, breaking\n",
+            "  --reverse             swap the roles of the two corpuses\n",
+            "  --only=SYMBOL         compare only the export SYMBOL and the types it references\n",
+            "  --added-ok            exit successfully if the only differences are added exports\n",
+            "  --crc                 show only exports whose hash of the referenced types changed\n",
+            "  --expected-dir=DIR    compare exports with their expected definitions in DIR\n",
+            "  --report-file=FILE    write also a report with a header and a summary to FILE\n",
         ),
         program
     );
 }
 
+/// Prints the usage message for the `stats` command on `stdout`.
+fn print_stats_usage(program: &str) {
+    print!(
+        concat!(
+            "Usage: {} stats [OPTION...] PATH\n",
+            "Show statistics about a symtypes corpus.\n",
+            "\n",
+            "Options:\n",
+            "  -h, --help            print this help\n",
+            "  -j, --jobs=NUM        use NUM workers to perform the operation simultaneously\n",
+            "  --stdin-format=FORMAT interpret standard input as auto (default), single or\n",
+            "                        consolidated\n",
+            "  --divergent           list types with multiple variants, sorted by their count\n",
+            "  --max-depth           show the longest chain of type references from an export\n",
+        ),
+        program
+    );
+}
+
+/// Prints the usage message for the `verify` command on `stdout`.
+fn print_verify_usage(program: &str) {
+    print!(
+        concat!(
+            "Usage: {} verify [OPTION...] FILE\n",
+            "Check that a consolidated file reads back identically.\n",
+            "\n",
+            "Options:\n",
+            "  -h, --help            print this help\n",
+        ),
+        program
+    );
+}
+
+/// Prints the usage message for the `text-diff` command on `stdout`.
+fn print_text_diff_usage(program: &str) {
+    print!(
+        concat!(
+            "Usage: {} text-diff [OPTION...] PATH1 PATH2\n",
+            "Show a text diff of two symtypes corpuses in the consolidated form.\n",
+            "\n",
+            "Options:\n",
+            "  -h, --help            print this help\n",
+            "  -j, --jobs=NUM        use NUM workers to perform the operation simultaneously\n",
+            "  --stdin-format=FORMAT interpret standard input as auto (default), single or\n",
+            "                        consolidated\n",
+            "  --color=WHEN          color differences: auto, always or never (default)\n",
+        ),
+        program
+    );
+}
+
+/// Prints the usage message for the `export-diff` command on `stdout`.
+fn print_export_diff_usage(program: &str) {
+    print!(
+        concat!(
+            "Usage: {} export-diff [OPTION...] PATH1 PATH2\n",
+            "List exports added or removed between two symtypes corpuses.\n",
+            "\n",
+            "Options:\n",
+            "  -h, --help            print this help\n",
+            "  -j, --jobs=NUM        use NUM workers to perform the operation simultaneously\n",
+            "  --stdin-format=FORMAT interpret standard input as auto (default), single or\n",
+            "                        consolidated\n",
+        ),
+        program
+    );
+}
+
+/// Prints the usage message for the `check` command on `stdout`.
+fn print_check_usage(program: &str) {
+    print!(
+        concat!(
+            "Usage: {} check [OPTION...] PATH\n",
+            "Run integrity checks of a symtypes corpus.\n",
+            "\n",
+            "Options:\n",
+            "  -h, --help            print this help\n",
+            "  -j, --jobs=NUM        use NUM workers to perform the operation simultaneously\n",
+            "  --stdin-format=FORMAT interpret standard input as auto (default), single or\n",
+            "                        consolidated\n",
+            "  --diagnostics-format=FORMAT\n",
+            "                        print diagnostics as text (default) or json\n",
+        ),
+        program
+    );
+}
+
+/// Prints the usage message for the `split` command on `stdout`.
+fn print_split_usage(program: &str) {
+    print!(
+        concat!(
+            "Usage: {} split [OPTION...] PATH OUTDIR\n",
+            "Expand a symtypes corpus into per-file symtypes under OUTDIR.\n",
+            "\n",
+            "Options:\n",
+            "  -h, --help            print this help\n",
+            "  -j, --jobs=NUM        use NUM workers to perform the operation simultaneously\n",
+            "  --stdin-format=FORMAT interpret standard input as auto (default), single or\n",
+            "                        consolidated\n",
+        ),
+        program
+    );
+}
+
+/// Prints the usage message for the `list` command on `stdout`.
+fn print_list_usage(program: &str) {
+    print!(
+        concat!(
+            "Usage: {} list [OPTION...] PATH [PATTERN]\n",
+            "List exports or types of a symtypes corpus, optionally only ones matching a glob\n",
+            "PATTERN.\n",
+            "\n",
+            "Options:\n",
+            "  -h, --help            print this help\n",
+            "  -j, --jobs=NUM        use NUM workers to perform the operation simultaneously\n",
+            "  --stdin-format=FORMAT interpret standard input as auto (default), single or\n",
+            "                        consolidated\n",
+            "  --exports             list names of all exports (default)\n",
+            "  --types               list names of all types, including exports\n",
+        ),
+        program
+    );
+}
+
+/// Prints the usage message for the `duplicates` command on `stdout`.
+fn print_duplicates_usage(program: &str) {
+    print!(
+        concat!(
+            "Usage: {} duplicates [OPTION...] PATH\n",
+            "List groups of differently named types with the same declaration.\n",
+            "\n",
+            "Options:\n",
+            "  -h, --help            print this help\n",
+            "  -j, --jobs=NUM        use NUM workers to perform the operation simultaneously\n",
+            "  --stdin-format=FORMAT interpret standard input as auto (default), single or\n",
+            "                        consolidated\n",
+        ),
+        program
+    );
+}
+
+/// Prints the usage message for the `show` command on `stdout`.
+fn print_show_usage(program: &str) {
+    print!(
+        concat!(
+            "Usage: {} show [OPTION...] SYMBOL PATH\n",
+            "Show the definition of a symbol and all types it uses. SYMBOL can be a glob pattern\n",
+            "matching multiple symbols.\n",
+            "\n",
+            "Options:\n",
+            "  -h, --help            print this help\n",
+            "  -j, --jobs=NUM        use NUM workers to perform the operation simultaneously\n",
+            "  --stdin-format=FORMAT interpret standard input as auto (default), single or\n",
+            "                        consolidated\n",
+            "  --depth=NUM           expand referenced types only up to NUM levels deep\n",
+        ),
+        program
+    );
+}
+
+/// Prints a diagnostic reported while processing a corpus on `stderr`.
+fn print_diagnostic(diagnostic: &Diagnostic) {
+    eprintln!("{}", diagnostic);
+}
+
 /// Handles an option with a mandatory value.
 ///
-/// When the `arg` matches the `short` or `long` variant, the function returns [`Ok(Some(String))`]
-/// with the option value. Otherwise, [`Ok(None)`] is returned when the `arg` doesn't match, or
-/// [`Err`] in case of an error.
+/// When the `arg` matches the `short` (if any) or `long` variant, the function returns
+/// [`Ok(Some(String))`] with the option value. Otherwise, [`Ok(None)`] is returned when the `arg`
+/// doesn't match, or [`Err`] in case of an error.
 fn handle_value_option<I>(
     arg: &str,
     args: &mut I,
-    short: &str,
+    short: Option<&str>,
     long: &str,
 ) -> Result<Option<String>, ()>
 where
     I: Iterator<Item = String>,
 {
     // Handle '-<short> <value>' and '--<long> <value>'.
-    if arg == short || arg == long {
+    if Some(arg) == short || arg == long {
         match args.next() {
             Some(value) => return Ok(Some(value.to_string())),
             None => {
@@ -113,209 +377,1767 @@ where
         };
     }
 
-    // Handle '-<short><value>'.
-    if let Some(value) = arg.strip_prefix(short) {
-        return Ok(Some(value.to_string()));
+    // Handle '-<short><value>'.
+    if let Some(short) = short {
+        if let Some(value) = arg.strip_prefix(short) {
+            return Ok(Some(value.to_string()));
+        }
+    }
+
+    // Handle '--<long>=<value>'.
+    if let Some(rem) = arg.strip_prefix(long) {
+        if let Some(value) = rem.strip_prefix("=") {
+            return Ok(Some(value.to_string()));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Handles the `-j`/`--jobs` option which specifies the number of workers to perform a given
+/// operation simultaneously.
+fn handle_jobs_option<I>(arg: &str, args: &mut I) -> Result<Option<i32>, ()>
+where
+    I: Iterator<Item = String>,
+{
+    if let Some(value) = handle_value_option(arg, args, Some("-j"), "--jobs")? {
+        match value.parse::<i32>() {
+            Ok(jobs) => {
+                if jobs < 1 {
+                    eprintln!("Invalid value for '{}': must be positive", arg);
+                    return Err(());
+                }
+                return Ok(Some(jobs));
+            }
+            Err(err) => {
+                eprintln!("Invalid value for '{}': {}", arg, err);
+                return Err(());
+            }
+        };
+    }
+
+    Ok(None)
+}
+
+/// Handles the `--color` option which specifies whether to color differences on the standard
+/// output, returning whether coloring is enabled.
+fn handle_color_option<I>(arg: &str, args: &mut I) -> Result<Option<bool>, ()>
+where
+    I: Iterator<Item = String>,
+{
+    if let Some(value) = handle_value_option(arg, args, None, "--color")? {
+        match value.as_str() {
+            "auto" => return Ok(Some(io::stdout().is_terminal())),
+            "always" => return Ok(Some(true)),
+            "never" => return Ok(Some(false)),
+            _ => {
+                eprintln!("Invalid value for '{}': unknown mode '{}'", arg, value);
+                return Err(());
+            }
+        };
+    }
+
+    Ok(None)
+}
+
+/// Handles the `--stdin-format` option which specifies how to interpret symtypes data read from the
+/// standard input.
+fn handle_stdin_format_option<I>(arg: &str, args: &mut I) -> Result<Option<InputFormat>, ()>
+where
+    I: Iterator<Item = String>,
+{
+    if let Some(value) = handle_value_option(arg, args, None, "--stdin-format")? {
+        match value.as_str() {
+            "auto" => return Ok(Some(InputFormat::Auto)),
+            "single" => return Ok(Some(InputFormat::Single)),
+            "consolidated" => return Ok(Some(InputFormat::Consolidated)),
+            _ => {
+                eprintln!("Invalid value for '{}': unknown format '{}'", arg, value);
+                return Err(());
+            }
+        };
+    }
+
+    Ok(None)
+}
+
+/// Reads a symtypes corpus from a specified path, which can be `-` to read from the standard input.
+fn read_corpus(
+    path: &str,
+    num_workers: i32,
+    stdin_format: InputFormat,
+    load_options: &LoadOptions,
+    do_timing: bool,
+) -> Result<SymCorpus, ()> {
+    let _timing = Timing::new(do_timing, &format!("Reading symtypes from '{}'", path));
+
+    let mut syms = SymCorpus::new();
+    let result = if path == "-" {
+        syms.load_stdin_with(stdin_format, load_options)
+    } else {
+        syms.load_with(Path::new(path), num_workers, load_options)
+    };
+    if let Err(err) = result {
+        eprintln!("Failed to read symtypes from '{}': {}", path, err);
+        return Err(());
+    }
+    Ok(syms)
+}
+
+/// Reads a symtypes corpus from fragment files described by a specified manifest.
+fn read_manifest(manifest: &str, do_timing: bool) -> Result<SymCorpus, ()> {
+    let _timing = Timing::new(do_timing, &format!("Reading manifest '{}'", manifest));
+
+    let mut syms = SymCorpus::new();
+    if let Err(err) = syms.load_manifest(Path::new(manifest)) {
+        eprintln!(
+            "Failed to read symtypes from manifest '{}': {}",
+            manifest, err
+        );
+        return Err(());
+    }
+    Ok(syms)
+}
+
+/// Handles the `consolidate` command which consolidates symtypes into a single file.
+fn do_consolidate<I>(program: &str, do_timing: bool, num_workers: i32, args: I) -> Result<(), ()>
+where
+    I: IntoIterator<Item = String>,
+{
+    // Parse specific command options.
+    let mut args = args.into_iter();
+    let mut output = "-".to_string();
+    let mut num_workers = num_workers;
+    let mut stdin_format = InputFormat::Auto;
+    let mut options = ConsolidateOptions::default();
+    let mut max_variants = None;
+    let mut single_variant = false;
+    let mut maybe_dump_records = None;
+    let mut canonical = false;
+    let mut maybe_manifest_out = None;
+    let mut load_options = LoadOptions::default();
+    let mut maybe_manifest = None;
+    let mut print_stats = false;
+    let mut paths = Vec::new();
+
+    loop {
+        let arg = match args.next() {
+            Some(arg) => arg,
+            None => break,
+        };
+
+        if let Some(value) = handle_value_option(&arg, &mut args, Some("-o"), "--output")? {
+            output = value;
+            continue;
+        }
+        if let Some(value) = handle_jobs_option(&arg, &mut args)? {
+            num_workers = value;
+            continue;
+        }
+        if let Some(value) = handle_stdin_format_option(&arg, &mut args)? {
+            stdin_format = value;
+            continue;
+        }
+        if let Some(value) = handle_value_option(&arg, &mut args, None, "--manifest")? {
+            maybe_manifest = Some(value);
+            continue;
+        }
+        if let Some(value) = handle_value_option(&arg, &mut args, None, "--exclude-kind")? {
+            for keyword in value.split(',') {
+                match keyword.parse::<TypeKind>() {
+                    Ok(kind) => options.exclude_kinds.push(kind),
+                    Err(err) => {
+                        eprintln!("Invalid value for '{}': {}", arg, err);
+                        return Err(());
+                    }
+                }
+            }
+            continue;
+        }
+        if let Some(value) = handle_value_option(&arg, &mut args, None, "--manifest-out")? {
+            maybe_manifest_out = Some(value);
+            continue;
+        }
+        if let Some(value) = handle_value_option(&arg, &mut args, None, "--dump-records")? {
+            maybe_dump_records = Some(value);
+            continue;
+        }
+        if let Some(value) = handle_value_option(&arg, &mut args, None, "--max-memory")? {
+            match value.parse::<u64>() {
+                Ok(value) if value > 0 => load_options.max_memory = Some(value * 1024 * 1024),
+                Ok(_) => {
+                    eprintln!("Invalid value for '{}': must be positive", arg);
+                    return Err(());
+                }
+                Err(err) => {
+                    eprintln!("Invalid value for '{}': {}", arg, err);
+                    return Err(());
+                }
+            }
+            continue;
+        }
+        if let Some(value) = handle_value_option(&arg, &mut args, None, "--sort")? {
+            match value.parse::<SortOrder>() {
+                Ok(sort) => options.sort = sort,
+                Err(err) => {
+                    eprintln!("Invalid value for '{}': {}", arg, err);
+                    return Err(());
+                }
+            }
+            continue;
+        }
+        if let Some(value) = handle_value_option(&arg, &mut args, None, "--wrap")? {
+            match value.parse::<usize>() {
+                Ok(value) if value > 0 => options.wrap_file_records = Some(value),
+                Ok(_) => {
+                    eprintln!("Invalid value for '{}': must be positive", arg);
+                    return Err(());
+                }
+                Err(err) => {
+                    eprintln!("Invalid value for '{}': {}", arg, err);
+                    return Err(());
+                }
+            }
+            continue;
+        }
+        if let Some(value) = handle_value_option(&arg, &mut args, None, "--max-variants")? {
+            match value.parse::<usize>() {
+                Ok(value) if value > 0 => max_variants = Some(value),
+                Ok(_) => {
+                    eprintln!("Invalid value for '{}': must be positive", arg);
+                    return Err(());
+                }
+                Err(err) => {
+                    eprintln!("Invalid value for '{}': {}", arg, err);
+                    return Err(());
+                }
+            }
+            continue;
+        }
+
+        if arg == "--canonical" {
+            canonical = true;
+            continue;
+        }
+        if arg == "--first-use-numbering" {
+            options.first_use_numbering = true;
+            continue;
+        }
+        if arg == "--explicit-variants" {
+            options.explicit_variants = true;
+            continue;
+        }
+        if arg == "--preserve-formatting" {
+            load_options.preserve_formatting = true;
+            options.preserve_formatting = true;
+            continue;
+        }
+        if arg == "--include-orphans" {
+            options.include_orphans = true;
+            continue;
+        }
+        if arg == "--single-variant" {
+            single_variant = true;
+            continue;
+        }
+        if arg == "--stats" {
+            print_stats = true;
+            continue;
+        }
+        if arg == "--check-refs" {
+            load_options.check_refs = true;
+            continue;
+        }
+        if arg == "--check-brackets" {
+            load_options.check_brackets = true;
+            continue;
+        }
+        if arg == "--strict" {
+            load_options.strict = true;
+            continue;
+        }
+        if arg == "--allow-duplicate-exports" {
+            load_options.allow_duplicate_exports = true;
+            continue;
+        }
+        if arg == "--merge-file-records" {
+            load_options.merge_file_records = true;
+            continue;
+        }
+        if arg == "--skip-errors" {
+            load_options.skip_errors = true;
+            load_options.diagnostic_callback = Some(Arc::new(print_diagnostic));
+            continue;
+        }
+        if let Some(value) = handle_value_option(&arg, &mut args, None, "--ext")? {
+            load_options.extensions.push(value);
+            continue;
+        }
+        if let Some(value) = handle_value_option(&arg, &mut args, None, "--include")? {
+            load_options.include.push(value);
+            continue;
+        }
+        if let Some(value) = handle_value_option(&arg, &mut args, None, "--exclude")? {
+            load_options.exclude.push(value);
+            continue;
+        }
+        if let Some(value) = handle_value_option(&arg, &mut args, None, "--cache")? {
+            load_options.cache_dir = Some(PathBuf::from(value));
+            continue;
+        }
+        if arg == "--progress" {
+            load_options.progress = io::stderr().is_terminal();
+            continue;
+        }
+        if arg == "--lenient" {
+            options.lenient = true;
+            options.diagnostic_callback = Some(Arc::new(print_diagnostic));
+            continue;
+        }
+        if arg == "-h" || arg == "--help" {
+            print_consolidate_usage(&program);
+            return Ok(());
+        }
+        if arg != "-" && (arg.starts_with("-") || arg.starts_with("--")) {
+            eprintln!("Unrecognized consolidate option '{}'", arg);
+            return Err(());
+        }
+        paths.push(arg);
+    }
+
+    let mut syms;
+    let path;
+    match maybe_manifest {
+        Some(_) if !paths.is_empty() => {
+            eprintln!("The consolidate source cannot be specified together with a manifest");
+            return Err(());
+        }
+        Some(manifest) => {
+            debug!("Consolidate manifest '{}' to '{}'", manifest, output);
+
+            syms = read_manifest(&manifest, do_timing)?;
+            path = manifest;
+        }
+        None if paths.is_empty() => {
+            eprintln!("The consolidate source is missing");
+            return Err(());
+        }
+        None => {
+            debug!("Consolidate '{}' to '{}'", paths.join("', '"), output);
+
+            if paths.iter().filter(|path| *path == "-").count() > 1 {
+                eprintln!("Only one consolidate source can be read from the standard input");
+                return Err(());
+            }
+
+            syms = SymCorpus::new();
+            for path in &paths {
+                let other = read_corpus(path, num_workers, stdin_format, &load_options, do_timing)?;
+
+                let _timing = Timing::new(do_timing, &format!("Merging symtypes from '{}'", path));
+
+                if let Err(err) = syms.merge_with(other, &load_options) {
+                    eprintln!("Failed to merge symtypes from '{}': {}", path, err);
+                    return Err(());
+                }
+            }
+            path = paths.join("', '");
+        }
+    }
+
+    if canonical {
+        syms.canonicalize();
+    }
+
+    if let Some(dump_records) = maybe_dump_records {
+        let _timing = Timing::new(do_timing, &format!("Writing records to '{}'", dump_records));
+
+        let result = fs::File::create(&dump_records)
+            .map_err(|err| err.to_string())
+            .and_then(|file| syms.write_records_json(file).map_err(|err| err.to_string()));
+        if let Err(err) = result {
+            eprintln!("Failed to write records to '{}': {}", dump_records, err);
+            return Err(());
+        }
+    }
+
+    if let Some(max_variants) = max_variants {
+        if let Err(err) = syms.check_max_variants(max_variants) {
+            eprintln!("Failed to consolidate symtypes from '{}': {}", path, err);
+            return Err(());
+        }
+    }
+    if single_variant {
+        if let Err(err) = syms.check_single_variant() {
+            eprintln!("Failed to consolidate symtypes from '{}': {}", path, err);
+            return Err(());
+        }
+    }
+
+    let consolidated_files = {
+        let _timing = Timing::new(
+            do_timing,
+            &format!("Writing consolidated symtypes to '{}'", output),
+        );
+
+        match syms.write_consolidated_file(&output, &options) {
+            Ok(consolidated_files) => consolidated_files,
+            Err(err) => {
+                eprintln!(
+                    "Failed to write consolidated symtypes to '{}': {}",
+                    output, err
+                );
+                return Err(());
+            }
+        }
+    };
+
+    if let Some(manifest_out) = maybe_manifest_out {
+        let _timing = Timing::new(
+            do_timing,
+            &format!("Writing consolidation manifest to '{}'", manifest_out),
+        );
+
+        if let Err(err) = write_consolidation_manifest(&manifest_out, &consolidated_files) {
+            eprintln!(
+                "Failed to write consolidation manifest to '{}': {}",
+                manifest_out, err
+            );
+            return Err(());
+        }
+    }
+
+    if print_stats {
+        eprint!("{}", syms.consolidate_stats());
+    }
+
+    Ok(())
+}
+
+/// Writes a JSON manifest describing the file records of a consolidated output.
+fn write_consolidation_manifest(
+    path: &str,
+    consolidated_files: &[ConsolidatedFile],
+) -> io::Result<()> {
+    let manifest = consolidated_files
+        .iter()
+        .map(|consolidated_file| {
+            serde_json::json!({
+                "path": consolidated_file.path.to_string_lossy(),
+                "types": consolidated_file.num_types,
+                "exports": consolidated_file.num_exports,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut writer = io::BufWriter::new(fs::File::create(path)?);
+    serde_json::to_writer_pretty(&mut writer, &manifest)?;
+    writeln!(writer)?;
+    writer.flush()
+}
+
+/// Handles the `compare` command which shows differences between two symtypes corpuses.
+///
+/// Returns whether any differences were found.
+fn do_compare<I>(program: &str, do_timing: bool, num_workers: i32, args: I) -> Result<bool, ()>
+where
+    I: IntoIterator<Item = String>,
+{
+    // Parse specific command options.
+    let mut args = args.into_iter();
+    let mut num_workers = num_workers;
+    let mut stdin_format = InputFormat::Auto;
+    let mut options = CompareOptions::default();
+    let mut load_options = LoadOptions::default();
+    let mut side_by_side = false;
+    let mut word_diff = false;
+    let mut json_format = false;
+    let mut color = false;
+    let mut context = diff::DEFAULT_CONTEXT;
+    let mut maybe_report_file = None;
+    let mut quiet = false;
+    let mut maybe_expected_dir = None;
+    let mut streaming = false;
+    let mut crc = false;
+    let mut added_ok = false;
+    let mut reverse = false;
+    let mut maybe_path1 = None;
+    let mut maybe_path2 = None;
+
+    loop {
+        let arg = match args.next() {
+            Some(arg) => arg,
+            None => break,
+        };
+
+        if let Some(value) = handle_jobs_option(&arg, &mut args)? {
+            num_workers = value;
+            continue;
+        }
+        if let Some(value) = handle_stdin_format_option(&arg, &mut args)? {
+            stdin_format = value;
+            continue;
+        }
+        if let Some(value) = handle_color_option(&arg, &mut args)? {
+            color = value;
+            continue;
+        }
+        if let Some(value) = handle_value_option(&arg, &mut args, None, "--format")? {
+            (side_by_side, json_format) = match value.as_str() {
+                "unified" => (false, false),
+                "side-by-side" => (true, false),
+                "json" => (false, true),
+                _ => {
+                    eprintln!("Invalid value for '{}': unknown format '{}'", arg, value);
+                    return Err(());
+                }
+            };
+            continue;
+        }
+        if let Some(value) = handle_value_option(&arg, &mut args, Some("-U"), "--unified")? {
+            match value.parse::<usize>() {
+                Ok(value) => context = value,
+                Err(err) => {
+                    eprintln!("Invalid value for '{}': {}", arg, err);
+                    return Err(());
+                }
+            }
+            continue;
+        }
+        if let Some(value) = handle_value_option(&arg, &mut args, None, "--anon-prefix")? {
+            options.anon_name_prefixes.push(value);
+            continue;
+        }
+        if let Some(value) = handle_value_option(&arg, &mut args, None, "--expected-dir")? {
+            maybe_expected_dir = Some(value);
+            continue;
+        }
+        if let Some(value) = handle_value_option(&arg, &mut args, None, "--report-file")? {
+            maybe_report_file = Some(value);
+            continue;
+        }
+        if let Some(value) = handle_value_option(&arg, &mut args, None, "--ignore")? {
+            let path = Path::new(&value);
+            let result = fs::File::open(path)
+                .map_err(|err| err.to_string())
+                .and_then(|file| {
+                    CompareOptions::read_ignore_list(path, file).map_err(|err| err.to_string())
+                });
+            match result {
+                Ok(names) => options.ignored_symbols.extend(names),
+                Err(err) => {
+                    eprintln!("Failed to read ignore list '{}': {}", value, err);
+                    return Err(());
+                }
+            }
+            continue;
+        }
+
+        if arg == "--expand-typedefs" {
+            options.expand_typedefs = true;
+            continue;
+        }
+        if arg == "--streaming" {
+            streaming = true;
+            continue;
+        }
+        if arg == "--crc" {
+            crc = true;
+            continue;
+        }
+        if arg == "--added-ok" {
+            added_ok = true;
+            continue;
+        }
+        if arg == "--reverse" {
+            reverse = true;
+            continue;
+        }
+        if let Some(value) = handle_value_option(&arg, &mut args, None, "--only")? {
+            options.only_symbols.insert(value);
+            continue;
+        }
+        if let Some(value) = handle_value_option(&arg, &mut args, None, "--min-severity")? {
+            match value.parse::<ChangeSeverity>() {
+                Ok(severity) => options.min_severity = severity,
+                Err(err) => {
+                    eprintln!("Invalid value for '{}': {}", arg, err);
+                    return Err(());
+                }
+            }
+            continue;
+        }
+        if arg == "--decls-only" {
+            options.decls_only = true;
+            continue;
+        }
+        if arg == "--shallow" {
+            options.shallow = true;
+            continue;
+        }
+        if arg == "--word-diff" {
+            word_diff = true;
+            continue;
+        }
+        if arg == "--ptr-array-equiv" {
+            options.ptr_array_equiv = true;
+            continue;
+        }
+        if arg == "--ignore-reordering" {
+            options.ignore_reordering = true;
+            continue;
+        }
+        if arg == "--allow-tail-append" {
+            options.allow_tail_append = true;
+            continue;
+        }
+        if arg == "--check-refs" {
+            load_options.check_refs = true;
+            continue;
+        }
+        if arg == "--check-brackets" {
+            load_options.check_brackets = true;
+            continue;
+        }
+        if arg == "--strict" {
+            load_options.strict = true;
+            continue;
+        }
+        if arg == "--allow-duplicate-exports" {
+            load_options.allow_duplicate_exports = true;
+            continue;
+        }
+        if arg == "--merge-file-records" {
+            load_options.merge_file_records = true;
+            continue;
+        }
+        if arg == "--skip-errors" {
+            load_options.skip_errors = true;
+            load_options.diagnostic_callback = Some(Arc::new(print_diagnostic));
+            continue;
+        }
+        if let Some(value) = handle_value_option(&arg, &mut args, None, "--ext")? {
+            load_options.extensions.push(value);
+            continue;
+        }
+        if let Some(value) = handle_value_option(&arg, &mut args, None, "--include")? {
+            load_options.include.push(value);
+            continue;
+        }
+        if let Some(value) = handle_value_option(&arg, &mut args, None, "--exclude")? {
+            load_options.exclude.push(value);
+            continue;
+        }
+        if let Some(value) = handle_value_option(&arg, &mut args, None, "--cache")? {
+            load_options.cache_dir = Some(PathBuf::from(value));
+            continue;
+        }
+        if arg == "--lenient" {
+            options.lenient = true;
+            options.diagnostic_callback = Some(Arc::new(print_diagnostic));
+            continue;
+        }
+        if arg == "--progress" {
+            options.progress = io::stderr().is_terminal();
+            load_options.progress = options.progress;
+            continue;
+        }
+        if arg == "--group-by-file" {
+            options.group_by_file = true;
+            continue;
+        }
+        if arg == "--name-only" {
+            options.name_only = true;
+            continue;
+        }
+        if arg == "-q" || arg == "--quiet" {
+            quiet = true;
+            continue;
+        }
+        if arg == "--ignore-anon-names" {
+            options
+                .anon_name_prefixes
+                .extend(CompareOptions::default_anon_name_prefixes());
+            continue;
+        }
+        if arg == "-h" || arg == "--help" {
+            print_compare_usage(&program);
+            return Ok(false);
+        }
+        if arg != "-" && (arg.starts_with("-") || arg.starts_with("--")) {
+            eprintln!("Unrecognized compare option '{}'", arg);
+            return Err(());
+        }
+        if maybe_path1.is_none() {
+            maybe_path1 = Some(arg);
+            continue;
+        }
+        if maybe_path2.is_none() {
+            maybe_path2 = Some(arg);
+            continue;
+        }
+        eprintln!("Excess compare argument '{}' specified", arg);
+        return Err(());
+    }
+
+    if json_format && maybe_report_file.is_some() {
+        eprintln!("The json format cannot be combined with a report file");
+        return Err(());
+    }
+
+    // Keep the output machine-readable when only names or json are requested.
+    if options.name_only || json_format {
+        quiet = true;
+    }
+
+    let renderer: Box<dyn DiffRenderer> = if side_by_side {
+        Box::new(SideBySideRenderer)
+    } else if word_diff {
+        Box::new(UnifiedRenderer::with_word_diff(context))
+    } else {
+        Box::new(UnifiedRenderer::with_context(context))
+    };
+
+    if let Some(expected_dir) = maybe_expected_dir {
+        let path = maybe_path1.ok_or_else(|| {
+            eprintln!("The compare source is missing");
+        })?;
+        if let Some(path2) = maybe_path2 {
+            eprintln!("Excess compare argument '{}' specified", path2);
+            return Err(());
+        }
+        return compare_with_expected(&expected_dir, &path, num_workers, stdin_format, do_timing);
+    }
+
+    let mut path1 = maybe_path1.ok_or_else(|| {
+        eprintln!("The first compare source is missing");
+    })?;
+    let mut path2 = maybe_path2.ok_or_else(|| {
+        eprintln!("The second compare source is missing");
+    })?;
+    if reverse {
+        mem::swap(&mut path1, &mut path2);
+    }
+
+    // Do the comparison.
+    debug!("Compare '{}' and '{}'", path1, path2);
+
+    if path1 == "-" && path2 == "-" {
+        eprintln!("Only one compare source can be read from the standard input");
+        return Err(());
+    }
+
+    // Determine whether the found differences should be reported by the exit status. Breaking
+    // changes always are, added exports unless they are allowed. Additive changes of types are
+    // compatible and so don't fail.
+    let is_failure = |summary: &CompareSummary| {
+        summary.has_breaking_differences() || (!added_ok && summary.added_exports > 0)
+    };
+
+    if streaming {
+        let summary = compare_streaming(&path1, &path2, &*renderer, do_timing)?;
+        if !quiet {
+            println!("{}", summary);
+        }
+        return Ok(is_failure(&summary));
+    }
+
+    let start_time = SystemTime::now();
+    let start = Instant::now();
+
+    let syms1 = read_corpus(&path1, num_workers, stdin_format, &load_options, do_timing)?;
+
+    let syms2 = read_corpus(&path2, num_workers, stdin_format, &load_options, do_timing)?;
+
+    if crc {
+        let _timing = Timing::new(do_timing, "Hash comparison");
+
+        let format_hash = |hash: Option<u64>| match hash {
+            Some(hash) => format!("0x{:016x}", hash),
+            None => "unknown".to_string(),
+        };
+        let changed = syms1.compare_hashes(&syms2);
+        for (name, hash, other_hash) in &changed {
+            println!(
+                "{}: {} -> {}",
+                name,
+                format_hash(*hash),
+                format_hash(*other_hash)
+            );
+        }
+        return Ok(!changed.is_empty());
+    }
+
+    let color = color && !side_by_side;
+    let maybe_report_file = match maybe_report_file {
+        Some(report_file) => report_file,
+        None => {
+            let _timing = Timing::new(do_timing, "Comparison");
+
+            let changes = match syms1.compare_with(&syms2, &options, &*renderer, num_workers) {
+                Ok(changes) => changes,
+                Err(err) => {
+                    eprintln!("Failed to compare '{}' and '{}': {}", path1, path2, err);
+                    return Err(());
+                }
+            };
+            let summary = CompareSummary::from_changes(&changes);
+            let write_output = || -> io::Result<()> {
+                let mut stdout = io::stdout().lock();
+                if json_format {
+                    let json = changes.iter().map(Change::to_json).collect::<Vec<_>>();
+                    writeln!(stdout, "{:#}", serde_json::Value::from(json))?;
+                } else {
+                    for line in syms1.format_changes(&syms2, &changes, &options) {
+                        writeln!(stdout, "{}", diff::paint(&line, color))?;
+                    }
+                    if !quiet {
+                        writeln!(stdout, "{}", summary)?;
+                    }
+                }
+                stdout.flush()
+            };
+            if let Err(err) = write_output() {
+                eprintln!("Failed to write the comparison to stdout: {}", err);
+                return Err(());
+            }
+            return Ok(is_failure(&summary));
+        }
+    };
+
+    // Render the comparison in memory so it can be both printed and archived in the report.
+    let mut output = Vec::new();
+    let summary = {
+        let _timing = Timing::new(do_timing, "Comparison");
+
+        match syms1.write_comparison(&syms2, &options, &*renderer, num_workers, &mut output) {
+            Ok(summary) => summary,
+            Err(err) => {
+                eprintln!("Failed to compare '{}' and '{}': {}", path1, path2, err);
+                return Err(());
+            }
+        }
+    };
+    let write_output = || -> io::Result<()> {
+        let mut stdout = io::stdout().lock();
+        if color {
+            for line in String::from_utf8_lossy(&output).lines() {
+                writeln!(stdout, "{}", diff::paint(line, color))?;
+            }
+        } else {
+            stdout.write_all(&output)?;
+        }
+        if !quiet {
+            writeln!(stdout, "{}", summary)?;
+        }
+        stdout.flush()
+    };
+    if let Err(err) = write_output() {
+        eprintln!("Failed to write the comparison to stdout: {}", err);
+        return Err(());
+    }
+
+    let _timing = Timing::new(
+        do_timing,
+        &format!("Writing report to '{}'", maybe_report_file),
+    );
+
+    let write_report = || -> io::Result<()> {
+        let mut writer = io::BufWriter::new(fs::File::create(&maybe_report_file)?);
+        writeln!(
+            writer,
+            "ksymtypes {} comparison report",
+            env!("CARGO_PKG_VERSION")
+        )?;
+        writeln!(writer, "Date: {}", format_utc_time(start_time))?;
+        writeln!(writer, "Run time: {:.3}s", start.elapsed().as_secs_f64())?;
+        writeln!(writer, "A: {}", path1)?;
+        writeln!(writer, "B: {}", path2)?;
+        writeln!(writer)?;
+        writer.write_all(&output)?;
+        if !output.is_empty() {
+            writeln!(writer)?;
+        }
+        writeln!(writer, "Summary:")?;
+        writeln!(writer, "Removed exports: {}", summary.removed_exports)?;
+        writeln!(writer, "Added exports: {}", summary.added_exports)?;
+        writeln!(writer, "Changed types: {}", summary.changed_types)?;
+        writer.flush()
+    };
+    if let Err(err) = write_report() {
+        eprintln!("Failed to write report '{}': {}", maybe_report_file, err);
+        return Err(());
+    }
+
+    Ok(is_failure(&summary))
+}
+
+/// Formats a specified time as a UTC date and time in the form `YYYY-MM-DD hh:mm:ss UTC`.
+fn format_utc_time(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+    let (days, day_secs) = (secs / 86400, secs % 86400);
+
+    // Convert the number of days since the epoch to a civil date, see
+    // https://howardhinnant.github.io/date_algorithms.html#civil_from_days.
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        day_secs / 3600,
+        day_secs / 60 % 60,
+        day_secs % 60
+    )
+}
+
+/// Opens a specified path for reading, which can be `-` to read from the standard input.
+fn open_input(path: &str) -> Result<Box<dyn io::Read>, ()> {
+    if path == "-" {
+        return Ok(Box::new(io::stdin().lock()));
+    }
+    match fs::File::open(path) {
+        Ok(file) => Ok(Box::new(file)),
+        Err(err) => {
+            eprintln!("Failed to open file '{}': {}", path, err);
+            Err(())
+        }
+    }
+}
+
+/// Compares two consolidated files in the canonical sorted form without loading them fully.
+///
+/// Returns the summary counts of the found differences.
+fn compare_streaming(
+    path1: &str,
+    path2: &str,
+    renderer: &dyn DiffRenderer,
+    do_timing: bool,
+) -> Result<CompareSummary, ()> {
+    let _timing = Timing::new(do_timing, "Streaming comparison");
+
+    let reader1 = open_input(path1)?;
+    let reader2 = open_input(path2)?;
+    match SymCorpus::compare_streaming(
+        Path::new(path1),
+        reader1,
+        Path::new(path2),
+        reader2,
+        renderer,
+        io::stdout(),
+    ) {
+        Ok(summary) => Ok(summary),
+        Err(err) => {
+            eprintln!("Failed to compare '{}' and '{}': {}", path1, path2, err);
+            Err(())
+        }
+    }
+}
+
+/// Compares exports of a corpus with their expected definitions in a specified directory.
+///
+/// Returns whether any differences were found.
+fn compare_with_expected(
+    expected_dir: &str,
+    path: &str,
+    num_workers: i32,
+    stdin_format: InputFormat,
+    do_timing: bool,
+) -> Result<bool, ()> {
+    debug!(
+        "Compare '{}' with expected files in '{}'",
+        path, expected_dir
+    );
+
+    let syms = read_corpus(
+        path,
+        num_workers,
+        stdin_format,
+        &LoadOptions::default(),
+        do_timing,
+    )?;
+
+    let _timing = Timing::new(do_timing, "Comparison");
+
+    match syms.compare_with_expected(Path::new(expected_dir)) {
+        Ok(lines) => {
+            for line in &lines {
+                println!("{}", line);
+            }
+            Ok(!lines.is_empty())
+        }
+        Err(err) => {
+            eprintln!(
+                "Failed to compare '{}' with expected files in '{}': {}",
+                path, expected_dir, err
+            );
+            Err(())
+        }
+    }
+}
+
+/// Handles the `stats` command which shows statistics about a symtypes corpus.
+fn do_stats<I>(program: &str, do_timing: bool, num_workers: i32, args: I) -> Result<(), ()>
+where
+    I: IntoIterator<Item = String>,
+{
+    // Parse specific command options.
+    let mut args = args.into_iter();
+    let mut num_workers = num_workers;
+    let mut stdin_format = InputFormat::Auto;
+    let mut divergent = false;
+    let mut max_depth = false;
+    let mut maybe_path = None;
+
+    while let Some(arg) = args.next() {
+        if let Some(value) = handle_jobs_option(&arg, &mut args)? {
+            num_workers = value;
+            continue;
+        }
+        if let Some(value) = handle_stdin_format_option(&arg, &mut args)? {
+            stdin_format = value;
+            continue;
+        }
+
+        if arg == "--divergent" {
+            divergent = true;
+            continue;
+        }
+        if arg == "--max-depth" {
+            max_depth = true;
+            continue;
+        }
+        if arg == "-h" || arg == "--help" {
+            print_stats_usage(program);
+            return Ok(());
+        }
+        if arg != "-" && (arg.starts_with("-") || arg.starts_with("--")) {
+            eprintln!("Unrecognized stats option '{}'", arg);
+            return Err(());
+        }
+        if maybe_path.is_none() {
+            maybe_path = Some(arg);
+            continue;
+        }
+        eprintln!("Excess stats argument '{}' specified", arg);
+        return Err(());
+    }
+
+    let path = maybe_path.ok_or_else(|| {
+        eprintln!("The stats source is missing");
+    })?;
+
+    // Load the corpus and report its statistics.
+    debug!("Stats '{}'", path);
+
+    let syms = read_corpus(
+        &path,
+        num_workers,
+        stdin_format,
+        &LoadOptions::default(),
+        do_timing,
+    )?;
+
+    if divergent {
+        for (name, count) in syms.multi_variant_types() {
+            println!("{} {}", name, count);
+        }
+    } else if max_depth {
+        let chain = syms.max_depth_chain().map_err(|err| {
+            eprintln!("Failed to find the deepest chain in '{}': {}", path, err);
+        })?;
+        println!("Max depth: {}", chain.len());
+        if !chain.is_empty() {
+            println!("{}", chain.join(" -> "));
+        }
+    } else {
+        println!("Files: {}", syms.num_files());
+        println!("Exports: {}", syms.num_exports());
+        println!("Types: {}", syms.num_types());
+        println!("Type variants: {}", syms.num_type_variants());
+        println!("Multi-variant types: {}", syms.multi_variant_types().len());
+    }
+
+    Ok(())
+}
+
+/// Handles the `verify` command which checks that a consolidated file reads back identically.
+fn do_verify<I>(program: &str, do_timing: bool, args: I) -> Result<(), ()>
+where
+    I: IntoIterator<Item = String>,
+{
+    // Parse specific command options.
+    let mut maybe_path = None;
+
+    for arg in args {
+        if arg == "-h" || arg == "--help" {
+            print_verify_usage(program);
+            return Ok(());
+        }
+        if arg.starts_with("-") || arg.starts_with("--") {
+            eprintln!("Unrecognized verify option '{}'", arg);
+            return Err(());
+        }
+        if maybe_path.is_none() {
+            maybe_path = Some(arg);
+            continue;
+        }
+        eprintln!("Excess verify argument '{}' specified", arg);
+        return Err(());
+    }
+
+    let path = maybe_path.ok_or_else(|| {
+        eprintln!("The verify source is missing");
+    })?;
+
+    // Load the file and consolidate it again in memory.
+    debug!("Verify '{}'", path);
+
+    let _timing = Timing::new(do_timing, &format!("Verifying '{}'", path));
+
+    let input = match fs::read_to_string(&path) {
+        Ok(input) => input,
+        Err(err) => {
+            eprintln!("Failed to read file '{}': {}", path, err);
+            return Err(());
+        }
+    };
+
+    let lines = match SymCorpus::verify_consolidated(Path::new(&path), &input) {
+        Ok(lines) => lines,
+        Err(err) => {
+            eprintln!("Failed to verify symtypes from '{}': {}", path, err);
+            return Err(());
+        }
+    };
+
+    // Print how the input differs from its canonical form, if at all.
+    if lines.is_empty() {
+        return Ok(());
+    }
+    println!("--- {}", path);
+    println!("+++ {} (reconsolidated)", path);
+    for line in lines {
+        println!("{}", line);
+    }
+    Err(())
+}
+
+/// Handles the `text-diff` command which shows a text diff of two corpuses in the consolidated
+/// form.
+fn do_text_diff<I>(program: &str, do_timing: bool, num_workers: i32, args: I) -> Result<(), ()>
+where
+    I: IntoIterator<Item = String>,
+{
+    // Parse specific command options.
+    let mut args = args.into_iter();
+    let mut num_workers = num_workers;
+    let mut stdin_format = InputFormat::Auto;
+    let mut color = false;
+    let mut maybe_path1 = None;
+    let mut maybe_path2 = None;
+
+    while let Some(arg) = args.next() {
+        if let Some(value) = handle_jobs_option(&arg, &mut args)? {
+            num_workers = value;
+            continue;
+        }
+        if let Some(value) = handle_stdin_format_option(&arg, &mut args)? {
+            stdin_format = value;
+            continue;
+        }
+        if let Some(value) = handle_color_option(&arg, &mut args)? {
+            color = value;
+            continue;
+        }
+
+        if arg == "-h" || arg == "--help" {
+            print_text_diff_usage(program);
+            return Ok(());
+        }
+        if arg != "-" && (arg.starts_with("-") || arg.starts_with("--")) {
+            eprintln!("Unrecognized text-diff option '{}'", arg);
+            return Err(());
+        }
+        if maybe_path1.is_none() {
+            maybe_path1 = Some(arg);
+            continue;
+        }
+        if maybe_path2.is_none() {
+            maybe_path2 = Some(arg);
+            continue;
+        }
+        eprintln!("Excess text-diff argument '{}' specified", arg);
+        return Err(());
+    }
+
+    let path1 = maybe_path1.ok_or_else(|| {
+        eprintln!("The first text-diff source is missing");
+    })?;
+    let path2 = maybe_path2.ok_or_else(|| {
+        eprintln!("The second text-diff source is missing");
+    })?;
+
+    // Consolidate both corpuses in memory and diff the resulting lines.
+    debug!("Text-diff '{}' and '{}'", path1, path2);
+
+    if path1 == "-" && path2 == "-" {
+        eprintln!("Only one text-diff source can be read from the standard input");
+        return Err(());
+    }
+
+    let mut consolidated = Vec::new();
+    for path in [&path1, &path2] {
+        let syms = read_corpus(
+            path,
+            num_workers,
+            stdin_format,
+            &LoadOptions::default(),
+            do_timing,
+        )?;
+
+        let _timing = Timing::new(do_timing, &format!("Consolidating '{}'", path));
+
+        match syms.to_consolidated_string() {
+            Ok(output) => consolidated.push(output.lines().map(String::from).collect::<Vec<_>>()),
+            Err(err) => {
+                eprintln!("Failed to consolidate symtypes from '{}': {}", path, err);
+                return Err(());
+            }
+        }
+    }
+
+    if consolidated[0] != consolidated[1] {
+        let lines = match diff::unified(&consolidated[0], &consolidated[1]) {
+            Ok(lines) => lines,
+            Err(err) => {
+                eprintln!("Failed to compare '{}' and '{}': {}", path1, path2, err);
+                return Err(());
+            }
+        };
+        println!("--- {}", path1);
+        println!("+++ {}", path2);
+        for line in lines {
+            println!("{}", diff::paint(&line, color));
+        }
+    }
+
+    Ok(())
+}
+
+/// Handles the `export-diff` command which lists exports added or removed between two corpuses.
+fn do_export_diff<I>(program: &str, do_timing: bool, num_workers: i32, args: I) -> Result<(), ()>
+where
+    I: IntoIterator<Item = String>,
+{
+    // Parse specific command options.
+    let mut args = args.into_iter();
+    let mut num_workers = num_workers;
+    let mut stdin_format = InputFormat::Auto;
+    let mut maybe_path1 = None;
+    let mut maybe_path2 = None;
+
+    while let Some(arg) = args.next() {
+        if let Some(value) = handle_jobs_option(&arg, &mut args)? {
+            num_workers = value;
+            continue;
+        }
+        if let Some(value) = handle_stdin_format_option(&arg, &mut args)? {
+            stdin_format = value;
+            continue;
+        }
+
+        if arg == "-h" || arg == "--help" {
+            print_export_diff_usage(program);
+            return Ok(());
+        }
+        if arg != "-" && (arg.starts_with("-") || arg.starts_with("--")) {
+            eprintln!("Unrecognized export-diff option '{}'", arg);
+            return Err(());
+        }
+        if maybe_path1.is_none() {
+            maybe_path1 = Some(arg);
+            continue;
+        }
+        if maybe_path2.is_none() {
+            maybe_path2 = Some(arg);
+            continue;
+        }
+        eprintln!("Excess export-diff argument '{}' specified", arg);
+        return Err(());
+    }
+
+    let path1 = maybe_path1.ok_or_else(|| {
+        eprintln!("The first export-diff source is missing");
+    })?;
+    let path2 = maybe_path2.ok_or_else(|| {
+        eprintln!("The second export-diff source is missing");
+    })?;
+
+    // Load both corpuses and compare their sets of exports.
+    debug!("Export-diff '{}' and '{}'", path1, path2);
+
+    if path1 == "-" && path2 == "-" {
+        eprintln!("Only one export-diff source can be read from the standard input");
+        return Err(());
     }
 
-    // Handle '--<long>=<value>'.
-    if let Some(rem) = arg.strip_prefix(long) {
-        if let Some(value) = rem.strip_prefix("=") {
-            return Ok(Some(value.to_string()));
-        }
+    let syms1 = read_corpus(
+        &path1,
+        num_workers,
+        stdin_format,
+        &LoadOptions::default(),
+        do_timing,
+    )?;
+    let syms2 = read_corpus(
+        &path2,
+        num_workers,
+        stdin_format,
+        &LoadOptions::default(),
+        do_timing,
+    )?;
+
+    let (removed, added) = syms1.export_diff(&syms2);
+    let mut lines = removed
+        .into_iter()
+        .map(|name| (name, '-'))
+        .chain(added.into_iter().map(|name| (name, '+')))
+        .collect::<Vec<_>>();
+    lines.sort();
+    for (name, prefix) in lines {
+        println!("{}{}", prefix, name);
     }
 
-    Ok(None)
+    Ok(())
 }
 
-/// Handles the `-j`/`--jobs` option which specifies the number of workers to perform a given
-/// operation simultaneously.
-fn handle_jobs_option<I>(arg: &str, args: &mut I) -> Result<Option<i32>, ()>
+/// Handles the `check` command which runs integrity checks of a symtypes corpus.
+fn do_check<I>(program: &str, do_timing: bool, num_workers: i32, args: I) -> Result<(), ()>
 where
-    I: Iterator<Item = String>,
+    I: IntoIterator<Item = String>,
 {
-    if let Some(value) = handle_value_option(arg, args, "-j", "--jobs")? {
-        match value.parse::<i32>() {
-            Ok(jobs) => {
-                if jobs < 1 {
-                    eprintln!("Invalid value for '{}': must be positive", arg);
+    // Parse specific command options.
+    let mut args = args.into_iter();
+    let mut num_workers = num_workers;
+    let mut stdin_format = InputFormat::Auto;
+    let mut json_format = false;
+    let mut maybe_path = None;
+
+    while let Some(arg) = args.next() {
+        if let Some(value) = handle_jobs_option(&arg, &mut args)? {
+            num_workers = value;
+            continue;
+        }
+        if let Some(value) = handle_stdin_format_option(&arg, &mut args)? {
+            stdin_format = value;
+            continue;
+        }
+        if let Some(value) = handle_value_option(&arg, &mut args, None, "--diagnostics-format")? {
+            json_format = match value.as_str() {
+                "text" => false,
+                "json" => true,
+                _ => {
+                    eprintln!("Invalid value for '{}': unknown format '{}'", arg, value);
                     return Err(());
                 }
-                return Ok(Some(jobs));
-            }
-            Err(err) => {
-                eprintln!("Invalid value for '{}': {}", arg, err);
-                return Err(());
-            }
-        };
+            };
+            continue;
+        }
+
+        if arg == "-h" || arg == "--help" {
+            print_check_usage(program);
+            return Ok(());
+        }
+        if arg != "-" && (arg.starts_with("-") || arg.starts_with("--")) {
+            eprintln!("Unrecognized check option '{}'", arg);
+            return Err(());
+        }
+        if maybe_path.is_none() {
+            maybe_path = Some(arg);
+            continue;
+        }
+        eprintln!("Excess check argument '{}' specified", arg);
+        return Err(());
     }
 
-    Ok(None)
+    let path = maybe_path.ok_or_else(|| {
+        eprintln!("The check source is missing");
+    })?;
+
+    // Load the corpus and report the found problems. Duplicate exports are allowed when loading
+    // so they can be reported as diagnostics.
+    debug!("Check '{}'", path);
+
+    let load_options = LoadOptions {
+        allow_duplicate_exports: true,
+        ..Default::default()
+    };
+    let syms = read_corpus(&path, num_workers, stdin_format, &load_options, do_timing)?;
+
+    let diagnostics = {
+        let _timing = Timing::new(do_timing, "Checking");
+        syms.check()
+    };
+
+    if json_format {
+        let json = diagnostics
+            .iter()
+            .map(Diagnostic::to_json)
+            .collect::<Vec<_>>();
+        println!("{:#}", serde_json::Value::from(json));
+    } else {
+        for diagnostic in &diagnostics {
+            println!("{}", diagnostic);
+        }
+    }
+
+    // Fail if any error was found.
+    if diagnostics
+        .iter()
+        .any(|diagnostic| diagnostic.severity == Severity::Error)
+    {
+        return Err(());
+    }
+    Ok(())
 }
 
-/// Handles the `consolidate` command which consolidates symtypes into a single file.
-fn do_consolidate<I>(program: &str, do_timing: bool, args: I) -> Result<(), ()>
+/// Handles the `split` command which expands a symtypes corpus into per-file symtypes.
+fn do_split<I>(program: &str, do_timing: bool, num_workers: i32, args: I) -> Result<(), ()>
 where
     I: IntoIterator<Item = String>,
 {
     // Parse specific command options.
     let mut args = args.into_iter();
-    let mut output = "-".to_string();
-    let mut num_workers = 1;
+    let mut num_workers = num_workers;
+    let mut stdin_format = InputFormat::Auto;
     let mut maybe_path = None;
+    let mut maybe_outdir = None;
 
-    loop {
-        let arg = match args.next() {
-            Some(arg) => arg,
-            None => break,
-        };
-
-        if let Some(value) = handle_value_option(&arg, &mut args, "-o", "--output")? {
-            output = value;
-            continue;
-        }
+    while let Some(arg) = args.next() {
         if let Some(value) = handle_jobs_option(&arg, &mut args)? {
             num_workers = value;
             continue;
         }
+        if let Some(value) = handle_stdin_format_option(&arg, &mut args)? {
+            stdin_format = value;
+            continue;
+        }
 
         if arg == "-h" || arg == "--help" {
-            print_consolidate_usage(&program);
+            print_split_usage(program);
             return Ok(());
         }
-        if arg.starts_with("-") || arg.starts_with("--") {
-            eprintln!("Unrecognized consolidate option '{}'", arg);
+        if arg != "-" && (arg.starts_with("-") || arg.starts_with("--")) {
+            eprintln!("Unrecognized split option '{}'", arg);
             return Err(());
         }
         if maybe_path.is_none() {
             maybe_path = Some(arg);
             continue;
         }
-        eprintln!("Excess consolidate argument '{}' specified", arg);
+        if maybe_outdir.is_none() {
+            maybe_outdir = Some(arg);
+            continue;
+        }
+        eprintln!("Excess split argument '{}' specified", arg);
         return Err(());
     }
 
     let path = maybe_path.ok_or_else(|| {
-        eprintln!("The consolidate source is missing");
+        eprintln!("The split source is missing");
     })?;
+    let outdir = maybe_outdir.ok_or_else(|| {
+        eprintln!("The split output directory is missing");
+    })?;
+
+    // Load the corpus and write its files.
+    debug!("Split '{}' to '{}'", path, outdir);
+
+    let syms = read_corpus(
+        &path,
+        num_workers,
+        stdin_format,
+        &LoadOptions::default(),
+        do_timing,
+    )?;
+
+    let _timing = Timing::new(
+        do_timing,
+        &format!("Writing split symtypes to '{}'", outdir),
+    );
+
+    if let Err(err) = syms.write_split(Path::new(&outdir)) {
+        eprintln!("Failed to write split symtypes to '{}': {}", outdir, err);
+        return Err(());
+    }
+    Ok(())
+}
 
-    // Do the consolidation.
-    debug!("Consolidate '{}' to '{}'", path, output);
+/// Handles the `list` command which lists exports or types of a symtypes corpus.
+fn do_list<I>(program: &str, do_timing: bool, num_workers: i32, args: I) -> Result<(), ()>
+where
+    I: IntoIterator<Item = String>,
+{
+    // Parse specific command options.
+    let mut args = args.into_iter();
+    let mut num_workers = num_workers;
+    let mut stdin_format = InputFormat::Auto;
+    let mut list_types = false;
+    let mut maybe_path = None;
+    let mut maybe_pattern = None;
 
-    let syms = {
-        let _timing = Timing::new(do_timing, &format!("Reading symtypes from '{}'", path));
+    while let Some(arg) = args.next() {
+        if let Some(value) = handle_jobs_option(&arg, &mut args)? {
+            num_workers = value;
+            continue;
+        }
+        if let Some(value) = handle_stdin_format_option(&arg, &mut args)? {
+            stdin_format = value;
+            continue;
+        }
 
-        let mut syms = SymCorpus::new();
-        if let Err(err) = syms.load(&Path::new(&path), num_workers) {
-            eprintln!("Failed to read symtypes from '{}': {}", path, err);
+        if arg == "--exports" {
+            list_types = false;
+            continue;
+        }
+        if arg == "--types" {
+            list_types = true;
+            continue;
+        }
+        if arg == "-h" || arg == "--help" {
+            print_list_usage(program);
+            return Ok(());
+        }
+        if arg != "-" && (arg.starts_with("-") || arg.starts_with("--")) {
+            eprintln!("Unrecognized list option '{}'", arg);
             return Err(());
         }
-        syms
-    };
+        if maybe_path.is_none() {
+            maybe_path = Some(arg);
+            continue;
+        }
+        if maybe_pattern.is_none() {
+            maybe_pattern = Some(arg);
+            continue;
+        }
+        eprintln!("Excess list argument '{}' specified", arg);
+        return Err(());
+    }
 
-    {
-        let _timing = Timing::new(
-            do_timing,
-            &format!("Writing consolidated symtypes to '{}'", output),
-        );
+    let path = maybe_path.ok_or_else(|| {
+        eprintln!("The list source is missing");
+    })?;
 
-        if let Err(err) = syms.write_consolidated_file(&output) {
-            eprintln!(
-                "Failed to write consolidated symtypes to '{}': {}",
-                output, err
-            );
-            return Err(());
+    // Load the corpus and print the requested names.
+    debug!("List '{}'", path);
+
+    let syms = read_corpus(
+        &path,
+        num_workers,
+        stdin_format,
+        &LoadOptions::default(),
+        do_timing,
+    )?;
+
+    let pattern = maybe_pattern.as_deref().unwrap_or("*");
+    if list_types {
+        for name in syms.type_names_matching(pattern) {
+            println!("{}", name);
+        }
+    } else {
+        for name in syms.export_names_matching(pattern) {
+            println!("{}", name);
         }
     }
 
     Ok(())
 }
 
-/// Handles the `compare` command which shows differences between two symtypes corpuses.
-fn do_compare<I>(program: &str, do_timing: bool, args: I) -> Result<(), ()>
+/// Handles the `show` command which shows the definition of a symbol and all types it uses.
+fn do_show<I>(program: &str, do_timing: bool, num_workers: i32, args: I) -> Result<(), ()>
 where
     I: IntoIterator<Item = String>,
 {
     // Parse specific command options.
     let mut args = args.into_iter();
-    let mut num_workers = 1;
-    let mut maybe_path1 = None;
-    let mut maybe_path2 = None;
-
-    loop {
-        let arg = match args.next() {
-            Some(arg) => arg,
-            None => break,
-        };
+    let mut num_workers = num_workers;
+    let mut stdin_format = InputFormat::Auto;
+    let mut maybe_symbol = None;
+    let mut maybe_path = None;
+    let mut max_depth = None;
 
+    while let Some(arg) = args.next() {
         if let Some(value) = handle_jobs_option(&arg, &mut args)? {
             num_workers = value;
             continue;
         }
+        if let Some(value) = handle_stdin_format_option(&arg, &mut args)? {
+            stdin_format = value;
+            continue;
+        }
+        if let Some(value) = handle_value_option(&arg, &mut args, None, "--depth")? {
+            match value.parse::<usize>() {
+                Ok(value) if value > 0 => max_depth = Some(value),
+                Ok(_) => {
+                    eprintln!("Invalid value for '{}': must be positive", arg);
+                    return Err(());
+                }
+                Err(err) => {
+                    eprintln!("Invalid value for '{}': {}", arg, err);
+                    return Err(());
+                }
+            }
+            continue;
+        }
 
         if arg == "-h" || arg == "--help" {
-            print_compare_usage(&program);
+            print_show_usage(program);
             return Ok(());
         }
-        if arg.starts_with("-") || arg.starts_with("--") {
-            eprintln!("Unrecognized compare option '{}'", arg);
+        if arg != "-" && (arg.starts_with("-") || arg.starts_with("--")) {
+            eprintln!("Unrecognized show option '{}'", arg);
             return Err(());
         }
-        if maybe_path1.is_none() {
-            maybe_path1 = Some(arg);
+        if maybe_symbol.is_none() {
+            maybe_symbol = Some(arg);
             continue;
         }
-        if maybe_path2.is_none() {
-            maybe_path2 = Some(arg);
+        if maybe_path.is_none() {
+            maybe_path = Some(arg);
             continue;
         }
-        eprintln!("Excess compare argument '{}' specified", arg);
+        eprintln!("Excess show argument '{}' specified", arg);
         return Err(());
     }
 
-    let path1 = maybe_path1.ok_or_else(|| {
-        eprintln!("The first compare source is missing");
+    let symbol = maybe_symbol.ok_or_else(|| {
+        eprintln!("The show symbol is missing");
     })?;
-    let path2 = maybe_path2.ok_or_else(|| {
-        eprintln!("The second compare source is missing");
+    let path = maybe_path.ok_or_else(|| {
+        eprintln!("The show source is missing");
     })?;
 
-    // Do the comparison.
-    debug!("Compare '{}' and '{}'", path1, path2);
+    // Load the corpus and print the definition.
+    debug!("Show '{}' in '{}'", symbol, path);
 
-    let syms1 = {
-        let _timing = Timing::new(do_timing, &format!("Reading symtypes from '{}'", path1));
+    let syms = read_corpus(
+        &path,
+        num_workers,
+        stdin_format,
+        &LoadOptions::default(),
+        do_timing,
+    )?;
 
-        let mut syms1 = SymCorpus::new();
-        if let Err(err) = syms1.load(&Path::new(&path1), num_workers) {
-            eprintln!("Failed to read symtypes from '{}': {}", path1, err);
+    // Show all matching symbols if a pattern is specified, separated by blank lines.
+    let names = if glob::has_wildcards(&symbol) {
+        let names = syms.type_names_matching(&symbol).collect::<Vec<_>>();
+        if names.is_empty() {
+            eprintln!("No symbol in '{}' matches '{}'", path, symbol);
             return Err(());
         }
-        syms1
+        names
+    } else {
+        vec![symbol.as_str()]
     };
 
-    let syms2 = {
-        let _timing = Timing::new(do_timing, &format!("Reading symtypes from '{}'", path1));
+    for (i, name) in names.into_iter().enumerate() {
+        match syms.format_type_with(name, max_depth) {
+            Ok(lines) => {
+                if i > 0 {
+                    println!();
+                }
+                for line in lines {
+                    println!("{}", line);
+                }
+            }
+            Err(err) => {
+                eprintln!("Failed to show '{}' from '{}': {}", name, path, err);
+                return Err(());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Handles the `duplicates` command which lists differently named types with the same
+/// declaration.
+fn do_duplicates<I>(program: &str, do_timing: bool, num_workers: i32, args: I) -> Result<(), ()>
+where
+    I: IntoIterator<Item = String>,
+{
+    // Parse specific command options.
+    let mut args = args.into_iter();
+    let mut num_workers = num_workers;
+    let mut stdin_format = InputFormat::Auto;
+    let mut maybe_path = None;
+
+    while let Some(arg) = args.next() {
+        if let Some(value) = handle_jobs_option(&arg, &mut args)? {
+            num_workers = value;
+            continue;
+        }
+        if let Some(value) = handle_stdin_format_option(&arg, &mut args)? {
+            stdin_format = value;
+            continue;
+        }
 
-        let mut syms2 = SymCorpus::new();
-        if let Err(err) = syms2.load(&Path::new(&path2), num_workers) {
-            eprintln!("Failed to read symtypes from '{}': {}", path2, err);
+        if arg == "-h" || arg == "--help" {
+            print_duplicates_usage(program);
+            return Ok(());
+        }
+        if arg != "-" && (arg.starts_with("-") || arg.starts_with("--")) {
+            eprintln!("Unrecognized duplicates option '{}'", arg);
             return Err(());
         }
-        syms2
-    };
+        if maybe_path.is_none() {
+            maybe_path = Some(arg);
+            continue;
+        }
+        eprintln!("Excess duplicates argument '{}' specified", arg);
+        return Err(());
+    }
 
-    {
-        let _timing = Timing::new(do_timing, "Comparison");
+    let path = maybe_path.ok_or_else(|| {
+        eprintln!("The duplicates source is missing");
+    })?;
+
+    // Load the corpus and print the groups of duplicate types, one per line.
+    debug!("Duplicates '{}'", path);
+
+    let syms = read_corpus(
+        &path,
+        num_workers,
+        stdin_format,
+        &LoadOptions::default(),
+        do_timing,
+    )?;
 
-        syms1.compare_with(&syms2, num_workers);
+    for names in syms.find_duplicate_types() {
+        println!("{}", names.join(" "));
     }
 
     Ok(())
 }
 
 fn main() {
-    env_logger::init();
+    // Show warnings reported when loading symtypes, such as records with unbalanced brackets, but
+    // otherwise keep the default logging configuration.
+    env_logger::Builder::from_env(
+        env_logger::Env::default().default_filter_or("ksymtypes::sym=warn"),
+    )
+    .init();
 
     let mut args = env::args();
 
@@ -330,6 +2152,9 @@ fn main() {
     // Handle global options and stop at the command.
     let mut maybe_command = None;
     let mut do_timing = false;
+    let mut num_workers = thread::available_parallelism()
+        .map(|num| num.get() as i32)
+        .unwrap_or(1);
     loop {
         let arg = match args.next() {
             Some(arg) => arg,
@@ -340,6 +2165,14 @@ fn main() {
             do_timing = true;
             continue;
         }
+        match handle_jobs_option(&arg, &mut args) {
+            Ok(Some(value)) => {
+                num_workers = value;
+                continue;
+            }
+            Ok(None) => {}
+            Err(()) => process::exit(1),
+        }
 
         if arg == "-h" || arg == "--help" {
             print_usage(&program);
@@ -364,12 +2197,59 @@ fn main() {
     // Process the specified command.
     match command.as_str() {
         "consolidate" => {
-            if let Err(_) = do_consolidate(&program, do_timing, args) {
+            if do_consolidate(&program, do_timing, num_workers, args).is_err() {
                 process::exit(1);
             }
         }
         "compare" => {
-            if let Err(_) = do_compare(&program, do_timing, args) {
+            // Differences are reported with the same exit status as errors.
+            match do_compare(&program, do_timing, num_workers, args) {
+                Ok(false) => {}
+                Ok(true) | Err(()) => process::exit(1),
+            }
+        }
+        "stats" => {
+            if do_stats(&program, do_timing, num_workers, args).is_err() {
+                process::exit(1);
+            }
+        }
+        "verify" => {
+            if do_verify(&program, do_timing, args).is_err() {
+                process::exit(1);
+            }
+        }
+        "check" => {
+            if do_check(&program, do_timing, num_workers, args).is_err() {
+                process::exit(1);
+            }
+        }
+        "split" => {
+            if do_split(&program, do_timing, num_workers, args).is_err() {
+                process::exit(1);
+            }
+        }
+        "list" => {
+            if do_list(&program, do_timing, num_workers, args).is_err() {
+                process::exit(1);
+            }
+        }
+        "show" => {
+            if do_show(&program, do_timing, num_workers, args).is_err() {
+                process::exit(1);
+            }
+        }
+        "duplicates" => {
+            if do_duplicates(&program, do_timing, num_workers, args).is_err() {
+                process::exit(1);
+            }
+        }
+        "text-diff" => {
+            if do_text_diff(&program, do_timing, num_workers, args).is_err() {
+                process::exit(1);
+            }
+        }
+        "export-diff" => {
+            if do_export_diff(&program, do_timing, num_workers, args).is_err() {
                 process::exit(1);
             }
         }
@@ -1,49 +1,132 @@
 // Copyright (C) 2024 SUSE LLC <petr.pavlu@suse.com>
 // SPDX-License-Identifier: GPL-2.0-or-later
 
-use log::debug;
+use flate2::read::GzDecoder;
+use log::{debug, warn};
+use serde_json::json;
+use std::borrow::Cow;
 use std::cmp::min;
 use std::collections::hash_map::Entry::{Occupied, Vacant};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::fs::File;
 use std::io::{prelude::*, BufReader, BufWriter};
 use std::path::{Path, PathBuf};
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Mutex;
-use std::{fs, io, thread};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use std::{fs, io, mem, thread};
 
 #[cfg(test)]
 mod tests;
 
-#[derive(Eq, PartialEq)]
-enum Token {
-    TypeRef(String),
-    Atom(String),
+/// A single token of a type declaration.
+///
+/// The strings are reference-counted so that tokens of types stored in a corpus can share storage
+/// for repeated words.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Token {
+    TypeRef(Arc<str>),
+    Atom(Arc<str>),
 }
 
 impl Token {
-    fn new_typeref<S: Into<String>>(name: S) -> Self {
+    fn new_typeref<S: Into<Arc<str>>>(name: S) -> Self {
         Token::TypeRef(name.into())
     }
 
-    fn new_atom<S: Into<String>>(name: S) -> Self {
+    fn new_atom<S: Into<Arc<str>>>(name: S) -> Self {
         Token::Atom(name.into())
     }
 
-    fn as_str(&self) -> &str {
+    /// Returns the token as a string slice.
+    pub fn as_str(&self) -> &str {
         match self {
-            Self::TypeRef(ref_name) => ref_name.as_str(),
-            Self::Atom(word) => word.as_str(),
+            Self::TypeRef(ref_name) => ref_name,
+            Self::Atom(word) => word,
         }
     }
 }
 
-type Tokens = Vec<Token>;
+/// A sequence of tokens which forms a type declaration.
+pub type Tokens = Vec<Token>;
+
+/// Parses a single type or export record, as found on one line of a symtypes file, into its name
+/// and tokens.
+///
+/// The name can include a variant suffix, as in `s#foo@1`, which is returned unchanged. File
+/// records are not accepted.
+pub fn parse_record(line: &str) -> Result<(String, Tokens), crate::Error> {
+    let mut words = line.split_ascii_whitespace();
+    let name = words
+        .next()
+        .ok_or_else(|| crate::Error::new_parse("Expected a record name"))?;
+    if name.starts_with("F#") {
+        return Err(crate::Error::new_parse(&format!(
+            "Expected a type or export record, found file record '{}'",
+            name
+        )));
+    }
+    Ok((name.to_string(), SymCorpus::words_into_tokens(&mut words)))
+}
+
+/// Formats a type or export record in the form in which it is written to a symtypes file, which
+/// is the name followed by the tokens, separated by single spaces.
+pub fn format_record(name: &str, tokens: &Tokens) -> String {
+    let mut record = name.to_string();
+    for token in tokens {
+        record.push(' ');
+        record.push_str(token.as_str());
+    }
+    record
+}
+
+/// A table of unique strings used by tokens of a corpus.
+///
+/// Atoms such as `int`, `{` or `;` and names of commonly referenced types repeat many times across
+/// a corpus. Interning them makes all equal tokens share a single allocation.
+#[derive(Default)]
+struct Interner {
+    strings: HashSet<Arc<str>>,
+}
+
+impl Interner {
+    /// Returns the shared copy of a given string, adding it to the table if it isn't yet present.
+    fn intern(&mut self, string: &Arc<str>) -> Arc<str> {
+        match self.strings.get(string) {
+            Some(interned) => interned.clone(),
+            None => {
+                self.strings.insert(string.clone());
+                string.clone()
+            }
+        }
+    }
+
+    /// Replaces the strings of all tokens with their shared copies.
+    fn intern_tokens(&mut self, tokens: &mut Tokens) {
+        for token in tokens {
+            match token {
+                Token::TypeRef(name) | Token::Atom(name) => *name = self.intern(name),
+            }
+        }
+    }
+}
+
+/// Records of a single symtypes input, as collected for the cache of parsed files, see
+/// [`SymCorpus::read_cache`].
+///
+/// The first member holds the type/export records, the second one the raw file records, both with
+/// their zero-based line indices.
+type ParsedRecords = (Vec<(usize, String, Tokens)>, Vec<(usize, String)>);
+
 type TypeVariants = Vec<Tokens>;
 type Types = HashMap<String, TypeVariants>;
 type Exports = HashMap<String, usize>;
 type FileRecords = HashMap<String, usize>;
 
+/// Original text of type variants, following their names in the input records, keyed by the type
+/// name and the variant tokens.
+type RawBodies = HashMap<String, HashMap<Tokens, String>>;
+
 struct SymFile {
     path: PathBuf,
     records: FileRecords,
@@ -51,939 +134,4992 @@ struct SymFile {
 
 type SymFiles = Vec<SymFile>;
 
+/// First line of each cache entry, identifying its format.
+const CACHE_HEADER: &str = "ksymtypes-cache 2";
+
+/// Last token of an `F#` record line which is continued on the next line.
+const FILE_RECORD_CONTINUATION: &str = "\\";
+
+/// Default maximum length of a chain of type references which is followed when walking types
+/// recursively.
+///
+/// Real types are nested much less deeply. The limit protects against overflowing the stack on a
+/// corrupted corpus.
+pub const DEFAULT_MAX_TYPE_DEPTH: usize = 256;
+
 pub struct SymCorpus {
     types: Types,
     exports: Exports,
     files: SymFiles,
+    strings: Interner,
+    raw_bodies: RawBodies,
 }
 
-type TypeChanges<'a> = HashMap<&'a str, Vec<(&'a Tokens, &'a Tokens)>>;
+/// A change of a type between two corpuses, together with the files of the first corpus in which
+/// it was found.
+struct TypeChange<'a> {
+    tokens: Cow<'a, Tokens>,
+    other_tokens: Cow<'a, Tokens>,
+    files: BTreeSet<&'a Path>,
+}
 
-struct ParallelLoadContext<'a> {
-    types: Mutex<&'a mut Types>,
-    exports: Mutex<&'a mut Exports>,
-    files: Mutex<&'a mut SymFiles>,
+type TypeChanges<'a> = HashMap<&'a str, Vec<TypeChange<'a>>>;
+
+/// State of a walk over the types reachable from the exports of one file, collecting the types
+/// which should be written to the consolidated output.
+struct ConsolidateWalk<'a, 'w> {
+    symfile: &'a SymFile,
+    options: &'w ConsolidateOptions,
+    /// Chain of type references from the export to the currently processed type.
+    chain: Vec<&'a str>,
+    /// All output symbols with a mapping from their internal variant index to the output index.
+    output_types: &'w mut HashMap<&'a str, HashMap<usize, usize>>,
+    /// Output symbols in the order in which they were first reached.
+    output_order: &'w mut Vec<&'a str>,
+    /// Symbols processed in the current file with their output variant index.
+    processed_types: HashMap<&'a str, usize>,
 }
 
-impl SymCorpus {
-    pub fn new() -> Self {
-        Self {
-            types: Types::new(),
-            exports: Exports::new(),
-            files: SymFiles::new(),
-        }
-    }
+/// State of a walk comparing the types reachable from an export with another corpus.
+struct CompareWalk<'a, 'w> {
+    other: &'a SymCorpus,
+    file: &'a SymFile,
+    other_file: &'a SymFile,
+    options: &'w CompareOptions,
+    /// Chain of type references from the export to the currently compared type.
+    chain: Vec<&'a str>,
+    /// Names of the already compared types.
+    processed: HashSet<String>,
+    changes: &'w Mutex<TypeChanges<'a>>,
+}
 
-    // TODO Describe.
-    pub fn load(&mut self, path: &Path, num_workers: i32) -> Result<(), crate::Error> {
-        // Determine if the input is a directory tree or a single symtypes file.
-        let md = fs::metadata(path).map_err(|err| {
-            crate::Error::new_io(&format!("Failed to query path '{}'", path.display()), err)
-        })?;
+/// Format of symtypes input data.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum InputFormat {
+    /// Detect the format automatically, the data is consolidated if it contains any `F#` record.
+    #[default]
+    Auto,
+    /// A single symtypes file, as produced by genksyms.
+    Single,
+    /// A consolidated symtypes file.
+    Consolidated,
+}
 
-        // Collect recursively all symtypes if it is a directory, or push the single file.
-        let mut symfiles = Vec::new();
-        if md.is_dir() {
-            Self::collect_symfiles(path, &mut symfiles)?;
-        } else {
-            symfiles.push(path.to_path_buf());
-        }
+/// Options controlling how two corpuses are compared.
+#[derive(Default)]
+pub struct CompareOptions {
+    /// Resolve references to typedefs to their target types before comparing.
+    ///
+    /// A change of a typedef is then reported in the body of each type that uses it, instead of
+    /// only once for the typedef itself. This can make the output larger but attributes the change
+    /// directly to the affected types.
+    pub expand_typedefs: bool,
 
-        // Load all files.
-        self.load_multiple(&symfiles, num_workers)
-    }
+    /// Classify struct changes which only append new members at the end as additive.
+    ///
+    /// Appending a member to a struct is ABI-compatible only if the struct is always allocated
+    /// by the kernel and modules never embed it, copy it or depend on its size. The tool cannot
+    /// verify this, the classification is only a heuristic.
+    pub allow_tail_append: bool,
 
-    /// Collects recursively all symtypes under a given path.
-    fn collect_symfiles(path: &Path, symfiles: &mut Vec<PathBuf>) -> Result<(), crate::Error> {
-        // TODO Report errors and skip directories?
-        let dir_iter = fs::read_dir(path).map_err(|err| {
-            crate::Error::new_io(
-                &format!("Failed to read directory '{}'", path.display()),
-                err,
-            )
-        })?;
+    /// Treat a flexible array member at the end of a struct as equal to a pointer member with the
+    /// same element type and name.
+    ///
+    /// A trailing member `T p[]` is normalized to `T *p` before the comparison. The two forms are
+    /// not interchangeable in general, the equivalence is meaningful only in specific ABI contexts
+    /// where the member is always accessed through the enclosing object.
+    pub ptr_array_equiv: bool,
 
-        for maybe_entry in dir_iter {
-            let entry = maybe_entry.map_err(|err| {
-                crate::Error::new_io(
-                    &format!("Failed to read directory '{}'", path.display()),
-                    err,
-                )
-            })?;
+    /// Consider two enums equal if they have the same enumerators, regardless of their order.
+    ///
+    /// The order is ignored only if every enumerator has an explicit value, because reordering
+    /// enumerators with implicit values changes the values. Struct and union members are always
+    /// compared in order as their order determines the layout.
+    pub ignore_reordering: bool,
 
-            let entry_path = entry.path();
+    /// Consider two types equal if they have the same name, which includes their kind, and ignore
+    /// their bodies.
+    ///
+    /// This is the coarsest comparison. Exports are still compared by their declarations, which
+    /// detects changes of the types that they reference by name, but the referenced types are not
+    /// compared recursively.
+    pub decls_only: bool,
 
-            let md = fs::symlink_metadata(&entry_path).map_err(|err| {
-                crate::Error::new_io(
-                    &format!("Failed to query path '{}'", entry_path.display()),
-                    err,
-                )
-            })?;
+    /// Compare only the signatures of exports, which are their declarations and the bodies of the
+    /// types that they directly reference.
+    ///
+    /// Types referenced from the bodies of these types are considered equal if they have the same
+    /// name. This sits between the full recursive comparison and [`CompareOptions::decls_only`].
+    pub shallow: bool,
 
-            if md.is_symlink() {
-                continue;
-            }
+    /// Prefixes of generated names of anonymous types.
+    ///
+    /// A type reference whose name consists of one of these prefixes followed by a number, for
+    /// instance `s#__anon_12`, is considered equal to a reference of the same kind with the same
+    /// prefix and a different number. Their bodies are then compared instead. An empty list
+    /// disables the matching.
+    pub anon_name_prefixes: Vec<String>,
 
-            if md.is_dir() {
-                Self::collect_symfiles(&entry_path, symfiles)?;
-                continue;
-            }
+    /// Report references to unknown types as warnings and skip them, instead of aborting.
+    pub lenient: bool,
 
-            let file_name = entry.file_name();
-            let ext = match Path::new(&file_name).extension() {
-                Some(ext) => ext,
-                None => continue,
-            };
-            if ext == "symtypes" {
-                symfiles.push(entry_path.to_path_buf());
-            }
-        }
-        Ok(())
-    }
+    /// A function called with each diagnostic reported in the lenient mode.
+    ///
+    /// The diagnostics are logged as warnings if no function is set.
+    pub diagnostic_callback: Option<DiagnosticCallback>,
 
-    /// Loads all specified symtypes.
-    pub fn load_multiple(
-        &mut self,
-        symfiles: &Vec<PathBuf>,
-        num_workers: i32,
-    ) -> Result<(), crate::Error> {
-        // Load data from the files.
-        let next_work_idx = AtomicUsize::new(0);
+    /// Maximum length of a chain of type references which is followed when comparing types.
+    ///
+    /// A deeper chain is reported as an error. The types are walked recursively, so a high limit
+    /// requires a correspondingly large stack. The default is [`DEFAULT_MAX_TYPE_DEPTH`].
+    pub max_type_depth: Option<usize>,
 
-        let load_context = ParallelLoadContext {
-            types: Mutex::new(&mut self.types),
-            exports: Mutex::new(&mut self.exports),
-            files: Mutex::new(&mut self.files),
-        };
+    /// Group the reported differences into sections by the files that they affect.
+    ///
+    /// Changed types are listed under every file of the first corpus in which they were found,
+    /// removed exports under their file in the first corpus and added exports under their file in
+    /// the second corpus.
+    pub group_by_file: bool,
 
-        thread::scope(|s| {
-            for _ in 0..num_workers {
-                // TODO Result/Error handling.
-                s.spawn(|| -> Result<(), crate::Error> {
-                    loop {
-                        let work_idx = next_work_idx.fetch_add(1, Ordering::Relaxed);
-                        if work_idx >= symfiles.len() {
-                            return Ok(());
-                        }
-                        let path = symfiles[work_idx].as_path();
+    /// Report only the sorted names of removed and added exports and changed types, instead of
+    /// the full descriptions of the changes.
+    pub name_only: bool,
 
-                        let file = File::open(path).map_err(|err| {
-                            crate::Error::new_io(
-                                &format!("Failed to open file '{}'", path.display()),
-                                err,
-                            )
-                        })?;
+    /// Periodically report the number of compared exports and the estimated remaining time on
+    /// `stderr`.
+    pub progress: bool,
 
-                        Self::load_single(path, file, &load_context)?;
-                    }
-                });
-            }
-        });
+    /// A function called after each export is compared.
+    pub progress_callback: Option<ProgressCallback>,
 
-        Ok(())
+    /// Names of symbols whose removal or type change is deliberately tolerated.
+    ///
+    /// A removed export or a changed type with one of these names is not reported. Added exports
+    /// are always reported.
+    pub ignored_symbols: HashSet<String>,
+
+    /// Minimum severity of the reported changes, less severe changes are omitted.
+    pub min_severity: ChangeSeverity,
+
+    /// Names of exports to which the comparison is restricted.
+    ///
+    /// Only these exports and the types reachable from them are compared. Each of them must be
+    /// present in at least one of the corpuses. An empty set compares all exports.
+    pub only_symbols: HashSet<String>,
+}
+
+impl CompareOptions {
+    /// Returns the default prefixes of generated names of anonymous types.
+    pub fn default_anon_name_prefixes() -> Vec<String> {
+        vec!["__anon_".to_string(), "$".to_string()]
     }
 
-    pub fn load_buffer<R>(&mut self, path: &Path, reader: R) -> Result<(), crate::Error>
+    /// Reads a list of ignored symbol names from a specified reader.
+    ///
+    /// The list contains one symbol name per line. Empty lines and lines starting with `#` are
+    /// skipped.
+    pub fn read_ignore_list<R>(path: &Path, reader: R) -> Result<HashSet<String>, crate::Error>
     where
         R: io::Read,
     {
-        let load_context = ParallelLoadContext {
-            types: Mutex::new(&mut self.types),
-            exports: Mutex::new(&mut self.exports),
-            files: Mutex::new(&mut self.files),
-        };
+        let mut names = HashSet::new();
+        for (i, line) in SymCorpus::read_lines(path, reader)?.iter().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line.contains(char::is_whitespace) {
+                return Err(crate::Error::new_parse(&format!(
+                    "{}:{}: Expected a single symbol name",
+                    path.display(),
+                    i + 1
+                )));
+            }
+            names.insert(line.to_string());
+        }
+        Ok(names)
+    }
 
-        Self::load_single(path, reader, &load_context)?;
+    /// Checks whether two type references are generated names of anonymous types which differ
+    /// only in their numeric suffix.
+    fn are_anon_names_eq(&self, ref_name: &str, other_ref_name: &str) -> bool {
+        // Split the references in the kind and the base name, as in `s#` and `__anon_12`.
+        if ref_name.len() < 2 || other_ref_name.len() < 2 {
+            return false;
+        }
+        let (kind, base_name) = ref_name.split_at(2);
+        let (other_kind, other_base_name) = other_ref_name.split_at(2);
+        if kind != other_kind {
+            return false;
+        }
 
-        Ok(())
+        let is_anon_suffix =
+            |suffix: &str| !suffix.is_empty() && suffix.chars().all(|ch| ch.is_ascii_digit());
+        self.anon_name_prefixes.iter().any(|prefix| {
+            match (
+                base_name.strip_prefix(prefix.as_str()),
+                other_base_name.strip_prefix(prefix.as_str()),
+            ) {
+                (Some(suffix), Some(other_suffix)) => {
+                    is_anon_suffix(suffix) && is_anon_suffix(other_suffix)
+                }
+                _ => false,
+            }
+        })
     }
+}
 
-    /// Loads symtypes data from a specified reader.
-    fn load_single<R>(
-        path: &Path,
-        reader: R,
-        load_context: &ParallelLoadContext,
-    ) -> Result<(), crate::Error>
-    where
-        R: io::Read,
-    {
-        debug!("Loading {}", path.display());
+/// Kind of a type, determined by the prefix of its name.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TypeKind {
+    Enum,
+    Struct,
+    Typedef,
+    Union,
+}
 
-        // Read all declarations.
-        // TODO Describe the types.
-        let mut records = FileRecords::new();
-        let mut remap = HashMap::new();
+impl TypeKind {
+    /// Returns the kind of a type with a specified name, or [`None`] if the name is not a type name
+    /// of a known kind.
+    pub fn of(name: &str) -> Option<Self> {
+        if !SymCorpus::is_type_name(name) {
+            return None;
+        }
+        match name.as_bytes()[0] {
+            b'e' => Some(Self::Enum),
+            b's' => Some(Self::Struct),
+            b't' => Some(Self::Typedef),
+            b'u' => Some(Self::Union),
+            _ => None,
+        }
+    }
+}
 
-        let lines = Self::read_lines(path, reader)?;
+impl std::str::FromStr for TypeKind {
+    type Err = crate::Error;
 
-        // Detect whether the input is a single or consolidated symtypes file.
-        let mut is_consolidated = false;
-        for line in &lines {
-            if line.starts_with("F#") {
-                is_consolidated = true;
-                break;
-            }
+    /// Parses a kind from its C keyword, such as `struct`.
+    fn from_str(keyword: &str) -> Result<Self, Self::Err> {
+        match keyword {
+            "enum" => Ok(Self::Enum),
+            "struct" => Ok(Self::Struct),
+            "typedef" => Ok(Self::Typedef),
+            "union" => Ok(Self::Union),
+            _ => Err(crate::Error::new_parse(&format!(
+                "Unknown type kind '{}'",
+                keyword
+            ))),
         }
+    }
+}
 
-        let file_idx = if !is_consolidated {
-            // Record the file early to determine its file_idx.
-            let symfile = SymFile {
-                // TODO Drop the root prefix.
-                path: path.to_path_buf(),
-                records: FileRecords::new(),
-            };
+/// Order of the type and export records in the consolidated output.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum SortOrder {
+    /// Sort types by their name, followed by exports sorted by their name.
+    #[default]
+    Name,
+    /// Group types and exports under the first file which references them, in the order of the
+    /// `F#` records, and sort them by name within each group.
+    File,
+    /// Keep the order in which the types were reached when walking the files and their exports.
+    /// Files are kept in the order in which they were loaded.
+    None,
+}
 
-            let mut files = load_context.files.lock().unwrap();
-            files.push(symfile);
-            files.len() - 1
-        } else {
-            usize::MAX
-        };
+impl std::str::FromStr for SortOrder {
+    type Err = crate::Error;
 
-        // Track names of all entries to detect duplicates.
-        let mut all_names = HashSet::new();
+    /// Parses a sort order from its name, such as `file`.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "name" => Ok(Self::Name),
+            "file" => Ok(Self::File),
+            "none" => Ok(Self::None),
+            _ => Err(crate::Error::new_parse(&format!(
+                "Unknown sort order '{}'",
+                name
+            ))),
+        }
+    }
+}
 
-        // Parse all declarations.
-        let mut file_indices = Vec::new();
-        for (i, line) in lines.iter().enumerate() {
-            // Obtain a name of the record.
-            let mut words = line.split_ascii_whitespace();
-            let name = words.next().ok_or_else(|| {
-                crate::Error::new_parse(&format!(
-                    "{}:{}: Expected a record name",
-                    path.display(),
-                    i + 1
-                ))
-            })?;
+/// Severity of a diagnostic.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Severity {
+    Warning,
+    Error,
+}
 
-            // Check if the record is a duplicate of another one.
-            match all_names.get(name) {
-                Some(_) => {
-                    return Err(crate::Error::new_parse(&format!(
-                        "{}:{}: Duplicate record '{}'",
-                        path.display(),
-                        i + 1,
-                        name,
-                    )))
-                }
-                None => all_names.insert(name.to_string()),
-            };
+impl Severity {
+    /// Returns the name of the severity as used in the rendered diagnostics.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Warning => "warning",
+            Self::Error => "error",
+        }
+    }
+}
 
-            // Check for a file declaration and remember its index. File declarations are processed
-            // later after remapping of all symbol variants is known.
-            if name.starts_with("F#") {
-                file_indices.push(i);
-                continue;
-            }
+/// A problem found by an integrity check of a corpus.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
 
-            // Handle a type/export record.
+    /// Stable identifier of the check which found the problem, for instance `ORPHAN_TYPE`.
+    pub code: &'static str,
 
-            // Turn the remaining words into tokens.
-            let tokens = Self::words_into_tokens(&mut words);
+    pub message: String,
 
-            // Parse the base name and any variant name/index, which is appended as a suffix after
-            // the `@` character.
-            let (base_name, orig_variant_name) = if is_consolidated {
-                Self::split_type_name(name)
-            } else {
-                (name, &name[name.len()..])
-            };
+    /// File in which the problem was found, if it is specific to a file.
+    pub file: Option<PathBuf>,
+}
 
-            // Insert the type into the corpus.
-            let variant_idx = Self::merge_type(base_name, tokens, load_context);
+impl Diagnostic {
+    fn new(severity: Severity, code: &'static str, message: String, file: Option<&Path>) -> Self {
+        Self {
+            severity,
+            code,
+            message,
+            file: file.map(Path::to_path_buf),
+        }
+    }
 
-            if is_consolidated {
-                // Record a mapping from the original variant name/index to the new one.
-                remap
-                    .entry(base_name.to_string())
-                    .or_insert_with(|| HashMap::new())
-                    .insert(orig_variant_name.to_string(), variant_idx);
-            } else {
-                // Insert the record.
-                Self::insert_record(base_name, variant_idx, file_idx, &mut records, load_context)?;
-            }
+    /// Returns the diagnostic as a JSON object.
+    pub fn to_json(&self) -> serde_json::Value {
+        json!({
+            "severity": self.severity.as_str(),
+            "code": self.code,
+            "message": self.message,
+            "file": self.file.as_ref().map(|file| file.to_string_lossy()),
+        })
+    }
+}
+
+impl std::fmt::Display for Diagnostic {
+    /// Formats the diagnostic as `<file>: <severity>: <message> [<code>]`, the file is omitted if
+    /// the diagnostic is not specific to a file.
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}: ", file.display())?;
         }
+        write!(
+            f,
+            "{}: {} [{}]",
+            self.severity.as_str(),
+            self.message,
+            self.code
+        )
+    }
+}
 
-        // TODO Validate all references?
+/// A function which is called each time an item of a long operation is processed.
+///
+/// The arguments are the number of items processed so far and the total number of items. The
+/// function can be called concurrently from multiple worker threads.
+pub type ProgressCallback = Arc<dyn Fn(usize, usize) + Send + Sync>;
 
-        if is_consolidated {
-            // Handle file declarations.
-            for i in file_indices {
-                let mut words = lines[i].split_ascii_whitespace();
+/// A function which is called with the path of each file parsed while loading.
+pub type ParseCallback = Arc<dyn Fn(&Path) + Send + Sync>;
 
-                let record_name = words.next().unwrap();
-                assert!(record_name.starts_with("F#"));
-                let file_name = &record_name[2..];
+/// A function which is called with each diagnostic reported while processing a corpus.
+///
+/// The function can be called concurrently from multiple worker threads.
+pub type DiagnosticCallback = Arc<dyn Fn(&Diagnostic) + Send + Sync>;
 
-                let file_idx = {
-                    let symfile = SymFile {
-                        path: Path::new(file_name).to_path_buf(),
-                        records: FileRecords::new(),
-                    };
-                    let mut files = load_context.files.lock().unwrap();
-                    files.push(symfile);
-                    files.len() - 1
-                };
+/// Options controlling how symtypes data is loaded.
+#[derive(Default)]
+pub struct LoadOptions {
+    /// Maximum resident memory of the process in bytes.
+    ///
+    /// Loading fails with an error when the limit is exceeded. This is a soft guard, the memory is
+    /// checked only before each file is loaded, so the limit can be exceeded by the data of the
+    /// files which are being loaded at that time. It is not a limit on allocations.
+    pub max_memory: Option<u64>,
 
-                let mut records = FileRecords::new();
-                for mut type_name in words {
-                    // Parse the base name and variant name/index.
-                    let (base_name, orig_variant_name) = Self::split_type_name(type_name);
-
-                    // Look up how the variant got remapped.
-                    let variant_idx = *remap
-                        .get(base_name)
-                        .and_then(|hash| hash.get(orig_variant_name))
-                        .ok_or_else(|| {
-                            crate::Error::new_parse(&format!(
-                                "{}:{}: Type {} is not known",
-                                path.display(),
-                                i + 1,
-                                type_name
-                            ))
-                        })?;
+    /// Check that all type references resolve after loading, see [`SymCorpus::validate`].
+    pub check_refs: bool,
 
-                    // Insert the record.
-                    Self::insert_record(
-                        base_name,
-                        variant_idx,
-                        file_idx,
-                        &mut records,
-                        load_context,
-                    )?;
-                }
+    /// Allow the same export to be declared in multiple files.
+    ///
+    /// A duplicate export is an error by default because each symbol should be exported exactly
+    /// once, a duplicate usually indicates a problem with the build. If allowed, the duplicate is
+    /// reported as a warning and the export is attributed to the last loaded file which declares
+    /// it.
+    pub allow_duplicate_exports: bool,
 
-                // Add implicit references, ones that were omitted by the F# declaration because
-                // only one variant exists in the entire consolidated file.
-                let walk_records: Vec<_> = records
-                    .iter()
-                    .map(|(k, v)| (k.clone(), v.clone()))
-                    .collect();
-                for (name, variant_idx) in walk_records {
-                    // TODO Simplify.
-                    let types = load_context.types.lock().unwrap();
-                    Self::extrapolate_file_record(
-                        path,
-                        file_name,
-                        &name,
-                        variant_idx,
-                        true,
-                        &*types,
-                        &mut records,
-                    )?;
-                }
+    /// Merge `F#` records with the same path in a consolidated file, instead of rejecting them as
+    /// duplicates.
+    ///
+    /// The file then contains the union of the types and exports listed by all its records. The
+    /// records must not reference different variants of the same type.
+    pub merge_file_records: bool,
 
-                let mut files = load_context.files.lock().unwrap();
-                files[file_idx].records = records;
-            }
-        } else {
-            // Update the file records.
-            let mut files = load_context.files.lock().unwrap();
-            files[file_idx].records = records;
-        }
+    /// Retain the original text of the type and export records, so that it can be written back
+    /// verbatim with [`ConsolidateOptions::preserve_formatting`].
+    ///
+    /// The text is kept in addition to the parsed tokens, which increases the memory usage. The
+    /// cache is not used when this option is set because it doesn't retain the text.
+    pub preserve_formatting: bool,
 
-        Ok(())
-    }
+    /// Skip entries of a directory tree which cannot be read, instead of failing.
+    ///
+    /// Each skipped entry is reported as a diagnostic. This is useful when some subdirectories of
+    /// an otherwise readable tree are not accessible.
+    pub skip_errors: bool,
 
-    /// Reads data from a specified reader and splits its content into a lines vector.
-    fn read_lines<R>(path: &Path, reader: R) -> Result<Vec<String>, crate::Error>
-    where
-        R: io::Read,
-    {
-        let reader = BufReader::new(reader);
-        let mut lines = Vec::new();
-        for maybe_line in reader.lines() {
+    /// A function called with each diagnostic reported for a skipped entry.
+    ///
+    /// The diagnostics are logged as warnings if no function is set.
+    pub diagnostic_callback: Option<DiagnosticCallback>,
+
+    /// Extensions of files which are collected when loading a directory tree.
+    ///
+    /// The extensions are matched case-insensitively, a file with an additional `.gz` extension is
+    /// collected as well and decompressed. An empty extension selects files without any extension.
+    /// An empty list selects the default `symtypes` extension.
+    pub extensions: Vec<String>,
+
+    /// Glob patterns of files which are collected when loading a directory tree, see
+    /// [`crate::glob::matches`].
+    ///
+    /// The patterns are matched against the path of each file relative to the loaded directory,
+    /// with components separated by forward slashes. An empty list selects all files.
+    pub include: Vec<String>,
+
+    /// Glob patterns of files which are skipped when loading a directory tree.
+    ///
+    /// The patterns are matched in the same way as the `include` ones and take precedence over
+    /// them.
+    pub exclude: Vec<String>,
+
+    /// Periodically report the number of loaded files and the estimated remaining time on
+    /// `stderr`.
+    pub progress: bool,
+
+    /// A function called after each file is loaded.
+    pub progress_callback: Option<ProgressCallback>,
+
+    /// Directory with cached parsed records of loaded files.
+    ///
+    /// The records of a file in a directory tree or a list of files are reused if the file has the
+    /// same path, modification time and size as when it was cached, otherwise the file is parsed
+    /// and its cache entry is updated. The cache is disabled by default.
+    pub cache_dir: Option<PathBuf>,
+
+    /// A function called each time a file is parsed from its source, instead of being loaded from
+    /// the cache.
+    pub parse_callback: Option<ParseCallback>,
+
+    /// Check that the `{}` and `()` brackets in each type and export record are balanced and
+    /// report a warning for each record where they are not.
+    pub check_brackets: bool,
+
+    /// Fail on problems found by the optional checks of the records, instead of reporting them as
+    /// warnings.
+    ///
+    /// This implies `check_brackets`.
+    pub strict: bool,
+}
+
+/// Options controlling how a corpus is written in the consolidated form.
+#[derive(Default)]
+pub struct ConsolidateOptions {
+    /// Write also all types which are not reachable from any export.
+    ///
+    /// Such orphan types are normally omitted from the output. When enabled, the orphan type
+    /// variants are written together with the other types but are not referenced by any `F#`
+    /// record.
+    pub include_orphans: bool,
+
+    /// Report references to unknown types as warnings and skip them, instead of aborting.
+    ///
+    /// This allows to write whatever is resolvable from a partially corrupted corpus. The output
+    /// then contains references to types which are not declared in it.
+    pub lenient: bool,
+
+    /// A function called with each diagnostic reported in the lenient mode.
+    ///
+    /// The diagnostics are logged as warnings if no function is set.
+    pub diagnostic_callback: Option<DiagnosticCallback>,
+
+    /// Maximum length of a chain of type references which is followed when consolidating types.
+    ///
+    /// A deeper chain is reported as an error. The types are walked recursively, so a high limit
+    /// requires a correspondingly large stack. The default is [`DEFAULT_MAX_TYPE_DEPTH`].
+    pub max_type_depth: Option<usize>,
+
+    /// Number the variants of each type in the order in which they are first referenced by the
+    /// sorted files.
+    ///
+    /// The variants are normally numbered in the order of their token content. This numbering
+    /// doesn't depend on which files reference the variants, so it stays the same when unrelated
+    /// files are renamed, added or removed.
+    pub first_use_numbering: bool,
+
+    /// Kinds of types whose records are omitted from the output.
+    ///
+    /// Types of the excluded kinds are still followed when looking for the types reachable from
+    /// the exports, so the types that they reference are written. References to the excluded types
+    /// remain in the output but their declarations are missing. Such output is suitable for
+    /// inspection but cannot be read back.
+    pub exclude_kinds: Vec<TypeKind>,
+
+    /// Suffix every type and export record with its variant index and list all referenced types
+    /// in the `F#` records.
+    ///
+    /// By default, a type with only one variant is written without a suffix and omitted from the
+    /// `F#` records, because a reader can determine it implicitly. The explicit form is larger but
+    /// simpler to process by other tools.
+    pub explicit_variants: bool,
+
+    /// Maximum number of references written on a single line of an `F#` record.
+    ///
+    /// A longer record is wrapped by ending each line except the last with a `\` token and
+    /// continuing on the next line, which is indented. [`None`] writes every record on a single
+    /// line, which is also the only form understood by older readers.
+    pub wrap_file_records: Option<usize>,
+
+    /// Order of the written type and export records.
+    ///
+    /// The records inside each `F#` record are always sorted by name.
+    pub sort: SortOrder,
+
+    /// Write the type and export records with their original text, as retained by
+    /// [`LoadOptions::preserve_formatting`], instead of separating their tokens by single spaces.
+    ///
+    /// Variants whose original text is not available are written in the normal form. Consolidating
+    /// an already consolidated file then reproduces it exactly if it is in the canonical order.
+    pub preserve_formatting: bool,
+}
+
+/// Differences found by comparing two corpuses.
+struct CompareResult<'a> {
+    removed_exports: Vec<&'a str>,
+    added_exports: Vec<&'a str>,
+    changes: TypeChanges<'a>,
+}
+
+/// Creates a corpus from a symtypes literal, recorded under the file name `<literal>`.
+///
+/// The literal is typically a single symtypes file, but consolidated data is accepted as well
+/// because the format is detected automatically by [`SymCorpus::from_reader`].
+///
+/// # Examples
+///
+/// ```
+/// use ksymtypes::sym::SymCorpus;
+///
+/// let syms = SymCorpus::try_from("s#foo struct foo { int a ; }\nbar int bar ( s#foo )\n")?;
+/// assert_eq!(syms.num_exports(), 1);
+/// assert_eq!(syms.num_types(), 2);
+/// # Ok::<(), ksymtypes::Error>(())
+/// ```
+impl TryFrom<&str> for SymCorpus {
+    type Error = crate::Error;
+
+    fn try_from(data: &str) -> Result<Self, Self::Error> {
+        Self::from_reader(Path::new("<literal>"), data.as_bytes())
+    }
+}
+
+/// Description of a file record written in the consolidated form.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ConsolidatedFile {
+    /// Path of the file, as written in its `F#` record.
+    pub path: PathBuf,
+
+    /// Number of types used by the file, including the ones omitted from the `F#` record because
+    /// they have only one variant.
+    pub num_types: usize,
+
+    /// Number of exports of the file.
+    pub num_exports: usize,
+}
+
+/// Statistics describing how much a corpus gets deduplicated by the consolidation.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ConsolidateStats {
+    /// Number of distinct type and export names.
+    pub num_types: usize,
+
+    /// Number of unique type variants, which is the number of type records in the consolidated
+    /// form.
+    pub num_variants: usize,
+
+    /// Number of types with more than one variant.
+    pub num_multi_variant_types: usize,
+
+    /// Number of type records of all files, as they would be written in separate symtypes files.
+    pub num_input_records: usize,
+}
+
+impl ConsolidateStats {
+    /// Returns the ratio of the number of input records to the number of the consolidated ones.
+    pub fn ratio(&self) -> f64 {
+        if self.num_variants == 0 {
+            return 0.0;
+        }
+        self.num_input_records as f64 / self.num_variants as f64
+    }
+}
+
+impl std::fmt::Display for ConsolidateStats {
+    /// Formats the statistics on multiple lines, each terminated by a newline.
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "Types: {}", self.num_types)?;
+        writeln!(f, "Unique type variants: {}", self.num_variants)?;
+        writeln!(
+            f,
+            "Types with multiple variants: {}",
+            self.num_multi_variant_types
+        )?;
+        writeln!(
+            f,
+            "Records: {} input, {} output, ratio {:.2}",
+            self.num_input_records,
+            self.num_variants,
+            self.ratio()
+        )
+    }
+}
+
+/// Severity of a change for compatibility of the ABI.
+///
+/// The severities are ordered from the least to the most severe one.
+#[derive(Clone, Copy, Debug, Default, Eq, Ord, PartialEq, PartialOrd)]
+pub enum ChangeSeverity {
+    /// The change is only informational. It is the lowest severity, selecting it as the minimum
+    /// includes changes of all severities.
+    #[default]
+    Info,
+    /// The change extends the ABI in a compatible way.
+    Compatible,
+    /// The change breaks users of some export.
+    Breaking,
+}
+
+impl ChangeSeverity {
+    /// Returns the name of the severity, as accepted by [`ChangeSeverity::from_str`].
+    ///
+    /// [`ChangeSeverity::from_str`]: std::str::FromStr::from_str
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Info => "info",
+            Self::Compatible => "compatible",
+            Self::Breaking => "breaking",
+        }
+    }
+}
+
+impl std::str::FromStr for ChangeSeverity {
+    type Err = crate::Error;
+
+    /// Parses a severity from its name, such as `breaking`.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "info" => Ok(Self::Info),
+            "compatible" => Ok(Self::Compatible),
+            "breaking" => Ok(Self::Breaking),
+            _ => Err(crate::Error::new_parse(&format!(
+                "Unknown severity '{}'",
+                name
+            ))),
+        }
+    }
+}
+
+/// A difference found by comparing two corpuses.
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Change {
+    /// An export is present in the first corpus but not in the second one.
+    ExportRemoved(String),
+
+    /// An export is present in the second corpus but not in the first one.
+    ExportAdded(String),
+
+    /// A type or export differs between the corpuses.
+    TypeChanged {
+        name: String,
+
+        /// Difference between the two versions of the type, as rendered by a [`DiffRenderer`].
+        diff: Vec<String>,
+
+        /// Files of the first corpus in which the type is reachable from an export, sorted by
+        /// their path.
+        files: Vec<PathBuf>,
+
+        /// Files which define the export in the first and second corpus, if the change is of an
+        /// export.
+        defined_in: Option<(PathBuf, PathBuf)>,
+
+        /// The change only appends members at the end of a struct, see
+        /// [`CompareOptions::allow_tail_append`].
+        additive: bool,
+    },
+}
+
+impl Change {
+    /// Returns the severity of the change for compatibility of the ABI.
+    ///
+    /// Removing an export or changing a type reachable from an export breaks users of the symbol.
+    /// Adding a new export and an additive change of a type are compatible.
+    pub fn severity(&self) -> ChangeSeverity {
+        match self {
+            Change::ExportRemoved(_) => ChangeSeverity::Breaking,
+            Change::ExportAdded(_) => ChangeSeverity::Compatible,
+            Change::TypeChanged { additive: true, .. } => ChangeSeverity::Compatible,
+            Change::TypeChanged { .. } => ChangeSeverity::Breaking,
+        }
+    }
+
+    /// Returns whether the change breaks compatibility of the ABI, see [`Change::severity`].
+    pub fn is_breaking(&self) -> bool {
+        self.severity() == ChangeSeverity::Breaking
+    }
+
+    /// Returns the change as a JSON object.
+    ///
+    /// The object has a `kind` field, which is `export_removed`, `export_added` or
+    /// `type_changed`, and a `name` field. A changed type has also a `diff` field with the
+    /// rendered lines of the difference, a `files` field with the affected files and an `additive`
+    /// field telling whether the change only appends members. A changed export has additionally a
+    /// `defined_in` field with the files which define it in both corpuses.
+    pub fn to_json(&self) -> serde_json::Value {
+        match self {
+            Change::ExportRemoved(name) => json!({ "kind": "export_removed", "name": name }),
+            Change::ExportAdded(name) => json!({ "kind": "export_added", "name": name }),
+            Change::TypeChanged {
+                name,
+                diff,
+                files,
+                defined_in,
+                additive,
+            } => {
+                let mut value = json!({
+                    "kind": "type_changed",
+                    "name": name,
+                    "diff": diff,
+                    "files": files.iter().map(|path| path.to_string_lossy()).collect::<Vec<_>>(),
+                    "additive": additive,
+                });
+                if let Some((path, other_path)) = defined_in {
+                    value["defined_in"] =
+                        json!([path.to_string_lossy(), other_path.to_string_lossy()]);
+                }
+                value
+            }
+        }
+    }
+}
+
+/// Summary counts of differences found by comparing two corpuses.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct CompareSummary {
+    pub removed_exports: usize,
+    pub added_exports: usize,
+    pub changed_types: usize,
+
+    /// Number of changes which break compatibility of the ABI, see [`Change::is_breaking`].
+    pub breaking_changes: usize,
+}
+
+impl CompareSummary {
+    /// Counts the changes of each kind.
+    pub fn from_changes(changes: &[Change]) -> Self {
+        let mut summary = Self::default();
+        let mut changed_types = HashSet::new();
+        for change in changes {
+            if change.is_breaking() {
+                summary.breaking_changes += 1;
+            }
+            match change {
+                Change::ExportRemoved(_) => summary.removed_exports += 1,
+                Change::ExportAdded(_) => summary.added_exports += 1,
+                Change::TypeChanged { name, .. } => {
+                    changed_types.insert(name.as_str());
+                }
+            }
+        }
+        summary.changed_types = changed_types.len();
+        summary
+    }
+
+    /// Returns whether any differences were found.
+    pub fn has_differences(&self) -> bool {
+        self.removed_exports > 0 || self.added_exports > 0 || self.changed_types > 0
+    }
+
+    /// Returns whether any differences which break compatibility of the ABI were found, see
+    /// [`Change::is_breaking`].
+    pub fn has_breaking_differences(&self) -> bool {
+        self.breaking_changes > 0
+    }
+}
+
+impl std::fmt::Display for CompareSummary {
+    /// Formats the summary as a single line, in the form
+    /// `N exports added, M exports removed, K types changed`.
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{} exports added, {} exports removed, {} types changed",
+            self.added_exports, self.removed_exports, self.changed_types
+        )
+    }
+}
+
+struct ParallelLoadContext<'a> {
+    types: Mutex<&'a mut Types>,
+    exports: Mutex<&'a mut Exports>,
+    files: Mutex<&'a mut SymFiles>,
+    strings: Mutex<&'a mut Interner>,
+    raw_bodies: Mutex<&'a mut RawBodies>,
+    allow_duplicate_exports: bool,
+    merge_file_records: bool,
+}
+
+impl SymCorpus {
+    pub fn new() -> Self {
+        Self {
+            types: Types::new(),
+            exports: Exports::new(),
+            files: SymFiles::new(),
+            strings: Interner::default(),
+            raw_bodies: RawBodies::new(),
+        }
+    }
+
+    // TODO Describe.
+    pub fn load(&mut self, path: &Path, num_workers: i32) -> Result<(), crate::Error> {
+        self.load_with(path, num_workers, &LoadOptions::default())
+    }
+
+    /// Loads symtypes data from a specified path, which can be a directory tree or a single file,
+    /// using the given options.
+    ///
+    /// The number of worker threads is passed separately from the options, in the same way as to
+    /// the other parallel operations of the corpus, such as [`SymCorpus::compare_with`].
+    pub fn load_with(
+        &mut self,
+        path: &Path,
+        num_workers: i32,
+        options: &LoadOptions,
+    ) -> Result<(), crate::Error> {
+        // Determine if the input is a directory tree or a single symtypes file.
+        let md = fs::metadata(path).map_err(|err| {
+            crate::Error::new_io(&format!("Failed to query path '{}'", path.display()), err)
+        })?;
+
+        // Collect recursively all symtypes if it is a directory, or push the single file. The files
+        // are recorded relative to the directory, a single file by its base name.
+        let mut symfiles = Vec::new();
+        let root = if md.is_dir() {
+            Self::collect_symfiles(path, path, options, &mut symfiles)?;
+            path
+        } else {
+            symfiles.push(path.to_path_buf());
+            path.parent().unwrap_or(Path::new(""))
+        };
+
+        // Load all files.
+        self.load_files(
+            &symfiles,
+            root,
+            num_workers,
+            options,
+            &Mutex::new(|_: &Path, _: Result<(), &crate::Error>| {}),
+        )?;
+
+        if options.check_refs {
+            self.validate()?;
+        }
+        Ok(())
+    }
+
+    /// Collects recursively all symtypes under a given path, which is inside the `root` directory
+    /// of the loaded tree.
+    ///
+    /// With the `skip_errors` option, entries which cannot be read are reported as diagnostics and
+    /// otherwise ignored.
+    fn collect_symfiles(
+        path: &Path,
+        root: &Path,
+        options: &LoadOptions,
+        symfiles: &mut Vec<PathBuf>,
+    ) -> Result<(), crate::Error> {
+        // Handles an I/O error, either by skipping the entry or by failing.
+        let handle_err = |desc: &str, entry_path: &Path, err: io::Error| {
+            if !options.skip_errors {
+                return Err(crate::Error::new_io(
+                    &format!("{} '{}'", desc, entry_path.display()),
+                    err,
+                ));
+            }
+            let diagnostic = Diagnostic::new(
+                Severity::Warning,
+                "SKIPPED_ENTRY",
+                format!("{}: {}", desc, err),
+                Some(entry_path),
+            );
+            match &options.diagnostic_callback {
+                Some(callback) => callback(&diagnostic),
+                None => warn!("{}", diagnostic),
+            }
+            Ok(())
+        };
+
+        let dir_iter = match fs::read_dir(path) {
+            Ok(dir_iter) => dir_iter,
+            Err(err) => return handle_err("Failed to read directory", path, err),
+        };
+
+        let mut subdirs = Vec::new();
+        for maybe_entry in dir_iter {
+            let entry = match maybe_entry {
+                Ok(entry) => entry,
+                Err(err) => {
+                    handle_err("Failed to read directory", path, err)?;
+                    continue;
+                }
+            };
+
+            let entry_path = entry.path();
+
+            let md = match fs::symlink_metadata(&entry_path) {
+                Ok(md) => md,
+                Err(err) => {
+                    handle_err("Failed to query path", &entry_path, err)?;
+                    continue;
+                }
+            };
+
+            if md.is_symlink() {
+                continue;
+            }
+
+            if md.is_dir() {
+                subdirs.push(entry_path);
+                continue;
+            }
+
+            if Self::has_symtypes_extension(&entry_path, options)
+                && Self::is_selected(&entry_path, root, options)
+            {
+                symfiles.push(entry_path.to_path_buf());
+            }
+        }
+
+        for subdir in subdirs {
+            Self::collect_symfiles(&subdir, root, options, symfiles)?;
+        }
+        Ok(())
+    }
+
+    /// Loads all specified symtypes.
+    pub fn load_multiple(
+        &mut self,
+        symfiles: &Vec<PathBuf>,
+        num_workers: i32,
+    ) -> Result<(), crate::Error> {
+        self.load_multiple_with(symfiles, num_workers, &LoadOptions::default())
+    }
+
+    /// Loads all specified symtypes using the given options.
+    ///
+    /// The files are recorded relative to their deepest common directory.
+    pub fn load_multiple_with(
+        &mut self,
+        symfiles: &Vec<PathBuf>,
+        num_workers: i32,
+        options: &LoadOptions,
+    ) -> Result<(), crate::Error> {
+        self.load_multiple_with_observer(symfiles, num_workers, options, |_, _| {})
+    }
+
+    /// Loads all specified symtypes using the given options, calling `observer` after each file is
+    /// loaded.
+    ///
+    /// The observer receives the path of the file and the result of loading it. The calls are
+    /// serialized, but they come from the worker threads in the order in which the files finish
+    /// loading. The files are recorded relative to their deepest common directory.
+    pub fn load_multiple_with_observer<F>(
+        &mut self,
+        symfiles: &Vec<PathBuf>,
+        num_workers: i32,
+        options: &LoadOptions,
+        observer: F,
+    ) -> Result<(), crate::Error>
+    where
+        F: FnMut(&Path, Result<(), &crate::Error>) + Send,
+    {
+        let mut root = match symfiles.first() {
+            Some(symfile) => symfile.parent().unwrap_or(Path::new("")).to_path_buf(),
+            None => PathBuf::new(),
+        };
+        for symfile in symfiles {
+            while !symfile.starts_with(&root) {
+                root.pop();
+            }
+        }
+        self.load_files(symfiles, &root, num_workers, options, &Mutex::new(observer))
+    }
+
+    /// Loads all specified symtypes, recording their paths relative to a given root directory.
+    ///
+    /// The `observer` is called with the result of loading each file.
+    fn load_files<F>(
+        &mut self,
+        symfiles: &[PathBuf],
+        root: &Path,
+        num_workers: i32,
+        options: &LoadOptions,
+        observer: &Mutex<F>,
+    ) -> Result<(), crate::Error>
+    where
+        F: FnMut(&Path, Result<(), &crate::Error>) + Send,
+    {
+        // Load data from the files.
+        let next_work_idx = AtomicUsize::new(0);
+        let num_done = AtomicUsize::new(0);
+        let failed = AtomicBool::new(false);
+
+        let load_context = ParallelLoadContext {
+            types: Mutex::new(&mut self.types),
+            exports: Mutex::new(&mut self.exports),
+            files: Mutex::new(&mut self.files),
+            strings: Mutex::new(&mut self.strings),
+            raw_bodies: Mutex::new(&mut self.raw_bodies),
+            allow_duplicate_exports: options.allow_duplicate_exports,
+            merge_file_records: options.merge_file_records,
+        };
+
+        thread::scope(|s| {
+            if options.progress {
+                s.spawn(|| report_progress("loaded", "files", &num_done, symfiles.len()));
+            }
+
+            let mut workers = Vec::new();
+            for _ in 0..num_workers {
+                workers.push(s.spawn(|| -> Result<(), crate::Error> {
+                    loop {
+                        let work_idx = next_work_idx.fetch_add(1, Ordering::Relaxed);
+                        if work_idx >= symfiles.len() || failed.load(Ordering::Relaxed) {
+                            return Ok(());
+                        }
+                        let path = symfiles[work_idx].as_path();
+
+                        if let Some(max_memory) = options.max_memory {
+                            Self::check_memory(max_memory)
+                                .inspect_err(|_| failed.store(true, Ordering::Relaxed))?;
+                        }
+
+                        let record_path = path.strip_prefix(root).unwrap_or(path);
+                        let result = Self::load_file(path, record_path, options, &load_context);
+
+                        // Let the observer know about the result, one file at a time.
+                        (observer.lock().unwrap())(path, result.as_ref().map(|_| ()));
+                        if let Err(err) = result {
+                            // Tell the other workers to stop, the corpus is incomplete.
+                            failed.store(true, Ordering::Relaxed);
+                            return Err(err);
+                        }
+
+                        let done = num_done.fetch_add(1, Ordering::Relaxed) + 1;
+                        if let Some(callback) = &options.progress_callback {
+                            callback(done, symfiles.len());
+                        }
+                    }
+                }));
+            }
+
+            // Report the first error from the workers. Mark all files as done so the progress
+            // reporter finishes also if some of them failed.
+            let result = workers
+                .into_iter()
+                .map(|worker| worker.join().unwrap())
+                .collect::<Result<Vec<_>, _>>();
+            num_done.store(symfiles.len(), Ordering::Relaxed);
+            result
+        })?;
+
+        Ok(())
+    }
+
+    /// Loads a symtypes file, reusing its parsed records from the cache if enabled by the options.
+    fn load_file(
+        path: &Path,
+        record_path: &Path,
+        options: &LoadOptions,
+        load_context: &ParallelLoadContext,
+    ) -> Result<(), crate::Error> {
+        // Opens the file for parsing.
+        let open_file = || -> Result<File, crate::Error> {
+            let file = File::open(path).map_err(|err| {
+                crate::Error::new_io(&format!("Failed to open file '{}'", path.display()), err)
+            })?;
+            if let Some(callback) = &options.parse_callback {
+                callback(path);
+            }
+            Ok(file)
+        };
+
+        // The cache doesn't retain the original formatting of the records. Without the cache, the
+        // records are inserted as they are parsed.
+        let cache_dir = match &options.cache_dir {
+            Some(cache_dir) if !options.preserve_formatting => cache_dir,
+            _ => {
+                let file = open_file()?;
+                return Self::load_single(
+                    path,
+                    record_path,
+                    file,
+                    InputFormat::Auto,
+                    options,
+                    load_context,
+                );
+            }
+        };
+
+        debug!("Loading {}", path.display());
+
+        let stamp = Self::get_file_stamp(path);
+        let cached = stamp
+            .as_ref()
+            .and_then(|stamp| Self::read_cache(cache_dir, path, stamp));
+        let parsed = match cached {
+            Some(parsed) => parsed,
+            None => {
+                let mut records = Vec::new();
+                let file_lines = Self::parse_input(
+                    path,
+                    open_file()?,
+                    InputFormat::Auto,
+                    false,
+                    |line_idx, name, tokens, _| {
+                        records.push((line_idx, name, tokens));
+                        Ok(())
+                    },
+                )?;
+                let parsed = (records, file_lines);
+                if let Some(stamp) = &stamp {
+                    Self::write_cache(cache_dir, path, stamp, &parsed);
+                }
+                parsed
+            }
+        };
+
+        Self::insert_parsed(path, record_path, parsed, options, load_context)
+    }
+
+    /// Checks that brackets in a parsed record are balanced, if requested by the options.
+    ///
+    /// An imbalance usually means that the declaration was truncated. It is reported as a warning,
+    /// or as an error in the strict mode.
+    fn check_brackets(
+        path: &Path,
+        line_idx: usize,
+        name: &str,
+        tokens: &Tokens,
+        options: &LoadOptions,
+    ) -> Result<(), crate::Error> {
+        if !options.check_brackets && !options.strict {
+            return Ok(());
+        }
+        if let Some(problem) = find_bracket_imbalance(tokens) {
+            let msg = format!("Record '{}' has unbalanced brackets, {}", name, problem);
+            if options.strict {
+                return Err(Self::new_line_error(path, line_idx, &msg));
+            }
+            warn!("{}:{}: {}", path.display(), line_idx + 1, msg);
+        }
+        Ok(())
+    }
+
+    /// Returns a stamp identifying the current content of a file, formed by its modification time
+    /// and size, or [`None`] if the file cannot be queried.
+    fn get_file_stamp(path: &Path) -> Option<String> {
+        let md = fs::metadata(path).ok()?;
+        let mtime = md.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(format!(
+            "{}.{:09} {}",
+            mtime.as_secs(),
+            mtime.subsec_nanos(),
+            md.len()
+        ))
+    }
+
+    /// Returns the path of the cache entry of a specified file.
+    ///
+    /// Entries are named by a hash of the absolute path of the file, the path itself is recorded in
+    /// the entry to detect collisions.
+    fn get_cache_path(cache_dir: &Path, path: &Path) -> (PathBuf, String) {
+        let abs_path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let abs_path = abs_path.to_string_lossy().to_string();
+        let mut hash = Fnv1aHasher::new();
+        hash.write(abs_path.as_bytes());
+        (
+            cache_dir.join(format!("{:016x}.cache", hash.finish())),
+            abs_path,
+        )
+    }
+
+    /// Reads the parsed records of a file from the cache, if it has an entry matching the given
+    /// stamp of the file.
+    ///
+    /// The entry consists of a header line, the absolute path and the stamp of the file, followed
+    /// by the type/export records prefixed with `R` and the file records prefixed with `F`, both
+    /// with their line indices. Any problem with the entry is treated as a cache miss.
+    fn read_cache(cache_dir: &Path, path: &Path, stamp: &str) -> Option<ParsedRecords> {
+        let (cache_path, abs_path) = Self::get_cache_path(cache_dir, path);
+        let file = File::open(&cache_path).ok()?;
+        let mut lines = BufReader::new(file).lines();
+        if lines.next()?.ok()? != CACHE_HEADER
+            || lines.next()?.ok()? != abs_path
+            || lines.next()?.ok()? != stamp
+        {
+            debug!("Cache entry '{}' is stale", cache_path.display());
+            return None;
+        }
+
+        let mut parsed = Vec::new();
+        let mut file_lines = Vec::new();
+        for line in lines {
+            let line = line.ok()?;
+            match line.split_once(' ')? {
+                ("R", record) => {
+                    let (idx, record) = record.split_once(' ')?;
+                    let mut words = record.split_ascii_whitespace();
+                    let name = words.next()?.to_string();
+                    parsed.push((idx.parse().ok()?, name, Self::words_into_tokens(&mut words)));
+                }
+                ("F", file_record) => {
+                    let (idx, file_record) = file_record.split_once(' ')?;
+                    file_lines.push((idx.parse().ok()?, file_record.to_string()));
+                }
+                _ => return None,
+            }
+        }
+        debug!("Using cache entry '{}'", cache_path.display());
+        Some((parsed, file_lines))
+    }
+
+    /// Writes the parsed records of a file to the cache, see [`SymCorpus::read_cache`] for the
+    /// format.
+    ///
+    /// The cache is only an optimization, a failure to write it is reported as a warning.
+    fn write_cache(
+        cache_dir: &Path,
+        path: &Path,
+        stamp: &str,
+        (parsed, file_lines): &ParsedRecords,
+    ) {
+        let (cache_path, abs_path) = Self::get_cache_path(cache_dir, path);
+
+        // Write a temporary file first and rename it so a concurrent reader never sees a partial
+        // entry.
+        let tmp_path = cache_path.with_extension(format!("tmp{}", std::process::id()));
+        let write = || -> io::Result<()> {
+            fs::create_dir_all(cache_dir)?;
+            let mut writer = BufWriter::new(File::create(&tmp_path)?);
+            writeln!(writer, "{}", CACHE_HEADER)?;
+            writeln!(writer, "{}", abs_path)?;
+            writeln!(writer, "{}", stamp)?;
+            for (idx, name, tokens) in parsed {
+                writeln!(writer, "R {} {}", idx, format_record(name, tokens))?;
+            }
+            for (idx, file_record) in file_lines {
+                writeln!(writer, "F {} {}", idx, file_record)?;
+            }
+            writer.flush()?;
+            drop(writer);
+            fs::rename(&tmp_path, &cache_path)
+        };
+        if let Err(err) = write() {
+            warn!(
+                "Failed to write cache entry '{}': {}",
+                cache_path.display(),
+                err
+            );
+            let _ = fs::remove_file(&tmp_path);
+        }
+    }
+
+    /// Checks that the resident memory of the process doesn't exceed a specified limit in bytes.
+    ///
+    /// The resident memory size is read from `/proc/self/status`. The check is skipped if it is not
+    /// available.
+    fn check_memory(max_memory: u64) -> Result<(), crate::Error> {
+        let status = match fs::read_to_string("/proc/self/status") {
+            Ok(status) => status,
+            Err(_) => return Ok(()),
+        };
+        let rss = status
+            .lines()
+            .find_map(|line| line.strip_prefix("VmRSS:"))
+            .and_then(|value| value.trim().strip_suffix("kB"))
+            .and_then(|value| value.trim().parse::<u64>().ok());
+        match rss {
+            Some(rss) => Self::check_memory_usage(rss * 1024, max_memory),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks that a resident memory size in bytes doesn't exceed a specified limit in bytes.
+    fn check_memory_usage(rss: u64, max_memory: u64) -> Result<(), crate::Error> {
+        if rss <= max_memory {
+            return Ok(());
+        }
+        // Round the resident size up so it is reported as larger than the limit.
+        const MB: u64 = 1024 * 1024;
+        Err(crate::Error::new_parse(&format!(
+            concat!(
+                "Resident memory of {} MB exceeds the limit of {} MB, raise the limit to load ",
+                "the input"
+            ),
+            rss.div_ceil(MB),
+            max_memory / MB
+        )))
+    }
+
+    /// Creates a new corpus from symtypes data in a specified buffer, detecting automatically
+    /// whether it is a single or consolidated file.
+    ///
+    /// The data is recorded under `path`, which is also used to report errors.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ksymtypes::sym::SymCorpus;
+    /// use std::path::Path;
+    ///
+    /// let syms = SymCorpus::from_buffer(
+    ///     Path::new("test.symtypes"),
+    ///     b"s#foo struct foo { int a ; }\nbar int bar ( s#foo )\n",
+    /// )?;
+    /// assert!(syms.contains_export("bar"));
+    /// assert_eq!(syms.type_definition("bar").unwrap(), "int bar ( s#foo )");
+    /// # Ok::<(), ksymtypes::Error>(())
+    /// ```
+    pub fn from_buffer(path: &Path, data: &[u8]) -> Result<Self, crate::Error> {
+        Self::from_reader(path, data)
+    }
+
+    /// Creates a new corpus from symtypes data read from a specified reader, detecting
+    /// automatically whether it is a single or consolidated file.
+    pub fn from_reader<R>(path: &Path, reader: R) -> Result<Self, crate::Error>
+    where
+        R: io::Read,
+    {
+        let mut syms = Self::new();
+        syms.load_buffer(path, reader)?;
+        Ok(syms)
+    }
+
+    /// Loads symtypes data from a specified reader, detecting automatically whether it is a single
+    /// or consolidated file.
+    pub fn load_buffer<R>(&mut self, path: &Path, reader: R) -> Result<(), crate::Error>
+    where
+        R: io::Read,
+    {
+        self.load_buffer_as(path, reader, InputFormat::Auto)
+    }
+
+    /// Loads symtypes data from a specified reader, interpreting it in the given format.
+    pub fn load_buffer_as<R>(
+        &mut self,
+        path: &Path,
+        reader: R,
+        format: InputFormat,
+    ) -> Result<(), crate::Error>
+    where
+        R: io::Read,
+    {
+        self.load_buffer_with(path, reader, format, &LoadOptions::default())
+    }
+
+    /// Loads symtypes data from a specified reader, interpreting it in the given format and using
+    /// the given options.
+    pub fn load_buffer_with<R>(
+        &mut self,
+        path: &Path,
+        reader: R,
+        format: InputFormat,
+        options: &LoadOptions,
+    ) -> Result<(), crate::Error>
+    where
+        R: io::Read,
+    {
+        let load_context = ParallelLoadContext {
+            types: Mutex::new(&mut self.types),
+            exports: Mutex::new(&mut self.exports),
+            files: Mutex::new(&mut self.files),
+            strings: Mutex::new(&mut self.strings),
+            raw_bodies: Mutex::new(&mut self.raw_bodies),
+            allow_duplicate_exports: options.allow_duplicate_exports,
+            merge_file_records: options.merge_file_records,
+        };
+
+        Self::load_single(path, path, reader, format, options, &load_context)?;
+
+        if options.check_refs {
+            self.validate()?;
+        }
+        Ok(())
+    }
+
+    /// Loads symtypes data from the standard input, interpreting it in the given format.
+    ///
+    /// The input is recorded under the file name `<stdin>`.
+    pub fn load_stdin(&mut self, format: InputFormat) -> Result<(), crate::Error> {
+        self.load_stdin_with(format, &LoadOptions::default())
+    }
+
+    /// Loads symtypes data from the standard input, interpreting it in the given format and using
+    /// the given options.
+    ///
+    /// The input is recorded under the file name `<stdin>`.
+    pub fn load_stdin_with(
+        &mut self,
+        format: InputFormat,
+        options: &LoadOptions,
+    ) -> Result<(), crate::Error> {
+        self.load_buffer_with(Path::new("<stdin>"), io::stdin().lock(), format, options)
+    }
+
+    /// Loads symtypes files described by a manifest.
+    ///
+    /// Each non-empty line of the manifest which doesn't start with `#` has the form
+    /// `<logical-name>: <fragment>, <fragment>, ...`. The records of all fragments of a logical
+    /// file are merged and recorded as one file with the logical name. Fragments can share
+    /// identical records, but a record with the same name and a different body in two fragments of
+    /// the same logical file is an error, as is listing the same logical name on multiple lines.
+    /// Relative fragment paths are resolved against the directory of the manifest.
+    pub fn load_manifest(&mut self, manifest: &Path) -> Result<(), crate::Error> {
+        let file = File::open(manifest).map_err(|err| {
+            crate::Error::new_io(
+                &format!("Failed to open file '{}'", manifest.display()),
+                err,
+            )
+        })?;
+        let lines = Self::read_lines(manifest, file)?;
+        let base_dir = manifest.parent().unwrap_or(Path::new(""));
+        let mut logical_lines = HashMap::new();
+
+        for (i, line) in lines.iter().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (logical_name, fragments) = line.split_once(':').ok_or_else(|| {
+                crate::Error::new_parse(&format!(
+                    "{}:{}: Expected a logical file name followed by ':'",
+                    manifest.display(),
+                    i + 1
+                ))
+            })?;
+            let logical_name = logical_name.trim();
+            if logical_name.is_empty() {
+                return Err(crate::Error::new_parse(&format!(
+                    "{}:{}: Expected a logical file name",
+                    manifest.display(),
+                    i + 1
+                )));
+            }
+            if let Some(other_line) = logical_lines.insert(logical_name, i) {
+                return Err(crate::Error::new_parse(&format!(
+                    "{}:{}: Logical file name '{}' is already listed on line {}",
+                    manifest.display(),
+                    i + 1,
+                    logical_name,
+                    other_line + 1
+                )));
+            }
+
+            // Merge records of all fragments, dropping duplicates.
+            let mut merged = String::new();
+            let mut seen = HashMap::new();
+            for fragment in fragments.split(',') {
+                let fragment = fragment.trim();
+                if fragment.is_empty() {
+                    continue;
+                }
+                let fragment_path = base_dir.join(fragment);
+                let file = File::open(&fragment_path).map_err(|err| {
+                    crate::Error::new_io(
+                        &format!("Failed to open file '{}'", fragment_path.display()),
+                        err,
+                    )
+                })?;
+
+                for (j, record) in Self::read_lines(&fragment_path, file)?
+                    .into_iter()
+                    .enumerate()
+                {
+                    let mut words = record.split_ascii_whitespace();
+                    let name = match words.next() {
+                        Some(name) => name.to_string(),
+                        None => continue,
+                    };
+                    let body = words.collect::<Vec<_>>().join(" ");
+                    match seen.entry(name) {
+                        Occupied(entry) => {
+                            let (other_fragment, other_body): &(PathBuf, String) = entry.get();
+                            if *other_body != body {
+                                return Err(crate::Error::new_parse(&format!(
+                                    "{}:{}: Record '{}' conflicts with its declaration in '{}'",
+                                    fragment_path.display(),
+                                    j + 1,
+                                    entry.key(),
+                                    other_fragment.display()
+                                )));
+                            }
+                        }
+                        Vacant(entry) => {
+                            merged.push_str(&record);
+                            merged.push('\n');
+                            entry.insert((fragment_path.clone(), body));
+                        }
+                    }
+                }
+            }
+
+            self.load_buffer_as(
+                Path::new(logical_name),
+                merged.as_bytes(),
+                InputFormat::Single,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Loads symtypes data from a specified reader.
+    ///
+    /// The `path` is used to report errors, a single symtypes file is recorded in the corpus under
+    /// `record_path`. The type/export records are inserted in the corpus as they are parsed.
+    fn load_single<R>(
+        path: &Path,
+        record_path: &Path,
+        reader: R,
+        format: InputFormat,
+        options: &LoadOptions,
+        load_context: &ParallelLoadContext,
+    ) -> Result<(), crate::Error>
+    where
+        R: io::Read,
+    {
+        debug!("Loading {}", path.display());
+
+        // When the format is detected automatically, it is known only after all `F#` records are
+        // read. Records with a name containing the `@` character are interpreted differently in
+        // each format and so their insertion is deferred until then, all other records are
+        // inserted immediately.
+        let mut remap = HashMap::new();
+        let mut deferred = Vec::new();
+        let file_lines = Self::parse_input(
+            path,
+            reader,
+            format,
+            options.preserve_formatting,
+            |line_idx, name, tokens, raw_body| {
+                Self::check_brackets(path, line_idx, &name, &tokens, options)?;
+                if format == InputFormat::Auto && name.contains('@') {
+                    deferred.push((name, tokens, raw_body));
+                } else {
+                    Self::insert_type_record(
+                        name,
+                        tokens,
+                        raw_body,
+                        format,
+                        &mut remap,
+                        load_context,
+                    );
+                }
+                Ok(())
+            },
+        )?;
+
+        let format = Self::resolve_format(format, &file_lines);
+        for (name, tokens, raw_body) in deferred {
+            Self::insert_type_record(name, tokens, raw_body, format, &mut remap, load_context);
+        }
+        Self::insert_file_records(path, record_path, file_lines, remap, format, load_context)
+    }
+
+    /// Resolves the format of a symtypes input, detecting it from its `file_lines` if the format
+    /// is [`InputFormat::Auto`].
+    fn resolve_format(format: InputFormat, file_lines: &[(usize, String)]) -> InputFormat {
+        match format {
+            InputFormat::Auto if file_lines.is_empty() => InputFormat::Single,
+            InputFormat::Auto => InputFormat::Consolidated,
+            _ => format,
+        }
+    }
+
+    /// Parses symtypes data from a specified reader, passing each type/export record to
+    /// `on_record`, see [`SymCorpus::read_records`].
+    fn parse_input<R, F>(
+        path: &Path,
+        reader: R,
+        format: InputFormat,
+        keep_raw: bool,
+        on_record: F,
+    ) -> Result<Vec<(usize, String)>, crate::Error>
+    where
+        R: io::Read,
+        F: FnMut(usize, String, Tokens, Option<String>) -> Result<(), crate::Error>,
+    {
+        // Parse the input incrementally, decompressing the data transparently if the file is
+        // gzipped. Only the raw file records are kept, which avoids holding the whole input text in
+        // memory.
+        if Self::is_gzip_path(path) {
+            Self::read_records(
+                path,
+                BufReader::new(GzDecoder::new(reader)),
+                format,
+                keep_raw,
+                on_record,
+            )
+        } else {
+            Self::read_records(path, BufReader::new(reader), format, keep_raw, on_record)
+        }
+    }
+
+    /// Inserts symtypes data, which was parsed beforehand, in the corpus.
+    ///
+    /// The `path` is used to report errors, a single symtypes file is recorded in the corpus under
+    /// `record_path`.
+    fn insert_parsed(
+        path: &Path,
+        record_path: &Path,
+        (parsed, file_lines): ParsedRecords,
+        options: &LoadOptions,
+        load_context: &ParallelLoadContext,
+    ) -> Result<(), crate::Error> {
+        let format = Self::resolve_format(InputFormat::Auto, &file_lines);
+        let mut remap = HashMap::new();
+        for (line_idx, name, tokens) in parsed {
+            Self::check_brackets(path, line_idx, &name, &tokens, options)?;
+            Self::insert_type_record(name, tokens, None, format, &mut remap, load_context);
+        }
+        Self::insert_file_records(path, record_path, file_lines, remap, format, load_context)
+    }
+
+    /// Inserts a type/export record of a symtypes input in the corpus.
+    ///
+    /// The name is split into its base name and any variant name/index, unless the input is known
+    /// to be a single symtypes file. A mapping from the original variant name/index to the new one
+    /// is recorded in `remap`, for use by [`SymCorpus::insert_file_records`].
+    fn insert_type_record(
+        name: String,
+        tokens: Tokens,
+        raw_body: Option<String>,
+        format: InputFormat,
+        remap: &mut HashMap<String, HashMap<String, usize>>,
+        load_context: &ParallelLoadContext,
+    ) {
+        // Parse the base name and any variant name/index, which is appended as a suffix after the
+        // `@` character.
+        let (base_name, orig_variant_name) = if format == InputFormat::Single {
+            (name.as_str(), "")
+        } else {
+            Self::split_type_name(&name)
+        };
+
+        // Retain the original text of the variant, if available. The first text read for the
+        // variant is kept.
+        if let Some(raw_body) = raw_body {
+            load_context
+                .raw_bodies
+                .lock()
+                .unwrap()
+                .entry(base_name.to_string())
+                .or_default()
+                .entry(tokens.clone())
+                .or_insert(raw_body);
+        }
+
+        // Insert the type into the corpus.
+        let variant_idx = Self::merge_type(base_name, tokens, load_context);
+
+        // Record a mapping from the original variant name/index to the new one.
+        remap
+            .entry(base_name.to_string())
+            .or_default()
+            .insert(orig_variant_name.to_string(), variant_idx);
+    }
+
+    /// Adds the files of a symtypes input to the corpus, after all its type/export records were
+    /// inserted by [`SymCorpus::insert_type_record`].
+    ///
+    /// A consolidated input adds the files declared by its `file_lines`, a single symtypes file is
+    /// recorded under `record_path` with all its records. The `format` must be already resolved by
+    /// [`SymCorpus::resolve_format`].
+    fn insert_file_records(
+        path: &Path,
+        record_path: &Path,
+        file_lines: Vec<(usize, String)>,
+        remap: HashMap<String, HashMap<String, usize>>,
+        format: InputFormat,
+        load_context: &ParallelLoadContext,
+    ) -> Result<(), crate::Error> {
+        if format != InputFormat::Consolidated {
+            let file_idx = {
+                let symfile = SymFile {
+                    path: record_path.to_path_buf(),
+                    records: FileRecords::new(),
+                };
+                let mut files = load_context.files.lock().unwrap();
+                files.push(symfile);
+                files.len() - 1
+            };
+
+            let mut records = FileRecords::new();
+            for (base_name, variants) in &remap {
+                Self::insert_record(
+                    base_name,
+                    variants[""],
+                    file_idx,
+                    &mut records,
+                    load_context,
+                )?;
+            }
+
+            // Update the file records.
+            let mut files = load_context.files.lock().unwrap();
+            files[file_idx].records = records;
+            return Ok(());
+        }
+
+        // Handle file declarations. Track the file index of each path to detect duplicate
+        // records, which are either rejected or merged.
+        let mut file_indices = HashMap::new();
+        for (i, line) in file_lines {
+            let mut words = line.split_ascii_whitespace();
+
+            let record_name = words.next().unwrap();
+            assert!(record_name.starts_with("F#"));
+            let file_name = &record_name[2..];
+
+            let file_idx = match file_indices.get(file_name) {
+                Some(&file_idx) if load_context.merge_file_records => file_idx,
+                Some(_) => {
+                    return Err(Self::new_line_error(
+                        path,
+                        i,
+                        &format!("Duplicate record '{}'", record_name),
+                    ))
+                }
+                None => {
+                    let symfile = SymFile {
+                        path: Path::new(file_name).to_path_buf(),
+                        records: FileRecords::new(),
+                    };
+                    let mut files = load_context.files.lock().unwrap();
+                    files.push(symfile);
+                    file_indices.insert(file_name.to_string(), files.len() - 1);
+                    files.len() - 1
+                }
+            };
+
+            let mut records = FileRecords::new();
+            for type_name in words {
+                // Parse the base name and variant name/index.
+                let (base_name, orig_variant_name) = Self::split_type_name(type_name);
+
+                // Check that an export listed by the record is declared. A reference with a variant
+                // suffix is a type reference and is reported below if it is not known.
+                if orig_variant_name.is_empty()
+                    && Self::is_export(base_name)
+                    && !remap.contains_key(base_name)
+                {
+                    return Err(Self::new_line_error(
+                        path,
+                        i,
+                        &format!(
+                            "Export {} listed by file '{}' is not declared",
+                            type_name, file_name
+                        ),
+                    ));
+                }
+
+                // Look up how the variant got remapped.
+                let variant_idx = *remap
+                    .get(base_name)
+                    .and_then(|hash| hash.get(orig_variant_name))
+                    .ok_or_else(|| {
+                        Self::new_line_error(path, i, &format!("Type {} is not known", type_name))
+                    })?;
+
+                // Insert the record.
+                Self::insert_record(base_name, variant_idx, file_idx, &mut records, load_context)?;
+            }
+
+            // Add implicit references, ones that were omitted by the F# declaration because
+            // only one variant exists in the entire consolidated file.
+            let walk_records: Vec<_> = records
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect();
+            for (name, variant_idx) in walk_records {
+                // TODO Simplify.
+                let types = load_context.types.lock().unwrap();
+                Self::extrapolate_file_record(
+                    path,
+                    file_name,
+                    &name,
+                    variant_idx,
+                    true,
+                    &*types,
+                    &mut records,
+                )?;
+            }
+
+            // Add the records to the file, possibly merging them with records of a previous
+            // F# record of the same file.
+            let mut files = load_context.files.lock().unwrap();
+            let file_records = &mut files[file_idx].records;
+            if file_records.is_empty() {
+                *file_records = records;
+                continue;
+            }
+            for (name, variant_idx) in records {
+                match file_records.entry(name) {
+                    Occupied(entry) if *entry.get() != variant_idx => {
+                        return Err(Self::new_line_error(
+                            path,
+                            i,
+                            &format!(
+                                "Type {} has a different variant in another record of file '{}'",
+                                entry.key(),
+                                file_name
+                            ),
+                        ));
+                    }
+                    Occupied(_) => {}
+                    Vacant(entry) => {
+                        entry.insert(variant_idx);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Creates a parse error for a problem found on the line with the zero-based index `line_idx`
+    /// of a given file, formatted as `<path>:<line>: <msg>`.
+    fn new_line_error(path: &Path, line_idx: usize, msg: &str) -> crate::Error {
+        crate::Error::new_parse(&format!("{}:{}: {}", path.display(), line_idx + 1, msg))
+    }
+
+    /// Returns whether a specified path names a gzip-compressed file, based on its `.gz`
+    /// extension.
+    fn is_gzip_path(path: &Path) -> bool {
+        path.extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("gz"))
+    }
+
+    /// Returns whether a specified path has one of the symtypes extensions selected by the load
+    /// options, possibly followed by the `.gz` extension.
+    fn has_symtypes_extension(path: &Path, options: &LoadOptions) -> bool {
+        let file_name = match path.file_name() {
+            Some(file_name) => Path::new(file_name),
+            None => return false,
+        };
+        let file_name = if Self::is_gzip_path(file_name) {
+            Path::new(file_name.file_stem().unwrap())
+        } else {
+            file_name
+        };
+        let ext = file_name.extension().unwrap_or_default();
+
+        if options.extensions.is_empty() {
+            return ext.eq_ignore_ascii_case("symtypes");
+        }
+        options
+            .extensions
+            .iter()
+            .any(|option_ext| ext.eq_ignore_ascii_case(option_ext))
+    }
+
+    /// Returns whether a file under a given root directory is selected by the `include` and
+    /// `exclude` patterns of the options.
+    fn is_selected(path: &Path, root: &Path, options: &LoadOptions) -> bool {
+        let rel_path = Self::normalize_record_path(path.strip_prefix(root).unwrap_or(path));
+        let is_match = |pattern: &String| crate::glob::matches(pattern, &rel_path);
+        (options.include.is_empty() || options.include.iter().any(is_match))
+            && !options.exclude.iter().any(is_match)
+    }
+
+    /// Reads data from a specified reader and splits its content into a lines vector.
+    fn read_lines<R>(path: &Path, reader: R) -> Result<Vec<String>, crate::Error>
+    where
+        R: io::Read,
+    {
+        let reader = BufReader::new(reader);
+        let mut lines = Vec::new();
+        for maybe_line in reader.lines() {
             match maybe_line {
                 Ok(line) => lines.push(line),
                 Err(err) => {
                     return Err(crate::Error::new_io(
-                        &format!("Failed to read data from file '{}'", path.display()),
+                        &format!("Failed to read data from file '{}'", path.display()),
+                        err,
+                    ))
+                }
+            };
+        }
+        Ok(lines)
+    }
+
+    /// Reads and parses records from a given reader, line by line.
+    ///
+    /// Each type/export record is passed to `on_record` as soon as it is parsed, with its
+    /// zero-based line index, name and tokens. If `keep_raw` is set, the original text following
+    /// the name of the record is passed as well. Returns the raw file records with their
+    /// zero-based line indices. Empty and duplicate records are rejected, as are file records if
+    /// the `format` is [`InputFormat::Single`].
+    fn read_records<R, F>(
+        path: &Path,
+        reader: R,
+        format: InputFormat,
+        keep_raw: bool,
+        mut on_record: F,
+    ) -> Result<Vec<(usize, String)>, crate::Error>
+    where
+        R: BufRead,
+        F: FnMut(usize, String, Tokens, Option<String>) -> Result<(), crate::Error>,
+    {
+        // Track names of all entries to detect duplicates.
+        let mut all_names = HashSet::new();
+
+        let mut file_lines: Vec<(usize, String)> = Vec::new();
+        let mut continued = false;
+        for (i, maybe_line) in reader.lines().enumerate() {
+            let line = maybe_line.map_err(|err| {
+                crate::Error::new_io(
+                    &format!("Failed to read data from file '{}'", path.display()),
+                    err,
+                )
+            })?;
+
+            // Append a continuation line to the preceding file record.
+            if continued {
+                let (_, file_line) = file_lines.last_mut().unwrap();
+                let (line, more) = Self::strip_continuation(&line);
+                file_line.push(' ');
+                file_line.push_str(line.trim_start());
+                continued = more;
+                continue;
+            }
+
+            // Obtain a name of the record.
+            let mut words = line.split_ascii_whitespace();
+            let name = words
+                .next()
+                .ok_or_else(|| Self::new_line_error(path, i, "Expected a record name"))?;
+
+            // Check if the record is a duplicate of another one. Duplicate file records are
+            // diagnosed when they are inserted because they can be merged.
+            if !name.starts_with("F#") && !all_names.insert(name.to_string()) {
+                return Err(Self::new_line_error(
+                    path,
+                    i,
+                    &format!("Duplicate record '{}'", name),
+                ));
+            }
+
+            // Check for a file declaration and keep its line for later processing.
+            if name.starts_with("F#") {
+                if format == InputFormat::Single {
+                    return Err(Self::new_line_error(
+                        path,
+                        i,
+                        &format!(
+                            "Unexpected file record '{}' in a single symtypes file",
+                            name
+                        ),
+                    ));
+                }
+                let (line, more) = Self::strip_continuation(&line);
+                file_lines.push((i, line.to_string()));
+                continued = more;
+                continue;
+            }
+
+            // Keep the original text after the name, if requested.
+            let raw_body = if keep_raw {
+                let name_end = line.find(name).unwrap() + name.len();
+                Some(line[name_end..].to_string())
+            } else {
+                None
+            };
+
+            // Turn the remaining words into tokens.
+            let name = name.to_string();
+            let tokens = Self::words_into_tokens(&mut words);
+            on_record(i, name, tokens, raw_body)?;
+        }
+
+        if continued {
+            return Err(crate::Error::new_parse(&format!(
+                "{}: Unterminated file record '{}'",
+                path.display(),
+                file_lines
+                    .last()
+                    .unwrap()
+                    .1
+                    .split_ascii_whitespace()
+                    .next()
+                    .unwrap()
+            )));
+        }
+
+        Ok(file_lines)
+    }
+
+    /// Removes a trailing continuation token from a line of an `F#` record and returns the
+    /// remaining line, together with whether the record continues on the next line.
+    fn strip_continuation(line: &str) -> (&str, bool) {
+        let trimmed = line.trim_end();
+        match trimmed.rsplit_once(|c: char| c.is_ascii_whitespace()) {
+            Some((rest, FILE_RECORD_CONTINUATION)) => (rest.trim_end(), true),
+            _ => (line, false),
+        }
+    }
+
+    /// Reads words from a given iterator and converts them to a [`Vec`] of [`Token`]s.
+    fn words_into_tokens<'a, I>(words: &mut I) -> Vec<Token>
+    where
+        I: Iterator<Item = &'a str>,
+    {
+        let mut tokens = Vec::new();
+        for word in words {
+            tokens.push(if Self::is_type_name(word) {
+                Token::new_typeref(word)
+            } else {
+                Token::new_atom(word)
+            });
+        }
+        tokens
+    }
+
+    /// Splits a given type name into a tuple of two `&str`, with the first one being the base name
+    /// and the second one containing the variant name/index (or an empty string of no variant was
+    /// present).
+    fn split_type_name(type_name: &str) -> (&str, &str) {
+        match type_name.rfind('@') {
+            Some(i) => (&type_name[..i], &type_name[i + 1..]),
+            None => (type_name, &type_name[type_name.len()..]),
+        }
+    }
+
+    fn merge_type(
+        type_name: &str,
+        mut tokens: Tokens,
+        load_context: &ParallelLoadContext,
+    ) -> usize {
+        let mut types = load_context.types.lock().unwrap();
+        // TODO Use .entry()?
+        match types.get_mut(type_name) {
+            Some(variants) => {
+                for (i, variant) in variants.iter().enumerate() {
+                    if Self::are_tokens_eq(&tokens, variant) {
+                        return i;
+                    }
+                }
+                load_context
+                    .strings
+                    .lock()
+                    .unwrap()
+                    .intern_tokens(&mut tokens);
+                variants.push(tokens);
+                return variants.len() - 1;
+            }
+            None => {
+                load_context
+                    .strings
+                    .lock()
+                    .unwrap()
+                    .intern_tokens(&mut tokens);
+                let mut variants = Vec::new();
+                variants.push(tokens);
+                types.insert(type_name.to_string(), variants);
+                return 0;
+            }
+        }
+    }
+
+    /// Inserts the type specified by `base_name@variant_idx` in the file `records` and registers it
+    /// with its `file_idx` in the `load_context.exports` if it is an exported symbol.
+    fn insert_record(
+        base_name: &str,
+        variant_idx: usize,
+        file_idx: usize,
+        records: &mut FileRecords,
+        load_context: &ParallelLoadContext,
+    ) -> Result<(), crate::Error> {
+        records.insert(base_name.to_string(), variant_idx);
+
+        if Self::is_export(base_name) {
+            let maybe_other_file_idx = load_context
+                .exports
+                .lock()
+                .unwrap()
+                .insert(base_name.to_string(), file_idx);
+
+            // Diagnose a duplicate export.
+            if let Some(other_file_idx) = maybe_other_file_idx {
+                if other_file_idx != file_idx {
+                    let files = load_context.files.lock().unwrap();
+                    let desc = format!(
+                        "Export {} is declared in both '{}' and '{}'",
+                        base_name,
+                        files[other_file_idx].path.display(),
+                        files[file_idx].path.display()
+                    );
+                    if !load_context.allow_duplicate_exports {
+                        return Err(crate::Error::new_parse(&desc));
+                    }
+                    warn!("{}", desc);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Processes a single symbol in some file originated from an `F#` record and enhances the
+    /// specified file records with the needed implicit types.
+    ///
+    /// This function is used when reading a consolidated input file and processing its `F#`
+    /// records. Each `F#` record is in form `F#<filename> <type@variant>... <export>...`. It lists
+    /// all types and exports in a given file but is allowed to omit any referenced types which have
+    /// only one variant in the whole consolidated file. The purpose of this function is to find all
+    /// such implicit references and add them to `records`.
+    ///
+    /// A caller of this function should pre-fill `records` with all explicit references given on
+    /// the processed `F#` record and then call this function on each of the references. These root
+    /// calls should be invoked with `is_explicit` set to `true`. The function then recursively adds
+    /// all needed implicit types which are referenced from these roots.
+    fn extrapolate_file_record(
+        corpus_path: &Path,
+        file_name: &str,
+        name: &str,
+        variant_idx: usize,
+        is_explicit: bool,
+        types: &Types,
+        records: &mut FileRecords,
+    ) -> Result<(), crate::Error> {
+        if is_explicit {
+            // All explicit symbols need to be added by the caller.
+            assert!(records.get(name).is_some());
+        } else {
+            // A symbol can be implicit only if it has one variant.
+            assert!(variant_idx == 0);
+
+            // See if the symbol was already processed.
+            //
+            // Unfortunately, HashMap in stable Rust doesn't offer to do a lookup using &str but
+            // insert the key as String if it is missing. The code opts to run the lookup again
+            // if the key is missing and the key+value pair needs inserting.
+            // https://stackoverflow.com/questions/51542024
+            if records.get(name).is_some() {
+                return Ok(());
+            }
+            records.insert(name.to_string(), variant_idx);
+        }
+
+        // Obtain tokens for the selected variant and check it is correctly specified.
+        let variants = types.get(name).ok_or_else(|| {
+            crate::Error::new_parse(&format!(
+                "{}: Type '{}' is referenced by file '{}' but is not known in the corpus",
+                corpus_path.display(),
+                name,
+                file_name,
+            ))
+        })?;
+        assert!(variants.len() > 0);
+        if !is_explicit && variants.len() > 1 {
+            return Err(crate::Error::new_parse(&format!(
+                concat!(
+                    "{}: Type '{}' is implicitly referenced by file '{}' but has multiple ",
+                    "variants in the corpus"
+                ),
+                corpus_path.display(),
+                name,
+                file_name,
+            )));
+        }
+        let tokens = &variants[variant_idx];
+
+        // Process recursively all types referenced by this symbol.
+        for token in tokens {
+            match token {
+                Token::TypeRef(ref_name) => {
+                    // Process the type. Note that passing variant_idx=0 is ok here:
+                    // * If the type is explicitly specified in the parent F# record then it must be
+                    //   already added in the records and the called function immediately returns.
+                    // * If the type is implicit then it can have only one variant and so only
+                    //   variant_idx=0 can be correct. The invoked function will check that no more
+                    //   than one variant is actually present.
+                    Self::extrapolate_file_record(
+                        corpus_path,
+                        file_name,
+                        ref_name,
+                        0,
+                        false,
+                        types,
+                        records,
+                    )?;
+                }
+                Token::Atom(_word) => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Removes the file with a specified path from the corpus and returns whether it was found.
+    ///
+    /// Types that were referenced only by the removed file are kept in the corpus but they are no
+    /// longer reachable from any export.
+    pub fn remove_file(&mut self, path: &Path) -> bool {
+        match self.files.iter().position(|symfile| symfile.path == path) {
+            Some(file_idx) => {
+                self.files.remove(file_idx);
+                self.reindex();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Rebuilds the mapping of exports to their files.
+    ///
+    /// The `exports` map refers to files by their index in `files`, which becomes stale when a file
+    /// is removed or the files are reordered. This function must be called after any such
+    /// structural change.
+    pub fn reindex(&mut self) {
+        self.exports.clear();
+        for (file_idx, symfile) in self.files.iter().enumerate() {
+            for name in symfile.records.keys() {
+                if Self::is_export(name) {
+                    self.exports.insert(name.clone(), file_idx);
+                }
+            }
+        }
+    }
+
+    /// Merges all types, exports and files of another corpus into this one.
+    ///
+    /// Type variants with identical tokens are unified, in the same way as when loading multiple
+    /// files. A file which is present in both corpuses is kept only once if its records are the
+    /// same in both, otherwise the merge fails. Exports declared in different files are reported as
+    /// a conflict. The corpus can be left partially merged if an error is returned.
+    pub fn merge(&mut self, other: SymCorpus) -> Result<(), crate::Error> {
+        self.merge_with(other, &LoadOptions::default())
+    }
+
+    /// Merges all types, exports and files of another corpus into this one, using the given
+    /// options.
+    ///
+    /// The merge is done as described for [`SymCorpus::merge`], except that
+    /// [`LoadOptions::allow_duplicate_exports`] permits an export to be declared in different files
+    /// and [`LoadOptions::merge_file_records`] combines the records of a file which is present in
+    /// both corpuses, as long as they don't reference different variants of the same type.
+    pub fn merge_with(
+        &mut self,
+        other: SymCorpus,
+        options: &LoadOptions,
+    ) -> Result<(), crate::Error> {
+        let load_context = ParallelLoadContext {
+            types: Mutex::new(&mut self.types),
+            exports: Mutex::new(&mut self.exports),
+            files: Mutex::new(&mut self.files),
+            strings: Mutex::new(&mut self.strings),
+            raw_bodies: Mutex::new(&mut self.raw_bodies),
+            allow_duplicate_exports: options.allow_duplicate_exports,
+            merge_file_records: options.merge_file_records,
+        };
+
+        // Merge the original text of the type variants, if retained.
+        for (name, raw_bodies) in other.raw_bodies {
+            let mut all_raw_bodies = load_context.raw_bodies.lock().unwrap();
+            let merged_raw_bodies = all_raw_bodies.entry(name).or_default();
+            for (tokens, raw_body) in raw_bodies {
+                merged_raw_bodies.entry(tokens).or_insert(raw_body);
+            }
+        }
+
+        // Merge all type variants and record how their indices got remapped.
+        let mut remap = HashMap::new();
+        for (name, variants) in other.types {
+            let variant_map = variants
+                .into_iter()
+                .map(|tokens| Self::merge_type(&name, tokens, &load_context))
+                .collect::<Vec<_>>();
+            remap.insert(name, variant_map);
+        }
+
+        // Add the files, with their records pointing to the merged variants. Track the file index
+        // of each path to find files which are present in both corpuses.
+        let mut file_indices = load_context
+            .files
+            .lock()
+            .unwrap()
+            .iter()
+            .enumerate()
+            .map(|(file_idx, symfile)| (symfile.path.clone(), file_idx))
+            .collect::<HashMap<_, _>>();
+        for symfile in other.files {
+            let records = symfile
+                .records
+                .iter()
+                .map(|(name, &variant_idx)| (name.clone(), remap[name][variant_idx]))
+                .collect::<FileRecords>();
+
+            let file_idx = match file_indices.get(&symfile.path) {
+                Some(&file_idx) if load_context.merge_file_records => {
+                    let files = load_context.files.lock().unwrap();
+                    for (name, variant_idx) in &records {
+                        match files[file_idx].records.get(name) {
+                            Some(other_variant_idx) if other_variant_idx != variant_idx => {
+                                return Err(crate::Error::new_parse(&format!(
+                                    "Type {} has a different variant in file '{}' of both corpuses",
+                                    name,
+                                    symfile.path.display()
+                                )));
+                            }
+                            _ => {}
+                        }
+                    }
+                    file_idx
+                }
+                Some(&file_idx) => {
+                    if load_context.files.lock().unwrap()[file_idx].records != records {
+                        return Err(crate::Error::new_parse(&format!(
+                            "File '{}' is present in both corpuses with different records",
+                            symfile.path.display()
+                        )));
+                    }
+                    continue;
+                }
+                None => {
+                    let mut files = load_context.files.lock().unwrap();
+                    files.push(SymFile {
+                        path: symfile.path.clone(),
+                        records: FileRecords::new(),
+                    });
+                    file_indices.insert(symfile.path, files.len() - 1);
+                    files.len() - 1
+                }
+            };
+
+            let mut file_records =
+                mem::take(&mut load_context.files.lock().unwrap()[file_idx].records);
+            for (name, variant_idx) in records {
+                Self::insert_record(
+                    &name,
+                    variant_idx,
+                    file_idx,
+                    &mut file_records,
+                    &load_context,
+                )?;
+            }
+            load_context.files.lock().unwrap()[file_idx].records = file_records;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the number of files in the corpus.
+    pub fn num_files(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Returns the number of exported symbols in the corpus.
+    pub fn num_exports(&self) -> usize {
+        self.exports.len()
+    }
+
+    /// Returns statistics describing how much the corpus gets deduplicated when it is written in
+    /// the consolidated form.
+    ///
+    /// The counts include orphan types, which are not reachable from any export.
+    pub fn consolidate_stats(&self) -> ConsolidateStats {
+        ConsolidateStats {
+            num_types: self.types.len(),
+            num_variants: self.types.values().map(Vec::len).sum(),
+            num_multi_variant_types: self
+                .types
+                .values()
+                .filter(|variants| variants.len() > 1)
+                .count(),
+            num_input_records: self.files.iter().map(|file| file.records.len()).sum(),
+        }
+    }
+
+    /// Returns the names of all exported symbols in the corpus, sorted by name.
+    pub fn export_names(&self) -> impl Iterator<Item = &str> {
+        let mut names = self.exports.keys().map(String::as_str).collect::<Vec<_>>();
+        names.sort();
+        names.into_iter()
+    }
+
+    /// Returns the names of all types in the corpus, including exports, sorted by name.
+    pub fn type_names(&self) -> impl Iterator<Item = &str> {
+        let mut names = self.types.keys().map(String::as_str).collect::<Vec<_>>();
+        names.sort();
+        names.into_iter()
+    }
+
+    /// Finds groups of differently named types with the same declaration.
+    ///
+    /// Only types of a known [`TypeKind`] with a single variant are considered. The name of a type
+    /// is ignored where it is declared, so `struct foo { int a ; }` and `struct bar { int a ; }`
+    /// are duplicates. Returns the groups with at least two types, each sorted by name, and the
+    /// groups sorted by their first name.
+    pub fn find_duplicate_types(&self) -> Vec<Vec<String>> {
+        let mut groups: HashMap<Vec<Option<&Token>>, Vec<String>> = HashMap::new();
+        for (name, variants) in &self.types {
+            let kind = match TypeKind::of(name) {
+                Some(kind) if variants.len() == 1 => kind,
+                _ => continue,
+            };
+            let tokens = &variants[0];
+            let name_idx = Self::find_declared_name(kind, &name[2..], tokens);
+            let body = tokens
+                .iter()
+                .enumerate()
+                .map(|(i, token)| {
+                    if Some(i) == name_idx {
+                        None
+                    } else {
+                        Some(token)
+                    }
+                })
+                .collect();
+            groups.entry(body).or_default().push(name.clone());
+        }
+
+        let mut duplicates = groups
+            .into_values()
+            .filter(|names| names.len() > 1)
+            .map(|mut names| {
+                names.sort();
+                names
+            })
+            .collect::<Vec<_>>();
+        duplicates.sort();
+        duplicates
+    }
+
+    /// Returns the index of the token which declares the name `short_name` of a type of a specified
+    /// kind, or [`None`] if it is not found.
+    ///
+    /// The name of a structure, union or enumeration follows its keyword. The name of a typedef is
+    /// its declarator, which is the first occurrence of the name outside of any braces.
+    fn find_declared_name(kind: TypeKind, short_name: &str, tokens: &Tokens) -> Option<usize> {
+        let is_name = |token: &Token| matches!(token, Token::Atom(word) if &**word == short_name);
+        match kind {
+            TypeKind::Enum | TypeKind::Struct | TypeKind::Union => {
+                tokens.get(1).filter(|token| is_name(token)).map(|_| 1)
+            }
+            TypeKind::Typedef => {
+                let mut depth = 0;
+                for (i, token) in tokens.iter().enumerate() {
+                    match token.as_str() {
+                        "{" => depth += 1,
+                        "}" => depth -= 1,
+                        _ if depth == 0 && is_name(token) => return Some(i),
+                        _ => {}
+                    }
+                }
+                None
+            }
+        }
+    }
+
+    /// Compares the exported symbols of the corpus with another corpus, without comparing their
+    /// types, and returns the names of the removed exports and the names of the added exports.
+    ///
+    /// An export is removed if it is present only in this corpus and added if it is present only in
+    /// the other one. Both lists are sorted by name.
+    pub fn export_diff<'a>(&'a self, other: &'a SymCorpus) -> (Vec<&'a str>, Vec<&'a str>) {
+        let removed = self
+            .export_names()
+            .filter(|name| !other.exports.contains_key(*name))
+            .collect();
+        let added = other
+            .export_names()
+            .filter(|name| !self.exports.contains_key(*name))
+            .collect();
+        (removed, added)
+    }
+
+    /// Computes a hash of an exported symbol over the closure of all types that it references.
+    ///
+    /// The tokens of the export are hashed in order and each referenced type is expanded in place
+    /// of its first reference, so the hash doesn't depend on the order of loading or on the number
+    /// of workers. It changes only if the declaration of the export or of some type reachable from
+    /// it changes. Returns `None` if the symbol is not exported or if its closure references an
+    /// unknown type.
+    pub fn symbol_hash(&self, name: &str) -> Option<u64> {
+        let symfile = &self.files[*self.exports.get(name)?];
+        let mut hash = Fnv1aHasher::new();
+        hash.write(name.as_bytes());
+        let mut processed = HashSet::from([name]);
+        self.hash_type(symfile, name, 0, &mut processed, &mut hash)?;
+        Some(hash.finish())
+    }
+
+    /// Feeds the tokens of a given type into a hash, expanding recursively all types that it
+    /// references for the first time.
+    fn hash_type<'a>(
+        &'a self,
+        symfile: &SymFile,
+        name: &str,
+        depth: usize,
+        processed: &mut HashSet<&'a str>,
+        hash: &mut Fnv1aHasher,
+    ) -> Option<()> {
+        if depth > DEFAULT_MAX_TYPE_DEPTH {
+            return None;
+        }
+        let variant_idx = *symfile.records.get(name)?;
+        for token in self.types.get(name)?.get(variant_idx)? {
+            // Separate the tokens so that their boundaries are part of the hash.
+            hash.write(&[0]);
+            hash.write(token.as_str().as_bytes());
+            if let Token::TypeRef(ref_name) = token {
+                if processed.insert(ref_name) {
+                    self.hash_type(symfile, ref_name, depth + 1, processed, hash)?;
+                }
+            }
+        }
+        Some(())
+    }
+
+    /// Compares the hashes of exports present in both corpuses, see [`SymCorpus::symbol_hash`].
+    ///
+    /// Returns the names of exports whose hash differs, sorted by name, together with the hashes
+    /// in the corpus and in the other one.
+    pub fn compare_hashes(&self, other: &SymCorpus) -> Vec<(&str, Option<u64>, Option<u64>)> {
+        self.export_names()
+            .filter(|name| other.contains_export(name))
+            .filter_map(|name| {
+                let hash = self.symbol_hash(name);
+                let other_hash = other.symbol_hash(name);
+                (hash != other_hash).then_some((name, hash, other_hash))
+            })
+            .collect()
+    }
+
+    /// Returns the names of all exported symbols in the corpus which match a glob pattern, sorted
+    /// by name, see [`crate::glob::matches`].
+    pub fn export_names_matching<'a>(&'a self, pattern: &'a str) -> impl Iterator<Item = &'a str> {
+        self.export_names()
+            .filter(move |name| crate::glob::matches(pattern, name))
+    }
+
+    /// Returns the names of all types in the corpus, including exports, which match a glob
+    /// pattern, sorted by name.
+    pub fn type_names_matching<'a>(&'a self, pattern: &'a str) -> impl Iterator<Item = &'a str> {
+        self.type_names()
+            .filter(move |name| crate::glob::matches(pattern, name))
+    }
+
+    /// Returns whether the corpus contains a specified exported symbol.
+    pub fn contains_export(&self, name: &str) -> bool {
+        self.exports.contains_key(name)
+    }
+
+    /// Returns the definition of a specified type or export as a string of its space-separated
+    /// tokens, as it appears in a symtypes record after the name.
+    ///
+    /// Returns [`None`] if the type is not known, or if it has multiple variants in the corpus and
+    /// its definition is therefore ambiguous.
+    pub fn type_definition(&self, name: &str) -> Option<String> {
+        match self.types.get(name)?.as_slice() {
+            [tokens] => Some(
+                tokens
+                    .iter()
+                    .map(Token::as_str)
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            ),
+            _ => None,
+        }
+    }
+
+    /// Returns the pretty-formatted declaration of a specified type or export as a [`Vec`] of
+    /// indented [`String`] lines.
+    ///
+    /// Returns [`None`] if the type is not known or if it has multiple variants in the corpus.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ksymtypes::sym::SymCorpus;
+    ///
+    /// let syms = SymCorpus::try_from(concat!(
+    ///     "s#foo struct foo { int a ; long b ; }\n",
+    ///     "bar int bar ( s#foo )\n",
+    /// ))?;
+    /// assert_eq!(
+    ///     syms.pretty_print("s#foo").unwrap(),
+    ///     vec!["struct foo {", "\tint a;", "\tlong b;", "}"]
+    /// );
+    /// # Ok::<(), ksymtypes::Error>(())
+    /// ```
+    pub fn pretty_print(&self, name: &str) -> Option<Vec<String>> {
+        match self.types.get(name)?.as_slice() {
+            [tokens] => Some(pretty_format_type(tokens)),
+            _ => None,
+        }
+    }
+
+    /// Returns the number of distinct type names in the corpus, including exports.
+    pub fn num_types(&self) -> usize {
+        self.types.len()
+    }
+
+    /// Returns the total number of type variants in the corpus.
+    pub fn num_type_variants(&self) -> usize {
+        self.types.values().map(|variants| variants.len()).sum()
+    }
+
+    /// Returns all types which have more than one variant in the corpus, together with their
+    /// variant count.
+    ///
+    /// The result is sorted by the variant count in descending order, types with the same count
+    /// are sorted by their name.
+    pub fn multi_variant_types(&self) -> Vec<(&str, usize)> {
+        let mut res = self
+            .types
+            .iter()
+            .filter(|(_, variants)| variants.len() > 1)
+            .map(|(name, variants)| (name.as_str(), variants.len()))
+            .collect::<Vec<_>>();
+        res.sort_by(|(name1, count1), (name2, count2)| {
+            count2.cmp(count1).then_with(|| name1.cmp(name2))
+        });
+        res
+    }
+
+    /// Runs integrity checks of the corpus and returns the found problems.
+    ///
+    /// The following checks are performed, each reporting diagnostics with its own code:
+    ///
+    /// * `EMPTY_FILE` - a file has no records (warning),
+    /// * `ORPHAN_TYPE` - a type variant is not used by any file (warning),
+    /// * `KIND_MISMATCH` - a type declaration doesn't start with the keyword of its kind, for
+    ///   instance a `s#foo` type which isn't declared as a `struct` (error),
+    /// * `DUPLICATE_EXPORT` - an export is declared in multiple files (error),
+    /// * `DANGLING_REF` - a record references a type which is not known in its file (error).
+    ///
+    /// The diagnostics are sorted by their file, with diagnostics not specific to a file first, and
+    /// then by their code and message.
+    pub fn check(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+
+        let mut used_variants = HashSet::new();
+        let mut export_files: HashMap<&str, Vec<&Path>> = HashMap::new();
+        for symfile in &self.files {
+            if symfile.records.is_empty() {
+                diagnostics.push(Diagnostic::new(
+                    Severity::Warning,
+                    "EMPTY_FILE",
+                    "File has no records".to_string(),
+                    Some(&symfile.path),
+                ));
+            }
+
+            for (name, &variant_idx) in &symfile.records {
+                used_variants.insert((name.as_str(), variant_idx));
+                if Self::is_export(name) {
+                    export_files
+                        .entry(name.as_str())
+                        .or_default()
+                        .push(&symfile.path);
+                }
+            }
+
+            for (name, ref_name) in self.find_dangling_refs(symfile) {
+                diagnostics.push(Diagnostic::new(
+                    Severity::Error,
+                    "DANGLING_REF",
+                    format!("Type {} is not known, referenced by {}", ref_name, name),
+                    Some(&symfile.path),
+                ));
+            }
+        }
+
+        for (name, variants) in &self.types {
+            let keyword = match TypeKind::of(name) {
+                Some(TypeKind::Enum) => Some("enum"),
+                Some(TypeKind::Struct) => Some("struct"),
+                Some(TypeKind::Typedef) => Some("typedef"),
+                Some(TypeKind::Union) => Some("union"),
+                None => None,
+            };
+            for (variant_idx, tokens) in variants.iter().enumerate() {
+                if !used_variants.contains(&(name.as_str(), variant_idx)) {
+                    diagnostics.push(Diagnostic::new(
+                        Severity::Warning,
+                        "ORPHAN_TYPE",
+                        format!(
+                            "Variant {} of type {} is not used by any file",
+                            variant_idx, name
+                        ),
+                        None,
+                    ));
+                }
+                if let Some(keyword) = keyword {
+                    let first = tokens.first().map(|token| token.as_str()).unwrap_or("");
+                    if first != keyword {
+                        diagnostics.push(Diagnostic::new(
+                            Severity::Error,
+                            "KIND_MISMATCH",
+                            format!(
+                                "Type {} is declared as '{}' instead of '{}'",
+                                name, first, keyword
+                            ),
+                            None,
+                        ));
+                    }
+                }
+            }
+        }
+
+        for (name, mut paths) in export_files {
+            paths.sort();
+            for path in &paths[1..] {
+                diagnostics.push(Diagnostic::new(
+                    Severity::Error,
+                    "DUPLICATE_EXPORT",
+                    format!(
+                        "Export {} is already declared in '{}'",
+                        name,
+                        paths[0].display()
+                    ),
+                    Some(path),
+                ));
+            }
+        }
+
+        diagnostics
+            .sort_by(|a, b| (&a.file, a.code, &a.message).cmp(&(&b.file, b.code, &b.message)));
+        diagnostics
+    }
+
+    /// Finds references to types which are not known in a given file and returns them as pairs of
+    /// the referencing record and the referenced type name.
+    fn find_dangling_refs<'a>(&'a self, symfile: &'a SymFile) -> Vec<(&'a str, &'a str)> {
+        let mut dangling_refs = Vec::new();
+        for name in symfile.records.keys() {
+            let tokens = match Self::find_type_tokens(self, symfile, name) {
+                Some(tokens) => tokens,
+                None => continue,
+            };
+            for token in tokens {
+                if let Token::TypeRef(ref_name) = token {
+                    if !symfile.records.contains_key(&**ref_name) {
+                        dangling_refs.push((name.as_str(), &**ref_name));
+                    }
+                }
+            }
+        }
+        dangling_refs
+    }
+
+    /// Validates that all type references in the corpus resolve to known types.
+    ///
+    /// Each file is checked that the types referenced by its records are known in the file. An
+    /// error listing all unresolved references together with their files is returned otherwise.
+    pub fn validate(&self) -> Result<(), crate::Error> {
+        let mut problems = Vec::new();
+        for symfile in &self.files {
+            for (name, ref_name) in self.find_dangling_refs(symfile) {
+                problems.push(format!(
+                    "{}: Type {} is not known, referenced by {}",
+                    symfile.path.display(),
+                    ref_name,
+                    name
+                ));
+            }
+        }
+        if problems.is_empty() {
+            return Ok(());
+        }
+
+        problems.sort();
+        Err(crate::Error::new_parse(&format!(
+            "Found {} unresolved type reference(s):\n{}",
+            problems.len(),
+            problems.join("\n")
+        )))
+    }
+
+    /// Returns the longest chain of type references from any export to a leaf type.
+    ///
+    /// The chain starts with the export and each next element is a type referenced by the previous
+    /// one. References which would close a cycle and references to unknown types are not followed.
+    /// Among chains of the same length, the first one found in the files sorted by path and their
+    /// exports sorted by name is returned. An empty chain is returned if the corpus has no exports.
+    /// A chain longer than [`DEFAULT_MAX_TYPE_DEPTH`] is reported as an error.
+    pub fn max_depth_chain(&self) -> Result<Vec<&str>, crate::Error> {
+        let mut files = self.files.iter().collect::<Vec<_>>();
+        files.sort_by_key(|symfile| &symfile.path);
+
+        let mut best_chain = Vec::new();
+        for symfile in files {
+            let mut exports = symfile
+                .records
+                .keys()
+                .filter(|name| Self::is_export(name))
+                .map(|name| name.as_str())
+                .collect::<Vec<_>>();
+            exports.sort();
+
+            // Find the depth of each export. The depth of types which don't reach any cycle is
+            // memoized for all exports in the file, the depth of other types depends on the
+            // references which are being followed and is memoized only for the current export.
+            let mut memo = HashMap::new();
+            for name in exports {
+                let mut walk_memo = HashMap::new();
+                let (depth, _) =
+                    self.type_depth(symfile, name, &mut Vec::new(), &mut memo, &mut walk_memo)?;
+                if depth <= best_chain.len() {
+                    continue;
+                }
+
+                // Reconstruct the chain from the recorded deepest references.
+                best_chain.clear();
+                let mut next = Some(name);
+                while let Some(type_name) = next {
+                    best_chain.push(type_name);
+                    next = walk_memo
+                        .get(type_name)
+                        .or_else(|| memo.get(type_name))
+                        .and_then(|&(_, next)| next);
+                }
+            }
+        }
+        Ok(best_chain)
+    }
+
+    /// Returns the number of types in the longest chain of references starting from a specified
+    /// type in a given file, and whether the chain was cut short by skipping a reference which
+    /// would close a cycle.
+    ///
+    /// Types in `chain` are currently being processed and references to them are skipped. The
+    /// depth of each processed type and its deepest reference are recorded in `memo` if the result
+    /// is complete, or in `walk_memo` if it was cut short and so is valid only for the current
+    /// walk.
+    fn type_depth<'a>(
+        &'a self,
+        symfile: &SymFile,
+        name: &'a str,
+        chain: &mut Vec<&'a str>,
+        memo: &mut HashMap<&'a str, (usize, Option<&'a str>)>,
+        walk_memo: &mut HashMap<&'a str, (usize, Option<&'a str>)>,
+    ) -> Result<(usize, bool), crate::Error> {
+        if let Some(&(depth, _)) = memo.get(name) {
+            return Ok((depth, false));
+        }
+        if let Some(&(depth, _)) = walk_memo.get(name) {
+            return Ok((depth, true));
+        }
+        if chain.contains(&name) {
+            return Ok((0, true));
+        }
+        let tokens = match Self::find_type_tokens(self, symfile, name) {
+            Some(tokens) => tokens,
+            None => return Ok((0, false)),
+        };
+        Self::check_type_depth(symfile, chain, name, None)?;
+
+        chain.push(name);
+        let mut deepest = (0, None);
+        let mut is_cut = false;
+        for token in tokens {
+            if let Token::TypeRef(ref_name) = token {
+                let (depth, is_ref_cut) =
+                    self.type_depth(symfile, ref_name, chain, memo, walk_memo)?;
+                is_cut |= is_ref_cut;
+                if depth > deepest.0 {
+                    deepest = (depth, Some(&**ref_name));
+                }
+            }
+        }
+        chain.pop();
+
+        let depth = deepest.0 + 1;
+        if is_cut {
+            walk_memo.insert(name, (depth, deepest.1));
+        } else {
+            memo.insert(name, (depth, deepest.1));
+        }
+        Ok((depth, is_cut))
+    }
+
+    /// Checks that no type in the corpus has more than `max_variants` variants.
+    ///
+    /// An error listing the types with the most variants is returned if the limit is exceeded.
+    pub fn check_max_variants(&self, max_variants: usize) -> Result<(), crate::Error> {
+        const MAX_REPORTED: usize = 10;
+
+        let offenders = self
+            .multi_variant_types()
+            .into_iter()
+            .filter(|&(_, count)| count > max_variants)
+            .collect::<Vec<_>>();
+        if offenders.is_empty() {
+            return Ok(());
+        }
+
+        let mut desc = format!(
+            "{} type(s) exceed the limit of {} variant(s): ",
+            offenders.len(),
+            max_variants
+        );
+        for (i, (name, count)) in offenders.iter().take(MAX_REPORTED).enumerate() {
+            if i > 0 {
+                desc.push_str(", ");
+            }
+            desc.push_str(&format!("{} ({})", name, count));
+        }
+        if offenders.len() > MAX_REPORTED {
+            desc.push_str(", ...");
+        }
+        Err(crate::Error::new_parse(&desc))
+    }
+
+    /// Checks that every type in the corpus has a single variant, meaning that it is declared the
+    /// same way by all files.
+    ///
+    /// Unlike [`SymCorpus::check_max_variants`], the returned error lists all types with multiple
+    /// variants, sorted by name.
+    pub fn check_single_variant(&self) -> Result<(), crate::Error> {
+        let mut names = self
+            .multi_variant_types()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+        if names.is_empty() {
+            return Ok(());
+        }
+        names.sort();
+        Err(crate::Error::new_parse(&format!(
+            "{} type(s) have multiple variants: {}",
+            names.len(),
+            names.join(", ")
+        )))
+    }
+
+    fn are_tokens_eq(a: &Tokens, b: &Tokens) -> bool {
+        if a.len() != b.len() {
+            return false;
+        }
+        for i in 0..a.len() {
+            if a[i] != b[i] {
+                return false;
+            };
+        }
+        return true;
+    }
+
+    /// Checks whether a given name refers to a type, which is in form `<kind>#<name>`, for
+    /// instance `s#foo`.
+    ///
+    /// The kind must be a single ASCII letter and the name must not be empty. Any other name,
+    /// including short ones such as `_` or ones starting with punctuation such as `.foo` or `$bar`,
+    /// is not a type name.
+    fn is_type_name(name: &str) -> bool {
+        let bytes = name.as_bytes();
+        bytes.len() > 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b'#'
+    }
+
+    /// Checks whether a given name refers to an exported symbol, which is any name that is not
+    /// a type name.
+    ///
+    /// Symbol names are used verbatim. Kernel symbols are C identifiers, and no demangling is
+    /// attempted for other names that can appear in out-of-tree modules.
+    fn is_export(name: &str) -> bool {
+        !Self::is_type_name(name)
+    }
+
+    /// Processes a single symbol specified in a given file and adds it to the consolidated output.
+    ///
+    /// The specified symbol is added to `output_types` and `processed_types`, if not already
+    /// present, and all its type references get recursively processed in the same way. The
+    /// `output_types` is a [`HashMap`] which records all symbols which should appear on the output
+    /// together with a mapping from the internal symbol variant index to the output variant index.
+    /// The `processed_types` is a [`HashMap`] which tracks all symbols in the current file and
+    /// their output variant indices.
+    fn consolidate_type<'a>(
+        &'a self,
+        walk: &mut ConsolidateWalk<'a, '_>,
+        name: &'a str,
+    ) -> Result<(), crate::Error> {
+        let symfile = walk.symfile;
+        let options = walk.options;
+
+        // See if the symbol was already processed.
+        let processed_entry = match walk.processed_types.entry(name) {
+            Occupied(_) => return Ok(()),
+            Vacant(processed_entry) => processed_entry,
+        };
+
+        // Look up the internal variant index and the type declaration. In the lenient mode, an
+        // unknown type is reported and skipped.
+        let variant_idx = match symfile.records.get(name) {
+            Some(&variant_idx) => variant_idx,
+            None if options.lenient => {
+                Self::warn_dangling_ref(symfile, &walk.chain, name, &options.diagnostic_callback);
+                return Ok(());
+            }
+            None => return Err(Self::new_unknown_type_error(symfile, name)),
+        };
+
+        let variants = match self.types.get(name) {
+            Some(variants) => variants,
+            None if options.lenient => {
+                Self::warn_dangling_ref(symfile, &walk.chain, name, &options.diagnostic_callback);
+                return Ok(());
+            }
+            None => return Err(Self::new_missing_declaration_error(symfile, name)),
+        };
+
+        // Determine the output variant index for the symbol.
+        let remap_idx;
+        match walk.output_types.entry(name) {
+            Occupied(mut active_entry) => {
+                let remap = active_entry.get_mut();
+                let remap_len = remap.len();
+                match remap.entry(variant_idx) {
+                    Occupied(remap_entry) => {
+                        remap_idx = *remap_entry.get();
+                    }
+                    Vacant(remap_entry) => {
+                        remap_idx = remap_len;
+                        remap_entry.insert(remap_idx);
+                    }
+                }
+            }
+            Vacant(active_entry) => {
+                remap_idx = 0;
+                active_entry.insert(HashMap::from([(variant_idx, remap_idx)]));
+                walk.output_order.push(name);
+            }
+        };
+        processed_entry.insert(remap_idx);
+
+        // Process recursively all types that the symbol references.
+        Self::check_type_depth(symfile, &walk.chain, name, walk.options.max_type_depth)?;
+        walk.chain.push(name);
+        for token in &variants[variant_idx] {
+            match token {
+                Token::TypeRef(ref_name) => self.consolidate_type(walk, ref_name)?,
+                Token::Atom(_word) => {}
+            }
+        }
+        walk.chain.pop();
+        Ok(())
+    }
+
+    /// Creates an error reporting that a type `name` is not known in a given file.
+    fn new_unknown_type_error(symfile: &SymFile, name: &str) -> crate::Error {
+        crate::Error::new_parse(&format!(
+            "Type {} is not known in file '{}'",
+            name,
+            symfile.path.display()
+        ))
+    }
+
+    /// Creates an error reporting that a type `name` used by a given file has no declaration in
+    /// the corpus.
+    fn new_missing_declaration_error(symfile: &SymFile, name: &str) -> crate::Error {
+        crate::Error::new_parse(&format!(
+            "Type {} has a missing declaration, used by file '{}'",
+            name,
+            symfile.path.display()
+        ))
+    }
+
+    /// Reports a reference to an unknown type `name` in a given file, reached through the `chain`
+    /// of type references from an export, as a `DANGLING_REF` warning diagnostic.
+    ///
+    /// The diagnostic is passed to the `callback`, or logged if it is not set.
+    fn warn_dangling_ref(
+        symfile: &SymFile,
+        chain: &[&str],
+        name: &str,
+        callback: &Option<DiagnosticCallback>,
+    ) {
+        let diagnostic = Diagnostic::new(
+            Severity::Warning,
+            "DANGLING_REF",
+            format!(
+                "Type {} is not known, referenced via {}",
+                name,
+                chain.join(" -> ")
+            ),
+            Some(&symfile.path),
+        );
+        match callback {
+            Some(callback) => callback(&diagnostic),
+            None => warn!("{}", diagnostic),
+        }
+    }
+
+    /// Returns a path of a file as written in its `F#` record.
+    ///
+    /// The path is separated by forward slashes, regardless of the platform, so the consolidated
+    /// output is portable.
+    fn normalize_record_path(path: &Path) -> String {
+        path.to_string_lossy().replace('\\', "/")
+    }
+
+    /// Checks that the type `name`, reached through the `chain` of type references, doesn't exceed
+    /// the maximum nesting depth, [`DEFAULT_MAX_TYPE_DEPTH`] if not specified.
+    fn check_type_depth(
+        symfile: &SymFile,
+        chain: &[&str],
+        name: &str,
+        max_depth: Option<usize>,
+    ) -> Result<(), crate::Error> {
+        let max_depth = max_depth.unwrap_or(DEFAULT_MAX_TYPE_DEPTH);
+        if chain.len() < max_depth {
+            return Ok(());
+        }
+        Err(crate::Error::new_parse(&format!(
+            "{}: Type {} exceeds the maximum nesting depth of {}, referenced via {} -> ...",
+            symfile.path.display(),
+            name,
+            max_depth,
+            chain[..min(chain.len(), 3)].join(" -> ")
+        )))
+    }
+
+    /// Writes the corpus in the consolidated form into a specified file.
+    pub fn write_consolidated_file(
+        &self,
+        filename: &str,
+        options: &ConsolidateOptions,
+    ) -> Result<Vec<ConsolidatedFile>, crate::Error> {
+        // Open the output file.
+        let path = Path::new(filename);
+        let file: Box<dyn Write> = if filename == "-" {
+            Box::new(io::stdout())
+        } else {
+            match File::create(path) {
+                Ok(file) => Box::new(file),
+                Err(err) => {
+                    return Err(crate::Error::new_io(
+                        &format!("Failed to create file '{}'", path.display()),
                         err,
                     ))
                 }
-            };
+            }
+        };
+
+        self.write_consolidated_with(file, options)
+    }
+
+    /// Writes the corpus in the consolidated form into a specified writer, using the default
+    /// options.
+    pub fn write_consolidated<W>(&self, writer: W) -> Result<(), crate::Error>
+    where
+        W: io::Write,
+    {
+        self.write_consolidated_with(writer, &ConsolidateOptions::default())?;
+        Ok(())
+    }
+
+    /// Writes the corpus in the consolidated form into a specified writer, using the given
+    /// options.
+    ///
+    /// Returns a description of all written `F#` records, in the order in which they were written.
+    pub fn write_consolidated_with<W>(
+        &self,
+        writer: W,
+        options: &ConsolidateOptions,
+    ) -> Result<Vec<ConsolidatedFile>, crate::Error>
+    where
+        W: io::Write,
+    {
+        let mut writer = BufWriter::new(writer);
+        let err_desc = "Failed to write the consolidated data";
+
+        // Initialize output data. Variable output_types records all output symbols, output_order
+        // the order in which they were first reached, file_types provides per-file information.
+        let mut output_types = HashMap::new();
+        let mut output_order = Vec::new();
+        let mut file_types = vec![HashMap::new(); self.files.len()];
+
+        // Sort all files in the corpus by their path, in the form written in the F# records,
+        // unless the load order should be kept.
+        let record_paths = self
+            .files
+            .iter()
+            .map(|symfile| Self::normalize_record_path(&symfile.path))
+            .collect::<Vec<_>>();
+        let mut file_indices = (0..self.files.len()).collect::<Vec<_>>();
+        if options.sort != SortOrder::None {
+            file_indices.sort_by_key(|&i| &record_paths[i]);
+        }
+
+        // Process the sorted files and add their needed types to the output.
+        for &i in &file_indices {
+            let symfile = &self.files[i];
+
+            // Collect sorted exports in the file which are the roots for consolidation.
+            let mut exports = Vec::new();
+            for (name, _) in &symfile.records {
+                if Self::is_export(name) {
+                    exports.push(name.as_str());
+                }
+            }
+            exports.sort();
+
+            // Add the exported types and their needed types to the output.
+            let mut walk = ConsolidateWalk {
+                symfile,
+                options,
+                chain: Vec::new(),
+                output_types: &mut output_types,
+                output_order: &mut output_order,
+                processed_types: HashMap::new(),
+            };
+            for name in &exports {
+                self.consolidate_type(&mut walk, name)?;
+            }
+            file_types[i] = walk.processed_types;
+        }
+
+        // Add all remaining type variants which are not referenced by any file, if requested. They
+        // are numbered after the referenced variants and don't appear in any F# record.
+        if options.include_orphans {
+            let num_referenced = output_order.len();
+            for (name, variants) in &self.types {
+                let remap = output_types.entry(name.as_str()).or_insert_with(|| {
+                    output_order.push(name.as_str());
+                    HashMap::new()
+                });
+                for variant_idx in 0..variants.len() {
+                    let remap_len = remap.len();
+                    remap.entry(variant_idx).or_insert(remap_len);
+                }
+            }
+            output_order[num_referenced..].sort();
+        }
+
+        // Renumber the output variants by their content, unless they should remain numbered by
+        // their first use.
+        if !options.first_use_numbering {
+            let mut renumbers = HashMap::new();
+            for (&name, remap) in &mut output_types {
+                let variants = self.types.get(name).unwrap();
+                let mut sorted_remap = remap
+                    .iter()
+                    .map(|(&variant_idx, &remap_idx)| (variant_idx, remap_idx))
+                    .collect::<Vec<_>>();
+                sorted_remap.sort_by(|a, b| variants[a.0].cmp(&variants[b.0]));
+
+                let mut renumber = vec![0; sorted_remap.len()];
+                for (new_remap_idx, (variant_idx, remap_idx)) in
+                    sorted_remap.into_iter().enumerate()
+                {
+                    renumber[remap_idx] = new_remap_idx;
+                    remap.insert(variant_idx, new_remap_idx);
+                }
+                renumbers.insert(name, renumber);
+            }
+            for processed_types in &mut file_types {
+                for (name, remap_idx) in processed_types {
+                    *remap_idx = renumbers.get(name).unwrap()[*remap_idx];
+                }
+            }
+        }
+
+        // Go through all files and their output types. Check if a given type has only one variant
+        // in the output and mark it as such, unless all variants are written explicitly.
+        if !options.explicit_variants {
+            for types in &mut file_types {
+                for (name, remap_idx) in types {
+                    let remap = output_types.get(name).unwrap();
+                    if remap.len() == 1 {
+                        *remap_idx = usize::MAX;
+                    }
+                }
+            }
+        }
+
+        // Drop types of the excluded kinds from the output.
+        if !options.exclude_kinds.is_empty() {
+            let is_excluded = |name: &str| {
+                TypeKind::of(name).is_some_and(|kind| options.exclude_kinds.contains(&kind))
+            };
+            output_types.retain(|name, _| !is_excluded(name));
+            for processed_types in &mut file_types {
+                processed_types.retain(|name, _| !is_excluded(name));
+            }
+        }
+
+        // Sort all output types and write them to the specified file.
+        let mut sorted_records = output_types.into_iter().collect::<Vec<_>>();
+        match options.sort {
+            SortOrder::Name => {
+                sorted_records.sort_by_key(|(name, _remap)| (Self::is_export(name), *name));
+            }
+            SortOrder::File => {
+                // Find the first file which references each type. Orphan types come last.
+                let mut first_files = HashMap::new();
+                for (pos, &i) in file_indices.iter().enumerate() {
+                    for &name in file_types[i].keys() {
+                        first_files.entry(name).or_insert(pos);
+                    }
+                }
+                sorted_records.sort_by_key(|(name, _remap)| {
+                    (
+                        first_files.get(name).copied().unwrap_or(usize::MAX),
+                        Self::is_export(name),
+                        *name,
+                    )
+                });
+            }
+            SortOrder::None => {
+                let positions = output_order
+                    .iter()
+                    .enumerate()
+                    .map(|(pos, &name)| (name, pos))
+                    .collect::<HashMap<_, _>>();
+                sorted_records.sort_by_key(|(name, _remap)| positions[name]);
+            }
+        }
+
+        for (name, remap) in sorted_records {
+            let variants = self.types.get(name).unwrap();
+            let mut sorted_remap = remap
+                .iter()
+                .map(|(&variant_idx, &remap_idx)| (remap_idx, variant_idx))
+                .collect::<Vec<_>>();
+            sorted_remap.sort();
+
+            let needs_suffix = options.explicit_variants || sorted_remap.len() > 1;
+            for (remap_idx, variant_idx) in sorted_remap {
+                let tokens = &variants[variant_idx];
+
+                if needs_suffix {
+                    write!(writer, "{}@{}", name, remap_idx)
+                        .map_err(|err| crate::Error::new_io(err_desc, err))?;
+                } else {
+                    write!(writer, "{}", name)
+                        .map_err(|err| crate::Error::new_io(err_desc, err))?;
+                }
+                let maybe_raw_body = if options.preserve_formatting {
+                    self.raw_bodies
+                        .get(name)
+                        .and_then(|raw_bodies| raw_bodies.get(tokens))
+                } else {
+                    None
+                };
+                match maybe_raw_body {
+                    Some(raw_body) => write!(writer, "{}", raw_body)
+                        .map_err(|err| crate::Error::new_io(err_desc, err))?,
+                    None => {
+                        for token in tokens {
+                            write!(writer, " {}", token.as_str())
+                                .map_err(|err| crate::Error::new_io(err_desc, err))?;
+                        }
+                    }
+                }
+                writeln!(writer).map_err(|err| crate::Error::new_io(err_desc, err))?;
+            }
         }
-        Ok(lines)
+
+        // Write file records.
+        for &i in &file_indices {
+            // TODO Sorting, make same as above.
+            let mut sorted_types = file_types[i]
+                .iter()
+                .map(|(&name, &remap_idx)| (Self::is_export(name), name, remap_idx))
+                .collect::<Vec<_>>();
+            sorted_types.sort();
+
+            // Output the F# record in form `F#<filename> <type@variant>... <export>...`. Types with
+            // only one variant in the entire consolidated file can be skipped because they can be
+            // implicitly determined by a reader.
+            let references = sorted_types
+                .iter()
+                .filter_map(|&(_, name, remap_idx)| {
+                    if remap_idx != usize::MAX {
+                        Some(format!("{}@{}", name, remap_idx))
+                    } else if Self::is_export(name) {
+                        Some(name.to_string())
+                    } else {
+                        None
+                    }
+                })
+                .collect::<Vec<_>>();
+            write!(writer, "F#{}", record_paths[i])
+                .map_err(|err| crate::Error::new_io(err_desc, err))?;
+            for (j, reference) in references.iter().enumerate() {
+                // Continue an overly long record on the next line.
+                if let Some(max_len) = options.wrap_file_records {
+                    if j > 0 && j % max_len.max(1) == 0 {
+                        write!(writer, " {}\n ", FILE_RECORD_CONTINUATION)
+                            .map_err(|err| crate::Error::new_io(err_desc, err))?;
+                    }
+                }
+                write!(writer, " {}", reference)
+                    .map_err(|err| crate::Error::new_io(err_desc, err))?;
+            }
+            writeln!(writer).map_err(|err| crate::Error::new_io(err_desc, err))?;
+        }
+
+        writer
+            .flush()
+            .map_err(|err| crate::Error::new_io(err_desc, err))?;
+
+        // Describe the composition of the written file records.
+        let consolidated_files = file_indices
+            .iter()
+            .map(|&i| {
+                let num_exports = file_types[i]
+                    .keys()
+                    .filter(|name| Self::is_export(name))
+                    .count();
+                ConsolidatedFile {
+                    path: PathBuf::from(&record_paths[i]),
+                    num_types: file_types[i].len() - num_exports,
+                    num_exports,
+                }
+            })
+            .collect();
+        Ok(consolidated_files)
     }
 
-    /// Reads words from a given iterator and converts them to a [`Vec`] of [`Token`]s.
-    fn words_into_tokens<'a, I>(words: &mut I) -> Vec<Token>
+    /// Reorders the variants of each type by their token content and updates the file records
+    /// accordingly.
+    ///
+    /// Variant indices are normally assigned in the order in which the variants are first
+    /// encountered, which depends on the order of loading the files. The canonical order is stable
+    /// and makes the indices reproducible for the same input.
+    pub fn canonicalize(&mut self) {
+        let mut renumbers = HashMap::new();
+        for (name, variants) in &mut self.types {
+            let mut order = (0..variants.len()).collect::<Vec<_>>();
+            order.sort_by(|&a, &b| variants[a].cmp(&variants[b]));
+            if order
+                .iter()
+                .enumerate()
+                .all(|(i, &variant_idx)| i == variant_idx)
+            {
+                continue;
+            }
+
+            let mut renumber = vec![0; order.len()];
+            for (new_variant_idx, &variant_idx) in order.iter().enumerate() {
+                renumber[variant_idx] = new_variant_idx;
+            }
+            let mut old_variants = mem::take(variants);
+            *variants = order
+                .iter()
+                .map(|&variant_idx| mem::take(&mut old_variants[variant_idx]))
+                .collect();
+            renumbers.insert(name.as_str(), renumber);
+        }
+
+        for symfile in &mut self.files {
+            for (name, variant_idx) in &mut symfile.records {
+                if let Some(renumber) = renumbers.get(name.as_str()) {
+                    *variant_idx = renumber[*variant_idx];
+                }
+            }
+        }
+    }
+
+    /// Invokes a specified callback for every type variant in the corpus, allowing it to rewrite
+    /// the tokens of the variant in place.
+    ///
+    /// The callback receives the name of the type and its tokens. Variants of a type which become
+    /// identical after the transformation are merged into the first of them, in the same way as
+    /// identical variants are merged when loading files, and the file records are updated
+    /// accordingly.
+    pub fn transform<F>(&mut self, mut f: F)
     where
-        I: Iterator<Item = &'a str>,
+        F: FnMut(&str, &mut Tokens),
     {
-        let mut tokens = Vec::new();
-        for word in words {
-            let mut is_typeref = false;
-            match word.chars().nth(1) {
-                Some(ch) => {
-                    if ch == '#' {
-                        is_typeref = true;
+        let mut renumbers = HashMap::new();
+        for (name, variants) in &mut self.types {
+            for tokens in variants.iter_mut() {
+                f(name, tokens);
+            }
+
+            // Merge variants which became identical.
+            let mut renumber = Vec::with_capacity(variants.len());
+            let mut merged_variants: Vec<Tokens> = Vec::with_capacity(variants.len());
+            for tokens in mem::take(variants) {
+                match merged_variants
+                    .iter()
+                    .position(|merged| Self::are_tokens_eq(merged, &tokens))
+                {
+                    Some(merged_idx) => renumber.push(merged_idx),
+                    None => {
+                        renumber.push(merged_variants.len());
+                        merged_variants.push(tokens);
                     }
                 }
-                None => {}
             }
-            tokens.push(if is_typeref {
-                Token::new_typeref(word)
-            } else {
-                Token::new_atom(word)
-            });
+            if merged_variants.len() != renumber.len() {
+                renumbers.insert(name.as_str(), renumber);
+            }
+            *variants = merged_variants;
+        }
+
+        for symfile in &mut self.files {
+            for (name, variant_idx) in &mut symfile.records {
+                if let Some(renumber) = renumbers.get(name.as_str()) {
+                    *variant_idx = renumber[*variant_idx];
+                }
+            }
         }
-        tokens
     }
 
-    /// Splits a given type name into a tuple of two `&str`, with the first one being the base name
-    /// and the second one containing the variant name/index (or an empty string of no variant was
-    /// present).
-    fn split_type_name(type_name: &str) -> (&str, &str) {
-        match type_name.rfind('@') {
-            Some(i) => (&type_name[..i], &type_name[i + 1..]),
-            None => (type_name, &type_name[type_name.len()..]),
+    /// Writes each file of the corpus as a separate single symtypes file under a specified
+    /// directory.
+    ///
+    /// A file is written to `<dir>/<path>`, where the path is the one recorded for the file in the
+    /// corpus. A root or prefix of an absolute path is dropped. The written file contains all type
+    /// and export records that the file references, without any variant suffixes, with types sorted
+    /// by name first, followed by exports sorted by name. Missing directories are created.
+    pub fn write_split(&self, dir: &Path) -> Result<(), crate::Error> {
+        for symfile in &self.files {
+            let rel_path = symfile
+                .path
+                .components()
+                .filter(|component| matches!(component, std::path::Component::Normal(_)))
+                .collect::<PathBuf>();
+            let path = dir.join(rel_path);
+
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).map_err(|err| {
+                    crate::Error::new_io(
+                        &format!("Failed to create directory '{}'", parent.display()),
+                        err,
+                    )
+                })?;
+            }
+            let file = File::create(&path).map_err(|err| {
+                crate::Error::new_io(&format!("Failed to create file '{}'", path.display()), err)
+            })?;
+            let mut writer = BufWriter::new(file);
+            let err_desc = format!("Failed to write file '{}'", path.display());
+
+            let mut names = symfile.records.keys().collect::<Vec<_>>();
+            names.sort_by_key(|name| (Self::is_export(name), *name));
+
+            for name in names {
+                let tokens = Self::get_type_tokens(self, symfile, name)?;
+                writeln!(writer, "{}", format_record(name, tokens))
+                    .map_err(|err| crate::Error::new_io(&err_desc, err))?;
+            }
+            writer
+                .flush()
+                .map_err(|err| crate::Error::new_io(&err_desc, err))?;
         }
+        Ok(())
     }
 
-    fn merge_type(type_name: &str, tokens: Tokens, load_context: &ParallelLoadContext) -> usize {
-        let mut types = load_context.types.lock().unwrap();
-        // TODO Use .entry()?
-        match types.get_mut(type_name) {
+    /// Writes the records of all files in the corpus as JSON to a specified writer.
+    ///
+    /// The output is an array of objects, one for each file sorted by the path, with the `path` of
+    /// the file and its `records`. Each record is an object with the `name` of a type or an export
+    /// and the index of its `variant` in the corpus. The records include all types which the
+    /// loader determined implicitly for files in a consolidated input. The output is intended for
+    /// debugging.
+    pub fn write_records_json<W>(&self, writer: W) -> Result<(), crate::Error>
+    where
+        W: io::Write,
+    {
+        let mut writer = BufWriter::new(writer);
+        let err_desc = "Failed to write the records";
+
+        let mut files = self.files.iter().collect::<Vec<_>>();
+        files.sort_by_key(|symfile| &symfile.path);
+
+        let output = files
+            .iter()
+            .map(|symfile| {
+                let mut records = symfile.records.iter().collect::<Vec<_>>();
+                records.sort();
+                json!({
+                    "path": symfile.path.to_string_lossy(),
+                    "records": records
+                        .iter()
+                        .map(|(name, variant_idx)| json!({ "name": name, "variant": variant_idx }))
+                        .collect::<Vec<_>>(),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        serde_json::to_writer_pretty(&mut writer, &output)
+            .map_err(|err| crate::Error::new_io(err_desc, err.into()))?;
+        writeln!(writer).map_err(|err| crate::Error::new_io(err_desc, err))?;
+        writer
+            .flush()
+            .map_err(|err| crate::Error::new_io(err_desc, err))
+    }
+
+    /// Returns the corpus in the consolidated form as a [`String`].
+    pub fn to_consolidated_string(&self) -> Result<String, crate::Error> {
+        let mut out = Vec::new();
+        self.write_consolidated(&mut out)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    /// Checks that the consolidated `input` read from `path` is in the canonical form, i.e. that
+    /// loading it and consolidating it again yields the same lines.
+    ///
+    /// Differences in whitespace are ignored because the reader normalizes them. Returns a unified
+    /// diff between the input and the reconsolidated output, which is empty if the input is
+    /// canonical.
+    pub fn verify_consolidated(path: &Path, input: &str) -> Result<Vec<String>, crate::Error> {
+        let mut syms = SymCorpus::new();
+        syms.load_buffer(path, input.as_bytes())?;
+        let output = syms.to_consolidated_string()?;
+
+        let input_lines = input
+            .lines()
+            .map(|line| line.split_ascii_whitespace().collect::<Vec<_>>().join(" "))
+            .collect::<Vec<_>>();
+        let output_lines = output.lines().map(String::from).collect::<Vec<_>>();
+        if input_lines == output_lines {
+            return Ok(Vec::new());
+        }
+        crate::diff::unified(&input_lines, &output_lines)
+    }
+
+    /// Returns the names of a type `name` and all types it references in a given file which are at
+    /// most `max_depth` levels deep, starting from 1 for the type itself.
+    ///
+    /// The types are walked breadth-first so that each type is found at its minimum depth, even if
+    /// it is also reachable through a longer chain of references.
+    fn get_types_within_depth<'a>(
+        &'a self,
+        file: &SymFile,
+        name: &'a str,
+        max_depth: usize,
+    ) -> Result<HashSet<&'a str>, crate::Error> {
+        let mut found = HashSet::new();
+        let mut queue = VecDeque::new();
+        if max_depth > 0 {
+            found.insert(name);
+            queue.push_back((name, 1));
+        }
+        while let Some((type_name, depth)) = queue.pop_front() {
+            if depth == max_depth {
+                continue;
+            }
+            for token in Self::get_type_tokens(self, file, type_name)? {
+                if let Token::TypeRef(ref_name) = token {
+                    if found.insert(&**ref_name) {
+                        queue.push_back((&**ref_name, depth + 1));
+                    }
+                }
+            }
+        }
+        Ok(found)
+    }
+
+    /// Appends pretty-formatted definitions of a type `name` and all types it references in a
+    /// given file to `lines`, with the referenced types coming first.
+    ///
+    /// If `included` is specified, only the types in the set are expanded, others appear only by
+    /// name in the types that reference them.
+    fn format_file_type(
+        &self,
+        file: &SymFile,
+        name: &str,
+        included: Option<&HashSet<&str>>,
+        processed: &mut HashSet<String>,
+        lines: &mut Vec<String>,
+    ) -> Result<(), crate::Error> {
+        if included.is_some_and(|included| !included.contains(name)) {
+            return Ok(());
+        }
+        if !processed.insert(name.to_string()) {
+            return Ok(());
+        }
+
+        let tokens = Self::get_type_tokens(self, file, name)?;
+        for token in tokens {
+            match token {
+                Token::TypeRef(ref_name) => {
+                    self.format_file_type(file, ref_name, included, processed, lines)?;
+                }
+                Token::Atom(_word) => {}
+            }
+        }
+
+        if !lines.is_empty() {
+            lines.push(String::new());
+        }
+        lines.extend(pretty_format_type(tokens));
+        Ok(())
+    }
+
+    /// Returns pretty-formatted definitions of a type `name` and all types it references, as a
+    /// [`Vec`] of [`String`] lines.
+    ///
+    /// The definitions are listed for each file which uses the type, preceded by a line naming the
+    /// file. Files which resolve the type to the same definitions are listed only once. An error is
+    /// returned if the type is not used by any file.
+    pub fn format_type(&self, name: &str) -> Result<Vec<String>, crate::Error> {
+        self.format_type_with(name, None)
+    }
+
+    /// Returns pretty-formatted definitions of a type `name` and the types it references up to
+    /// `max_depth` levels deep, as a [`Vec`] of [`String`] lines.
+    ///
+    /// The type itself is at level 1, so a `max_depth` of 1 shows only its definition. Types beyond
+    /// the limit appear only by name in the definitions which reference them. No limit is applied
+    /// if `max_depth` is [`None`]. See [`SymCorpus::format_type`] for the details.
+    pub fn format_type_with(
+        &self,
+        name: &str,
+        max_depth: Option<usize>,
+    ) -> Result<Vec<String>, crate::Error> {
+        let mut res = Vec::new();
+        let mut seen = HashSet::new();
+        let mut found = false;
+        for file in &self.files {
+            if !file.records.contains_key(name) {
+                continue;
+            }
+            found = true;
+
+            let included = match max_depth {
+                Some(max_depth) => Some(self.get_types_within_depth(file, name, max_depth)?),
+                None => None,
+            };
+            let mut lines = Vec::new();
+            self.format_file_type(
+                file,
+                name,
+                included.as_ref(),
+                &mut HashSet::new(),
+                &mut lines,
+            )?;
+            if !seen.insert(lines.clone()) {
+                continue;
+            }
+
+            if !res.is_empty() {
+                res.push(String::new());
+            }
+            res.push(format!("Found type {} in {}:", name, file.path.display()));
+            res.extend(lines);
+        }
+
+        if !found {
+            return Err(crate::Error::new_parse(&format!(
+                "Type {} is not known",
+                name
+            )));
+        }
+        Ok(res)
+    }
+
+    /// Prints pretty-formatted definitions of a type `name` and all types it references on
+    /// `stdout`. See [`SymCorpus::format_type`] for the details.
+    pub fn print_type(&self, name: &str) -> Result<(), crate::Error> {
+        for line in self.format_type(name)? {
+            println!("{}", line);
+        }
+        Ok(())
+    }
+
+    /// Compares the definitions of a type `name` in the corpus and in another corpus and returns
+    /// the lines of a unified diff of their pretty-formatted tokens.
+    ///
+    /// The definition is taken from the file with the smallest path which uses the type, in each
+    /// corpus. Types that they reference are not compared. Returns [`None`] if the definitions are
+    /// the same or if the type is not used by any file in either corpus.
+    pub fn diff_type(
+        &self,
+        other: &SymCorpus,
+        name: &str,
+    ) -> Result<Option<Vec<String>>, crate::Error> {
+        let (tokens, other_tokens) = match (
+            self.lookup_type_tokens(name)?,
+            other.lookup_type_tokens(name)?,
+        ) {
+            (Some(tokens), Some(other_tokens)) => (tokens, other_tokens),
+            _ => return Ok(None),
+        };
+        if Self::are_tokens_eq(tokens, other_tokens) {
+            return Ok(None);
+        }
+        get_type_diff(tokens, other_tokens, crate::diff::DEFAULT_CONTEXT, false).map(Some)
+    }
+
+    /// Returns the tokens of a type `name` as defined by the file with the smallest path which
+    /// uses it, or [`None`] if no file uses the type.
+    fn lookup_type_tokens(&self, name: &str) -> Result<Option<&Tokens>, crate::Error> {
+        let file = self
+            .files
+            .iter()
+            .filter(|file| file.records.contains_key(name))
+            .min_by_key(|file| &file.path);
+        match file {
+            Some(file) => Self::get_type_tokens(self, file, name).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Creates a new corpus with a specified export and all types that it references.
+    ///
+    /// The new corpus contains a single file, named after the export, with the export and the
+    /// variants of the types that are used by the file of the export in the original corpus.
+    /// Returns [`None`] if the export is not known.
+    pub fn extract_closure(&self, name: &str) -> Option<SymCorpus> {
+        let &file_idx = self.exports.get(name)?;
+        let symfile = &self.files[file_idx];
+
+        let mut closure = SymCorpus::new();
+        let mut records = FileRecords::new();
+        let mut pending = vec![name];
+        while let Some(type_name) = pending.pop() {
+            if records.contains_key(type_name) {
+                continue;
+            }
+            // Skip references to unknown types, they remain dangling in the closure.
+            let tokens = match Self::find_type_tokens(self, symfile, type_name) {
+                Some(tokens) => tokens,
+                None => continue,
+            };
+            for token in tokens {
+                if let Token::TypeRef(ref_name) = token {
+                    pending.push(ref_name);
+                }
+            }
+            closure
+                .types
+                .insert(type_name.to_string(), vec![tokens.clone()]);
+            records.insert(type_name.to_string(), 0);
+        }
+
+        closure.exports.insert(name.to_string(), 0);
+        closure.files.push(SymFile {
+            path: PathBuf::from(name),
+            records,
+        });
+        Some(closure)
+    }
+
+    /// Looks up tokens of a given type in a specific file, returning [`None`] if the type is not
+    /// known.
+    fn find_type_tokens<'a>(
+        symtypes: &'a SymCorpus,
+        file: &SymFile,
+        name: &str,
+    ) -> Option<&'a Tokens> {
+        let &variant_idx = file.records.get(name)?;
+        symtypes
+            .types
+            .get(name)
+            .map(|variants| &variants[variant_idx])
+    }
+
+    /// Looks up tokens of a given type in a specific file, returning an error if the type is not
+    /// known.
+    fn get_type_tokens<'a>(
+        symtypes: &'a SymCorpus,
+        file: &SymFile,
+        name: &str,
+    ) -> Result<&'a Tokens, crate::Error> {
+        match file.records.get(name) {
+            Some(&variant_idx) => match symtypes.types.get(name) {
+                Some(variants) => Ok(&variants[variant_idx]),
+                None => Err(Self::new_missing_declaration_error(file, name)),
+            },
+            None => Err(Self::new_unknown_type_error(file, name)),
+        }
+    }
+
+    fn record_type_change<'a>(
+        name: &'a str,
+        tokens: Cow<'a, Tokens>,
+        other_tokens: Cow<'a, Tokens>,
+        file: &'a SymFile,
+        changes: &Mutex<TypeChanges<'a>>,
+    ) {
+        let mut changes = changes.lock().unwrap();
+        // TODO Rewrite using .entry().
+        match changes.get_mut(name) {
             Some(variants) => {
-                for (i, variant) in variants.iter().enumerate() {
-                    if Self::are_tokens_eq(&tokens, variant) {
-                        return i;
+                for change in &mut *variants {
+                    if Self::are_tokens_eq(&tokens, &change.tokens)
+                        && Self::are_tokens_eq(&other_tokens, &change.other_tokens)
+                    {
+                        change.files.insert(&file.path);
+                        return;
                     }
                 }
-                variants.push(tokens);
-                return variants.len() - 1;
+                variants.push(TypeChange {
+                    tokens,
+                    other_tokens,
+                    files: BTreeSet::from([file.path.as_path()]),
+                });
             }
             None => {
                 let mut variants = Vec::new();
-                variants.push(tokens);
-                types.insert(type_name.to_string(), variants);
-                return 0;
+                variants.push(TypeChange {
+                    tokens,
+                    other_tokens,
+                    files: BTreeSet::from([file.path.as_path()]),
+                });
+                changes.insert(name, variants);
+            }
+        }
+    }
+
+    /// Obtains tokens of a given type in a specific file, prepared for comparison according to
+    /// the specified options.
+    fn get_compare_tokens<'a>(
+        &'a self,
+        file: &SymFile,
+        name: &str,
+        options: &CompareOptions,
+    ) -> Result<Cow<'a, Tokens>, crate::Error> {
+        let tokens = Self::get_type_tokens(self, file, name)?;
+        let tokens = if options.expand_typedefs {
+            self.expand_typedefs(file, tokens, &mut Vec::new(), options.max_type_depth)?
+        } else {
+            Cow::Borrowed(tokens)
+        };
+        let tokens = if options.ptr_array_equiv {
+            normalize_flexible_array(tokens)
+        } else {
+            tokens
+        };
+        if options.ignore_reordering {
+            Ok(normalize_enum_order(tokens))
+        } else {
+            Ok(tokens)
+        }
+    }
+
+    /// Replaces all typedef references in given tokens with the types that the typedefs alias.
+    ///
+    /// Each `t#<name>` reference is substituted with the declaration of the typedef, without the
+    /// `typedef` keyword and the declared name. For instance, `t#u64` declared as
+    /// `typedef unsigned long long u64` is replaced with `unsigned long long`. The substitution is
+    /// applied recursively, so typedefs of typedefs get fully resolved.
+    ///
+    /// The `chain` holds the typedefs which are being expanded. A typedef which refers back to
+    /// itself or a chain longer than `max_depth` is reported as an error.
+    fn expand_typedefs<'a>(
+        &'a self,
+        file: &SymFile,
+        tokens: &'a Tokens,
+        chain: &mut Vec<&'a str>,
+        max_depth: Option<usize>,
+    ) -> Result<Cow<'a, Tokens>, crate::Error> {
+        let has_typedef = tokens.iter().any(|token| match token {
+            Token::TypeRef(ref_name) => ref_name.starts_with("t#"),
+            Token::Atom(_word) => false,
+        });
+        if !has_typedef {
+            return Ok(Cow::Borrowed(tokens));
+        }
+
+        let mut expanded = Tokens::new();
+        for token in tokens {
+            match token {
+                Token::TypeRef(ref_name) if ref_name.starts_with("t#") => {
+                    // Keep an unknown typedef as is, it gets reported when the type is compared.
+                    let typedef_tokens = match Self::find_type_tokens(self, file, ref_name) {
+                        Some(typedef_tokens) => typedef_tokens,
+                        None => {
+                            expanded.push(token.clone());
+                            continue;
+                        }
+                    };
+                    if chain.contains(&&**ref_name) {
+                        return Err(crate::Error::new_parse(&format!(
+                            "{}: Typedef {} references itself via {} -> {}",
+                            file.path.display(),
+                            ref_name,
+                            chain.join(" -> "),
+                            ref_name
+                        )));
+                    }
+                    Self::check_type_depth(file, chain, ref_name, max_depth)?;
+                    chain.push(ref_name);
+                    let typedef_tokens =
+                        self.expand_typedefs(file, typedef_tokens, chain, max_depth)?;
+                    chain.pop();
+
+                    // Skip the leading `typedef` keyword and the last occurrence of the declared
+                    // name.
+                    let typedef_name = &ref_name[2..];
+                    let name_idx = typedef_tokens
+                        .iter()
+                        .rposition(|token| token.as_str() == typedef_name);
+                    for (i, typedef_token) in typedef_tokens.iter().enumerate() {
+                        if (i == 0 && typedef_token.as_str() == "typedef") || Some(i) == name_idx {
+                            continue;
+                        }
+                        expanded.push(typedef_token.clone());
+                    }
+                }
+                _ => expanded.push(token.clone()),
             }
         }
+        Ok(Cow::Owned(expanded))
     }
 
-    /// Inserts the type specified by `base_name@variant_idx` in the file `records` and registers it
-    /// with its `file_idx` in the `load_context.exports` if it is an exported symbol.
-    fn insert_record(
-        base_name: &str,
-        variant_idx: usize,
-        file_idx: usize,
-        records: &mut FileRecords,
-        load_context: &ParallelLoadContext,
+    /// Compares the type `name` with the type `other_name` of the other corpus, and recursively
+    /// all types that they reference, as part of the given walk.
+    fn compare_types<'a>(
+        &'a self,
+        walk: &mut CompareWalk<'a, '_>,
+        name: &'a str,
+        other_name: &str,
     ) -> Result<(), crate::Error> {
-        records.insert(base_name.to_string(), variant_idx);
+        let options = walk.options;
 
-        if Self::is_export(base_name) {
-            // TODO Diagnose duplicates.
-            let mut exports = load_context.exports.lock().unwrap();
-            exports.insert(base_name.to_string(), file_idx);
+        // In the shallow mode, types deeper than those directly referenced by the export are equal
+        // to other types with the same name. This is checked before marking the type as processed
+        // so it still gets compared if it is directly referenced by the export on another path.
+        if options.shallow && walk.chain.len() >= 2 && Self::is_type_name(name) {
+            return Ok(());
         }
 
-        Ok(())
-    }
-
-    /// Processes a single symbol in some file originated from an `F#` record and enhances the
-    /// specified file records with the needed implicit types.
-    ///
-    /// This function is used when reading a consolidated input file and processing its `F#`
-    /// records. Each `F#` record is in form `F#<filename> <type@variant>... <export>...`. It lists
-    /// all types and exports in a given file but is allowed to omit any referenced types which have
-    /// only one variant in the whole consolidated file. The purpose of this function is to find all
-    /// such implicit references and add them to `records`.
-    ///
-    /// A caller of this function should pre-fill `records` with all explicit references given on
-    /// the processed `F#` record and then call this function on each of the references. These root
-    /// calls should be invoked with `is_explicit` set to `true`. The function then recursively adds
-    /// all needed implicit types which are referenced from these roots.
-    fn extrapolate_file_record(
-        corpus_path: &Path,
-        file_name: &str,
-        name: &str,
-        variant_idx: usize,
-        is_explicit: bool,
-        types: &Types,
-        records: &mut FileRecords,
-    ) -> Result<(), crate::Error> {
-        if is_explicit {
-            // All explicit symbols need to be added by the caller.
-            assert!(records.get(name).is_some());
-        } else {
-            // A symbol can be implicit only if it has one variant.
-            assert!(variant_idx == 0);
+        // TODO Take into account different variants?
+        if !walk.processed.insert(name.to_string()) {
+            return Ok(());
+        }
 
-            // See if the symbol was already processed.
-            //
-            // Unfortunately, HashMap in stable Rust doesn't offer to do a lookup using &str but
-            // insert the key as String if it is missing. The code opts to run the lookup again
-            // if the key is missing and the key+value pair needs inserting.
-            // https://stackoverflow.com/questions/51542024/how-do-i-use-the-entry-api-with-an-expensive-key-that-is-only-constructed-if-the
-            if records.get(name).is_some() {
+        // In the lenient mode, check that the type is known in both corpuses, and report and skip
+        // it otherwise.
+        if options.lenient {
+            if Self::find_type_tokens(self, walk.file, name).is_none() {
+                Self::warn_dangling_ref(walk.file, &walk.chain, name, &options.diagnostic_callback);
+                return Ok(());
+            }
+            if Self::find_type_tokens(walk.other, walk.other_file, other_name).is_none() {
+                Self::warn_dangling_ref(
+                    walk.other_file,
+                    &walk.chain,
+                    other_name,
+                    &options.diagnostic_callback,
+                );
                 return Ok(());
             }
-            records.insert(name.to_string(), variant_idx);
         }
 
-        // Obtain tokens for the selected variant and check it is correctly specified.
-        let variants = types.get(name).unwrap();
-        assert!(variants.len() > 0);
-        if !is_explicit && variants.len() > 1 {
-            return Err(crate::Error::new_parse(&format!(
-                "{}: Type '{}' is implicitly referenced by file '{}' but has multiple variants in the corpus",
-                corpus_path.display(),
-                name,
-                file_name,
-            )));
+        // In the declarations-only mode, a type is equal to another one with the same name.
+        if options.decls_only && Self::is_type_name(name) {
+            return Ok(());
         }
-        let tokens = &variants[variant_idx];
 
-        // Process recursively all types referenced by this symbol.
-        for token in tokens {
-            match token {
-                Token::TypeRef(ref_name) => {
-                    // Process the type. Note that passing variant_idx=0 is ok here:
-                    // * If the type is explicitly specified in the parent F# record then it must be
-                    //   already added in the records and the called function immediately returns.
-                    // * If the type is implicit then it can have only one variant and so only
-                    //   variant_idx=0 can be correct. The invoked function will check that no more
-                    //   than one variant is actually present.
-                    Self::extrapolate_file_record(
-                        corpus_path,
-                        file_name,
-                        ref_name,
-                        0,
-                        false,
-                        types,
-                        records,
-                    )?;
+        let tokens = self.get_compare_tokens(walk.file, name, options)?;
+        let other_tokens = walk
+            .other
+            .get_compare_tokens(walk.other_file, other_name, options)?;
+
+        Self::check_type_depth(walk.file, &walk.chain, name, options.max_type_depth)?;
+        walk.chain.push(name);
+        let mut is_equal = tokens.len() == other_tokens.len();
+        let min_tokens = min(tokens.len(), other_tokens.len());
+        for i in 0..min_tokens {
+            let token = &tokens[i];
+            let other_token = &other_tokens[i];
+
+            is_equal &= match (token, other_token) {
+                (Token::TypeRef(ref_name), Token::TypeRef(other_ref_name))
+                    if ref_name == other_ref_name
+                        || options.are_anon_names_eq(ref_name, other_ref_name) =>
+                {
+                    // Look up the name again in the corpus to obtain a reference with the
+                    // corpus lifetime, the token can be owned by expanded tokens.
+                    match self.types.get_key_value(&**ref_name) {
+                        Some((ref_name, _)) => {
+                            self.compare_types(walk, ref_name.as_str(), other_ref_name)?
+                        }
+                        None if options.lenient => Self::warn_dangling_ref(
+                            walk.file,
+                            &walk.chain,
+                            ref_name,
+                            &options.diagnostic_callback,
+                        ),
+                        None if walk.file.records.contains_key(&**ref_name) => {
+                            return Err(Self::new_missing_declaration_error(walk.file, ref_name))
+                        }
+                        None => return Err(Self::new_unknown_type_error(walk.file, ref_name)),
+                    }
+                    true
                 }
-                Token::Atom(_word) => {}
-            }
+                (Token::Atom(word), Token::Atom(other_word)) => word == other_word,
+                _ => false,
+            };
         }
+        walk.chain.pop();
 
+        if !is_equal {
+            // TODO
+            Self::record_type_change(name, tokens, other_tokens, walk.file, walk.changes);
+        }
         Ok(())
     }
 
-    fn are_tokens_eq(a: &Tokens, b: &Tokens) -> bool {
-        if a.len() != b.len() {
-            return false;
-        }
-        for i in 0..a.len() {
-            if a[i] != b[i] {
-                return false;
-            };
+    /// Compares the corpus with another one and collects all differences between them.
+    fn get_changes<'a>(
+        &'a self,
+        other: &'a SymCorpus,
+        options: &CompareOptions,
+        num_workers: i32,
+    ) -> Result<CompareResult<'a>, crate::Error> {
+        // Restrict the comparison to the selected exports, if requested. Each of them must be
+        // present in at least one of the corpuses.
+        let mut only_symbols = options.only_symbols.iter().collect::<Vec<_>>();
+        only_symbols.sort();
+        for name in only_symbols {
+            if !self.exports.contains_key(name) && !other.exports.contains_key(name) {
+                return Err(crate::Error::new_parse(&format!(
+                    "Export '{}' is not present in either corpus",
+                    name
+                )));
+            }
         }
-        return true;
-    }
+        let is_selected =
+            |name: &str| options.only_symbols.is_empty() || options.only_symbols.contains(name);
 
-    fn is_export(name: &str) -> bool {
-        match name.chars().nth(1) {
-            Some(ch) => ch != '#',
-            None => true,
+        let works: Vec<_> = self
+            .exports
+            .iter()
+            .filter(|(name, _)| is_selected(name))
+            .collect();
+        let next_work_idx = AtomicUsize::new(0);
+        let num_done = AtomicUsize::new(0);
+
+        let removed_exports = Mutex::new(Vec::new());
+        let changes = Mutex::new(TypeChanges::new());
+
+        thread::scope(|s| {
+            if options.progress {
+                s.spawn(|| report_progress("compared", "exports", &num_done, works.len()));
+            }
+
+            let mut workers = Vec::new();
+            for _ in 0..num_workers {
+                workers.push(s.spawn(|| -> Result<(), crate::Error> {
+                    loop {
+                        let work_idx = next_work_idx.fetch_add(1, Ordering::Relaxed);
+                        if work_idx >= works.len() {
+                            return Ok(());
+                        }
+                        let (name, file_idx) = works[work_idx];
+
+                        let file = &self.files[*file_idx];
+                        match other.exports.get(name) {
+                            Some(other_file_idx) => {
+                                let mut walk = CompareWalk {
+                                    other,
+                                    file,
+                                    other_file: &other.files[*other_file_idx],
+                                    options,
+                                    chain: Vec::new(),
+                                    processed: HashSet::new(),
+                                    changes: &changes,
+                                };
+                                self.compare_types(&mut walk, name, name)?;
+                            }
+                            None => {
+                                removed_exports.lock().unwrap().push(name.as_str());
+                            }
+                        }
+
+                        let done = num_done.fetch_add(1, Ordering::Relaxed) + 1;
+                        if let Some(callback) = &options.progress_callback {
+                            callback(done, works.len());
+                        }
+                    }
+                }));
+            }
+
+            // Report the first error from the workers. Mark all exports as done so the progress
+            // reporter finishes also if some of them failed.
+            let result = workers
+                .into_iter()
+                .map(|worker| worker.join().unwrap())
+                .collect::<Result<Vec<_>, _>>();
+            num_done.store(works.len(), Ordering::Relaxed);
+            result
+        })?;
+
+        // Check for symbols in B and not in A.
+        let mut added_exports = Vec::new();
+        for (other_name, _other_file_idx) in &other.exports {
+            match self.exports.get(other_name) {
+                Some(_file_idx) => {}
+                None if is_selected(other_name) => added_exports.push(other_name.as_str()),
+                None => {}
+            }
         }
+
+        Ok(CompareResult {
+            removed_exports: removed_exports.into_inner().unwrap(),
+            added_exports,
+            changes: changes.into_inner().unwrap(),
+        })
     }
 
-    /// Processes a single symbol specified in a given file and adds it to the consolidated output.
+    /// Compares the corpus with another one and returns the found differences.
     ///
-    /// The specified symbol is added to `output_types` and `processed_types`, if not already
-    /// present, and all its type references get recursively processed in the same way. The
-    /// `output_types` is a [`HashMap`] which records all symbols which should appear on the output
-    /// together with a mapping from the internal symbol variant index to the output variant index.
-    /// The `processed_types` is a [`HashMap`] which tracks all symbols in the current file and
-    /// their output variant indices.
-    fn consolidate_type<'a>(
-        &'a self,
-        symfile: &SymFile,
-        name: &'a str,
-        output_types: &mut HashMap<&'a str, HashMap<usize, usize>>,
-        processed_types: &mut HashMap<&'a str, usize>,
-    ) {
-        // See if the symbol was already processed.
-        let processed_entry = match processed_types.entry(name) {
-            Occupied(_) => return,
-            Vacant(processed_entry) => processed_entry,
-        };
+    /// The changes are ordered by their kind, removed exports first, then added exports and
+    /// changed types, and each kind is sorted by the symbol name. The differences of changed types
+    /// are rendered by a specified renderer.
+    pub fn compare_with(
+        &self,
+        other: &SymCorpus,
+        options: &CompareOptions,
+        renderer: &dyn DiffRenderer,
+        num_workers: i32,
+    ) -> Result<Vec<Change>, crate::Error> {
+        let result = self.get_changes(other, options, num_workers)?;
 
-        // Look up the internal variant index.
-        let variant_idx = match symfile.records.get(name) {
-            Some(&variant_idx) => variant_idx,
-            None => panic!(
-                "Type {} is not known in file {}",
-                name,
-                symfile.path.display()
-            ),
+        let mut removed_exports = result.removed_exports;
+        removed_exports.sort();
+        let mut added_exports = result.added_exports;
+        added_exports.sort();
+
+        let mut type_changes = Vec::new();
+        for (name, variants) in result.changes {
+            for change in variants {
+                let diff = renderer.render(name, &change.tokens, &change.other_tokens)?;
+                let additive = options.allow_tail_append
+                    && is_tail_append(&change.tokens, &change.other_tokens);
+                let defined_in = match (self.exports.get(name), other.exports.get(name)) {
+                    (Some(&file_idx), Some(&other_file_idx)) => Some((
+                        self.files[file_idx].path.clone(),
+                        other.files[other_file_idx].path.clone(),
+                    )),
+                    _ => None,
+                };
+                type_changes.push(Change::TypeChanged {
+                    name: name.to_string(),
+                    diff,
+                    files: change.files.iter().map(|path| path.to_path_buf()).collect(),
+                    defined_in,
+                    additive,
+                });
+            }
+        }
+        type_changes.sort();
+
+        // Drop the changes which are deliberately tolerated or not severe enough.
+        let is_reported = |change: &Change| {
+            let is_ignored = match change {
+                Change::ExportRemoved(name) | Change::TypeChanged { name, .. } => {
+                    options.ignored_symbols.contains(name)
+                }
+                Change::ExportAdded(_) => false,
+            };
+            !is_ignored && change.severity() >= options.min_severity
         };
 
-        // Determine the output variant index for the symbol.
-        let remap_idx;
-        match output_types.entry(name) {
-            Occupied(mut active_entry) => {
-                let remap = active_entry.get_mut();
-                let remap_len = remap.len();
-                match remap.entry(variant_idx) {
-                    Occupied(remap_entry) => {
-                        remap_idx = *remap_entry.get();
+        Ok(removed_exports
+            .into_iter()
+            .map(|name| Change::ExportRemoved(name.to_string()))
+            .chain(
+                added_exports
+                    .into_iter()
+                    .map(|name| Change::ExportAdded(name.to_string())),
+            )
+            .chain(type_changes)
+            .filter(is_reported)
+            .collect())
+    }
+
+    /// Formats changes found by comparing the corpus with another one as text.
+    ///
+    /// Each removed and added export is described by one line and each changed type by its
+    /// rendered difference, followed by a line listing the files that it affects. With the
+    /// `group_by_file` option, the changes are instead listed in sections by the files that they
+    /// affect. With the `name_only` option, only the sorted names of the changed symbols are
+    /// listed, one per line.
+    pub fn format_changes(
+        &self,
+        other: &SymCorpus,
+        changes: &[Change],
+        options: &CompareOptions,
+    ) -> Vec<String> {
+        if options.name_only {
+            let names = changes
+                .iter()
+                .map(|change| match change {
+                    Change::ExportRemoved(name)
+                    | Change::ExportAdded(name)
+                    | Change::TypeChanged { name, .. } => name.clone(),
+                })
+                .collect::<BTreeSet<_>>();
+            return names.into_iter().collect();
+        }
+
+        // Format the changes and record them under the files that they affect.
+        let mut change_lines = Vec::new();
+        for change in changes {
+            match change {
+                Change::ExportRemoved(name) => {
+                    let path = self.files[self.exports[name]].path.as_path();
+                    let line = format!("Export {} is present in A but not in B", name);
+                    change_lines.push((path, vec![line]));
+                }
+                Change::ExportAdded(name) => {
+                    let path = other.files[other.exports[name]].path.as_path();
+                    let line = format!("Export {} is present in B but not in A", name);
+                    change_lines.push((path, vec![line]));
+                }
+                Change::TypeChanged {
+                    name,
+                    diff,
+                    files,
+                    defined_in,
+                    additive,
+                } => {
+                    // Start a changed export with a header naming the files which define it, and
+                    // an additive change with a note about its classification.
+                    let mut diff = diff.clone();
+                    if *additive {
+                        diff.insert(
+                            0,
+                            format!("Type {} is only extended by appended members", name),
+                        );
                     }
-                    Vacant(remap_entry) => {
-                        remap_idx = remap_len;
-                        remap_entry.insert(remap_idx);
+                    if let Some((path, other_path)) = defined_in {
+                        diff.insert(
+                            0,
+                            format!(
+                                "Export {} is defined in '{}' in A and '{}' in B",
+                                name,
+                                path.display(),
+                                other_path.display()
+                            ),
+                        );
+                    }
+
+                    if options.group_by_file {
+                        for path in files {
+                            change_lines.push((path.as_path(), diff.clone()));
+                        }
+                    } else {
+                        // Output the change only once in the flat mode, followed by the list of
+                        // files that it affects.
+                        let mut lines = diff;
+                        lines.push(format!(
+                            "Affected files: {}",
+                            files
+                                .iter()
+                                .map(|path| path.display().to_string())
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        ));
+                        change_lines.push((files[0].as_path(), lines));
                     }
                 }
             }
-            Vacant(active_entry) => {
-                remap_idx = 0;
-                active_entry.insert(HashMap::from([(variant_idx, remap_idx)]));
-            }
-        };
-        processed_entry.insert(remap_idx);
+        }
 
-        // Process recursively all types that the symbol references.
-        let variants = match self.types.get(name) {
-            Some(variants) => variants,
-            None => panic!("Type {} has a missing declaration", name),
-        };
+        if !options.group_by_file {
+            return change_lines
+                .into_iter()
+                .flat_map(|(_, lines)| lines)
+                .collect();
+        }
 
-        for token in &variants[variant_idx] {
-            match token {
-                Token::TypeRef(ref_name) => {
-                    self.consolidate_type(symfile, ref_name, output_types, processed_types)
-                }
-                Token::Atom(_word) => {}
+        let mut sections: BTreeMap<_, Vec<_>> = BTreeMap::new();
+        for (path, lines) in change_lines {
+            sections.entry(path).or_default().extend(lines);
+        }
+        let mut output = Vec::new();
+        for (i, (path, lines)) in sections.into_iter().enumerate() {
+            if i > 0 {
+                output.push(String::new());
             }
+            output.push(format!("{}:", path.display()));
+            output.extend(lines);
         }
+        output
     }
 
-    /// Writes the corpus in the consolidated form into a specified file.
-    pub fn write_consolidated_file(&self, filename: &str) -> Result<(), crate::Error> {
-        // Open the output file.
-        let path = Path::new(filename);
-        let file: Box<dyn Write> = if filename == "-" {
-            Box::new(io::stdout())
-        } else {
-            match File::create(path) {
-                Ok(file) => Box::new(file),
-                Err(err) => {
-                    return Err(crate::Error::new_io(
-                        &format!("Failed to create file '{}'", path.display()),
-                        err,
-                    ))
-                }
+    /// Compares all exports in the corpus with their expected definitions stored in a specified
+    /// directory and returns the formatted differences.
+    ///
+    /// Each file `<dir>/<export>` holds the consolidated form of the closure of the export, as
+    /// produced by [`SymCorpus::extract_closure`]. The closure of each current export is
+    /// consolidated and compared line by line with the expected file, after the expected file is
+    /// read and consolidated again to normalize its formatting. Exports without an expected file
+    /// are reported as added and expected files without an export as removed.
+    pub fn compare_with_expected(&self, dir: &Path) -> Result<Vec<String>, crate::Error> {
+        // Collect the names of all expected files.
+        let dir_iter = fs::read_dir(dir).map_err(|err| {
+            crate::Error::new_io(
+                &format!("Failed to read directory '{}'", dir.display()),
+                err,
+            )
+        })?;
+        let mut expected_names = BTreeSet::new();
+        for maybe_entry in dir_iter {
+            let entry = maybe_entry.map_err(|err| {
+                crate::Error::new_io(
+                    &format!("Failed to read directory '{}'", dir.display()),
+                    err,
+                )
+            })?;
+            if entry.path().is_file() {
+                expected_names.insert(entry.file_name().to_string_lossy().to_string());
+            }
+        }
+
+        let mut names = self.exports.keys().collect::<Vec<_>>();
+        names.sort();
+
+        let mut lines = Vec::new();
+        for name in names {
+            let expected_path = dir.join(name);
+            if !expected_names.remove(name.as_str()) {
+                lines.push(format!(
+                    "Export {} is new, no expected file '{}'",
+                    name,
+                    expected_path.display()
+                ));
+                continue;
+            }
+
+            let file = File::open(&expected_path).map_err(|err| {
+                crate::Error::new_io(
+                    &format!("Failed to open file '{}'", expected_path.display()),
+                    err,
+                )
+            })?;
+            let expected = SymCorpus::from_reader(&expected_path, file)?;
+            let expected_lines = expected
+                .to_consolidated_string()?
+                .lines()
+                .map(String::from)
+                .collect::<Vec<_>>();
+            let current_lines = self
+                .extract_closure(name)
+                .unwrap()
+                .to_consolidated_string()?
+                .lines()
+                .map(String::from)
+                .collect::<Vec<_>>();
+
+            if expected_lines != current_lines {
+                lines.push(format!(
+                    "Export {} differs from its expected file '{}'",
+                    name,
+                    expected_path.display()
+                ));
+                lines.extend(crate::diff::unified(&expected_lines, &current_lines)?);
             }
-        };
+        }
+
+        for name in expected_names {
+            lines.push(format!(
+                "Export {} is removed, found only its expected file '{}'",
+                name,
+                dir.join(&name).display()
+            ));
+        }
 
-        self.write_consolidated(file)
+        Ok(lines)
     }
 
-    pub fn write_consolidated<W>(&self, writer: W) -> Result<(), crate::Error>
+    /// Compares two consolidated files in the canonical sorted form, without loading them fully,
+    /// and writes the differences to a specified writer.
+    ///
+    /// Both inputs must have their type records sorted by name, followed by export records sorted
+    /// by name and then by `F#` records, which is the form produced by the writer. Each type must
+    /// have a single variant, an input with multiple variants of a type is rejected with an error
+    /// because the variant used by each export is known only from the `F#` records. The records of
+    /// the two files are merge-joined by name, so only one type from each file is held in memory at
+    /// a time. Records are compared directly, each changed type is reported once, regardless of
+    /// which files and exports use it, and without listing the affected files. Exports present in
+    /// only one file are reported as removed or added.
+    pub fn compare_streaming<R1, R2, W>(
+        path: &Path,
+        reader: R1,
+        other_path: &Path,
+        other_reader: R2,
+        renderer: &dyn DiffRenderer,
+        writer: W,
+    ) -> Result<CompareSummary, crate::Error>
     where
+        R1: io::Read,
+        R2: io::Read,
         W: io::Write,
     {
         let mut writer = BufWriter::new(writer);
+        let err_desc = "Failed to write the comparison";
 
-        // Initialize output data. Variable output_types records all output symbols, file_types
-        // provides per-file information.
-        let mut output_types = HashMap::new();
-        let mut file_types = vec![HashMap::new(); self.files.len()];
+        let mut records = SortedRecordReader::new(path, reader);
+        let mut other_records = SortedRecordReader::new(other_path, other_reader);
 
-        // Sort all files in the corpus by their path.
-        let mut file_indices = (0..self.files.len()).collect::<Vec<_>>();
-        file_indices.sort_by_key(|&i| &self.files[i].path);
+        let mut removed_lines = Vec::new();
+        let mut added_lines = Vec::new();
+        let mut change_lines = Vec::new();
+        let mut summary = CompareSummary::default();
 
-        // Process the sorted files and add their needed types to the output.
-        for &i in &file_indices {
-            let symfile = &self.files[i];
+        let mut group = records.next_group()?;
+        let mut other_group = other_records.next_group()?;
+        loop {
+            let ordering = match (&group, &other_group) {
+                (Some((name, _)), Some((other_name, _))) => {
+                    SortedRecordReader::<R1>::sort_key(name)
+                        .cmp(&SortedRecordReader::<R2>::sort_key(other_name))
+                }
+                (Some(_), None) => std::cmp::Ordering::Less,
+                (None, Some(_)) => std::cmp::Ordering::Greater,
+                (None, None) => break,
+            };
 
-            // Collect sorted exports in the file which are the roots for consolidation.
-            let mut exports = Vec::new();
-            for (name, _) in &symfile.records {
-                if Self::is_export(name) {
-                    exports.push(name.as_str());
+            match ordering {
+                std::cmp::Ordering::Less => {
+                    let (name, _) = group.unwrap();
+                    if Self::is_export(&name) {
+                        removed_lines.push(format!("Export {} is present in A but not in B", name));
+                        summary.removed_exports += 1;
+                        summary.breaking_changes += 1;
+                    }
+                    group = records.next_group()?;
                 }
-            }
-            exports.sort();
+                std::cmp::Ordering::Greater => {
+                    let (other_name, _) = other_group.unwrap();
+                    if Self::is_export(&other_name) {
+                        added_lines.push(format!(
+                            "Export {} is present in B but not in A",
+                            other_name
+                        ));
+                        summary.added_exports += 1;
+                    }
+                    other_group = other_records.next_group()?;
+                }
+                std::cmp::Ordering::Equal => {
+                    let (name, tokens) = group.unwrap();
+                    let (_, other_tokens) = other_group.unwrap();
+                    if tokens != other_tokens {
+                        // The streaming comparison does not track which exports reach the type, a
+                        // changed type is conservatively considered breaking.
+                        summary.changed_types += 1;
+                        summary.breaking_changes += 1;
+                        change_lines.extend(renderer.render(&name, &tokens, &other_tokens)?);
+                    }
 
-            // Add the exported types and their needed types to the output.
-            let mut processed_types = HashMap::new();
-            for name in &exports {
-                self.consolidate_type(symfile, name, &mut output_types, &mut processed_types);
+                    group = records.next_group()?;
+                    other_group = other_records.next_group()?;
+                }
             }
-            file_types[i] = processed_types;
         }
 
-        // Go through all files and their output types. Check if a given type has only one variant
-        // in the output and mark it as such.
-        for i in 0..file_types.len() {
-            for (name, remap_idx) in &mut file_types[i] {
-                let remap = output_types.get(name).unwrap();
-                if remap.len() == 1 {
-                    *remap_idx = usize::MAX;
-                }
-            }
+        for line in removed_lines
+            .into_iter()
+            .chain(added_lines)
+            .chain(change_lines)
+        {
+            writeln!(writer, "{}", line).map_err(|err| crate::Error::new_io(err_desc, err))?;
         }
+        writer
+            .flush()
+            .map_err(|err| crate::Error::new_io(err_desc, err))?;
+        Ok(summary)
+    }
 
-        // Sort all output types and write them to the specified file.
-        let mut sorted_records = output_types.into_iter().collect::<Vec<_>>();
-        sorted_records.sort_by_key(|(name, _remap)| (Self::is_export(name), *name));
+    /// Compares the corpus with another one and writes the differences to a specified writer.
+    pub fn write_comparison<W>(
+        &self,
+        other: &SymCorpus,
+        options: &CompareOptions,
+        renderer: &dyn DiffRenderer,
+        num_workers: i32,
+        writer: W,
+    ) -> Result<CompareSummary, crate::Error>
+    where
+        W: io::Write,
+    {
+        let mut writer = BufWriter::new(writer);
+        let err_desc = "Failed to write the comparison";
 
-        for (name, remap) in sorted_records {
-            let variants = self.types.get(name).unwrap();
-            let mut sorted_remap = remap
-                .iter()
-                .map(|(&variant_idx, &remap_idx)| (remap_idx, variant_idx))
-                .collect::<Vec<_>>();
-            sorted_remap.sort();
+        let changes = self.compare_with(other, options, renderer, num_workers)?;
+        for line in self.format_changes(other, &changes, options) {
+            writeln!(writer, "{}", line).map_err(|err| crate::Error::new_io(err_desc, err))?;
+        }
+        writer
+            .flush()
+            .map_err(|err| crate::Error::new_io(err_desc, err))?;
 
-            let needs_suffix = sorted_remap.len() > 1;
-            for (remap_idx, variant_idx) in sorted_remap {
-                let tokens = &variants[variant_idx];
+        Ok(CompareSummary::from_changes(&changes))
+    }
+}
 
-                if needs_suffix {
-                    write!(writer, "{}@{}", name, remap_idx);
-                } else {
-                    write!(writer, "{}", name);
-                }
-                for token in tokens {
-                    write!(writer, " {}", token.as_str());
-                }
-                writeln!(writer, "");
+/// Rewrites a flexible array member at the end of a struct or union body to a pointer member.
+///
+/// Tokens ending with `<name> [ ] ; }` are changed to end with `* <name> ; }`. Other tokens are
+/// returned unchanged.
+fn normalize_flexible_array(tokens: Cow<'_, Tokens>) -> Cow<'_, Tokens> {
+    let len = tokens.len();
+    if len < 5 {
+        return tokens;
+    }
+    let is_flexible_array = matches!(&tokens[len - 5], Token::Atom(_))
+        && tokens[len - 4].as_str() == "["
+        && tokens[len - 3].as_str() == "]"
+        && tokens[len - 2].as_str() == ";"
+        && tokens[len - 1].as_str() == "}";
+    if !is_flexible_array {
+        return tokens;
+    }
+
+    let mut normalized = tokens.into_owned();
+    normalized.truncate(len - 4);
+    normalized.insert(len - 5, Token::new_atom("*"));
+    normalized.push(Token::new_atom(";"));
+    normalized.push(Token::new_atom("}"));
+    Cow::Owned(normalized)
+}
+
+/// Sorts the enumerators of an enum body if all of them have an explicit value.
+///
+/// Tokens of other types, or of an enum with any enumerator which has an implicit value, are
+/// returned unchanged.
+fn normalize_enum_order(tokens: Cow<'_, Tokens>) -> Cow<'_, Tokens> {
+    if tokens.first().map(Token::as_str) != Some("enum") {
+        return tokens;
+    }
+    let (start, end) = match find_body(&tokens) {
+        Some(body) => body,
+        None => return tokens,
+    };
+
+    // Split the body into enumerators, separated by commas outside of any parentheses.
+    let mut enumerators = Vec::new();
+    let mut enumerator_start = start;
+    let mut depth = 0;
+    for i in start..end {
+        match tokens[i].as_str() {
+            "(" => depth += 1,
+            ")" => depth -= 1,
+            "," if depth == 0 => {
+                enumerators.push(&tokens[enumerator_start..i]);
+                enumerator_start = i + 1;
             }
+            _ => {}
         }
+    }
+    enumerators.push(&tokens[enumerator_start..end]);
 
-        // Write file records.
-        for &i in &file_indices {
-            let symfile = &self.files[i];
+    let is_explicit = |enumerator: &&[Token]| enumerator.iter().any(|token| token.as_str() == "=");
+    if !enumerators.iter().all(is_explicit) {
+        return tokens;
+    }
+    enumerators.sort();
 
-            // TODO Sorting, make same as above.
-            let mut sorted_types = file_types[i]
-                .iter()
-                .map(|(&name, &remap_idx)| (Self::is_export(name), name, remap_idx))
-                .collect::<Vec<_>>();
-            sorted_types.sort();
+    let mut normalized = tokens[..start].to_vec();
+    for (i, enumerator) in enumerators.iter().enumerate() {
+        if i > 0 {
+            normalized.push(Token::new_atom(","));
+        }
+        normalized.extend_from_slice(enumerator);
+    }
+    normalized.extend_from_slice(&tokens[end..]);
+    Cow::Owned(normalized)
+}
 
-            // Output the F# record in form `F#<filename> <type@variant>... <export>...`. Types with
-            // only one variant in the entire consolidated file can be skipped because they can be
-            // implicitly determined by a reader.
-            write!(writer, "F#{}", symfile.path.display());
-            for &(_, name, remap_idx) in &sorted_types {
-                if remap_idx != usize::MAX {
-                    write!(writer, " {}@{}", name, remap_idx);
-                } else if Self::is_export(name) {
-                    write!(writer, " {}", name);
-                }
+/// Looks for unbalanced `{}` and `()` brackets in the tokens of a record.
+///
+/// Returns a description of the first problem found, or [`None`] if the brackets are balanced.
+fn find_bracket_imbalance(tokens: &Tokens) -> Option<String> {
+    let mut expected = Vec::new();
+    for token in tokens {
+        match token.as_str() {
+            "{" => expected.push("}"),
+            "(" => expected.push(")"),
+            closing @ ("}" | ")") if expected.pop() != Some(closing) => {
+                return Some(format!("unexpected '{}'", closing));
             }
-            writeln!(writer, "");
+            _ => {}
         }
-        Ok(())
+    }
+    expected
+        .last()
+        .map(|closing| format!("missing '{}'", closing))
+}
+
+/// A 64-bit FNV-1a hasher.
+///
+/// The hash is stable across runs, platforms and Rust versions, unlike the hashers provided by the
+/// standard library.
+struct Fnv1aHasher(u64);
+
+impl Fnv1aHasher {
+    fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
     }
 
-    // TODO
-    fn print_file_type(&self, file: &SymFile, name: &str, processed: &mut HashSet<String>) {
-        match processed.get(name) {
-            Some(_) => return,
-            None => {}
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
         }
-        processed.insert(name.to_string());
+    }
 
-        match file.records.get(name) {
-            Some(&variant_idx) => match self.types.get(name) {
-                Some(variants) => {
-                    let tokens = &variants[variant_idx];
-                    for token in tokens {
-                        match token {
-                            Token::TypeRef(ref_name) => {
-                                self.print_file_type(file, ref_name, processed);
-                            }
-                            Token::Atom(_word) => {}
-                        }
-                    }
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
 
-                    print!("{}", name);
-                    for token in tokens {
-                        match token {
-                            Token::TypeRef(ref_name) => {
-                                print!(" {}", ref_name);
-                            }
-                            Token::Atom(word) => {
-                                print!(" {}", word);
-                            }
-                        }
-                    }
-                    println!("");
-                }
-                None => {
-                    panic!("Type {} has a missing declaration", name);
-                }
-            },
-            None => {
-                panic!("Type {} is not known in file {}", name, file.path.display())
-            }
+/// Periodically reports progress of an operation on `stderr`, until `num_done` reaches `total`.
+///
+/// Each report shows `<verb> <num_done>/<total> <what>` and the estimated remaining time, which
+/// is computed from the average rate over the last several reports.
+fn report_progress(verb: &str, what: &str, num_done: &AtomicUsize, total: usize) {
+    const INTERVAL: Duration = Duration::from_millis(200);
+    const WINDOW: usize = 10;
+
+    let mut samples = VecDeque::new();
+    loop {
+        let done = num_done.load(Ordering::Relaxed);
+        let now = Instant::now();
+        samples.push_back((now, done));
+        if samples.len() > WINDOW {
+            samples.pop_front();
         }
-    }
 
-    pub fn print_type(&self, name: &str) {
-        for file in &self.files {
-            match file.records.get(name) {
-                Some(_variant_idx) => {
-                    println!("Found type {} in {}:", name, file.path.display());
-                    let mut processed = HashSet::new();
-                    self.print_file_type(&file, name, &mut processed);
-                }
-                None => {}
-            }
+        // Estimate the remaining time using the moving average of the rate.
+        let (first_time, first_done) = samples[0];
+        let elapsed = now.duration_since(first_time).as_secs_f64();
+        let eta = if done > first_done && elapsed > 0.0 {
+            let rate = (done - first_done) as f64 / elapsed;
+            format!("{:.0}s", (total - done) as f64 / rate)
+        } else {
+            "?".to_string()
+        };
+
+        eprint!("\r{} {}/{} {}, ETA {}\x1b[K", verb, done, total, what, eta);
+        if done >= total {
+            eprintln!();
+            return;
         }
+        thread::sleep(INTERVAL);
     }
+}
 
-    fn get_type_tokens<'a>(symtypes: &'a SymCorpus, file: &SymFile, name: &str) -> &'a Tokens {
-        match file.records.get(name) {
-            Some(&variant_idx) => match symtypes.types.get(name) {
-                Some(variants) => &variants[variant_idx],
-                None => {
-                    panic!("Type {} has a missing declaration", name);
-                }
-            },
-            None => {
-                panic!("Type {} is not known in file {}", name, file.path.display())
-            }
+/// A reader of records from a consolidated file in the canonical sorted form, which checks that
+/// each type has a single variant.
+struct SortedRecordReader<R> {
+    path: PathBuf,
+    lines: io::Lines<BufReader<R>>,
+    line_idx: usize,
+    pending: Option<(String, Tokens)>,
+    last_name: Option<String>,
+    at_end: bool,
+}
+
+impl<R: io::Read> SortedRecordReader<R> {
+    fn new(path: &Path, reader: R) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            lines: BufReader::new(reader).lines(),
+            line_idx: 0,
+            pending: None,
+            last_name: None,
+            at_end: false,
         }
     }
 
-    fn record_type_change<'a>(
-        name: &'a str,
-        tokens: &'a Tokens,
-        other_tokens: &'a Tokens,
-        changes: &Mutex<TypeChanges<'a>>,
-    ) {
-        let mut changes = changes.lock().unwrap();
-        // TODO Rewrite using .entry().
-        match changes.get_mut(name) {
-            Some(variants) => {
-                for (tokens2, other_tokens2) in &*variants {
-                    if Self::are_tokens_eq(tokens, tokens2)
-                        && Self::are_tokens_eq(other_tokens, other_tokens2)
-                    {
-                        return;
-                    }
-                }
-                variants.push((tokens, other_tokens));
+    /// Returns the key by which records are sorted in the canonical form.
+    fn sort_key(name: &str) -> (bool, &str) {
+        (SymCorpus::is_export(name), name)
+    }
+
+    /// Reads the next type or export record and returns its base name and tokens, or [`None`]
+    /// when the `F#` records or the end of the input are reached.
+    fn next_record(&mut self) -> Result<Option<(String, Tokens)>, crate::Error> {
+        if self.at_end {
+            return Ok(None);
+        }
+        let line = match self.lines.next() {
+            Some(Ok(line)) => line,
+            Some(Err(err)) => {
+                return Err(crate::Error::new_io(
+                    &format!("Failed to read data from file '{}'", self.path.display()),
+                    err,
+                ))
             }
             None => {
-                let mut variants = Vec::new();
-                variants.push((tokens, other_tokens));
-                changes.insert(name, variants);
+                self.at_end = true;
+                return Ok(None);
             }
-        }
-    }
+        };
+        self.line_idx += 1;
 
-    fn compare_types<'a>(
-        &'a self,
-        other: &'a SymCorpus,
-        file: &SymFile,
-        other_file: &SymFile,
-        name: &'a str,
-        processed: &mut HashSet<String>,
-        changes: &Mutex<TypeChanges<'a>>,
-    ) {
-        // TODO Take into account different variants?
-        match processed.get(name) {
-            Some(_) => return,
-            None => {}
+        let mut words = line.split_ascii_whitespace();
+        let name = match words.next() {
+            Some(name) => name,
+            None => {
+                return Err(crate::Error::new_parse(&format!(
+                    "{}:{}: Expected a record name",
+                    self.path.display(),
+                    self.line_idx
+                )))
+            }
+        };
+        if name.starts_with("F#") {
+            self.at_end = true;
+            return Ok(None);
         }
-        processed.insert(name.to_string());
 
-        let tokens = Self::get_type_tokens(self, file, name);
-        let other_tokens = Self::get_type_tokens(other, other_file, name);
+        let (base_name, _) = SymCorpus::split_type_name(name);
+        let tokens = SymCorpus::words_into_tokens(&mut words);
+        Ok(Some((base_name.to_string(), tokens)))
+    }
 
-        let mut is_equal = tokens.len() == other_tokens.len();
-        let min_tokens = min(tokens.len(), other_tokens.len());
-        for i in 0..min_tokens {
-            let token = &tokens[i];
-            let other_token = &other_tokens[i];
+    /// Reads the next type or export and returns its name and its single variant, or [`None`] at
+    /// the end of the records.
+    fn next_group(&mut self) -> Result<Option<(String, Tokens)>, crate::Error> {
+        let (name, tokens) = match self.pending.take() {
+            Some(record) => record,
+            None => match self.next_record()? {
+                Some(record) => record,
+                None => return Ok(None),
+            },
+        };
 
-            is_equal &= match (token, other_token) {
-                (Token::TypeRef(ref_name), Token::TypeRef(other_ref_name)) => {
-                    if ref_name == other_ref_name {
-                        self.compare_types(
-                            other,
-                            file,
-                            other_file,
-                            ref_name.as_str(),
-                            processed,
-                            changes,
-                        );
-                        true
-                    } else {
-                        false
-                    }
-                }
-                (Token::Atom(word), Token::Atom(other_word)) => word == other_word,
-                _ => false,
-            };
+        // Check that the records are sorted.
+        if let Some(last_name) = &self.last_name {
+            if Self::sort_key(&name) <= Self::sort_key(last_name) {
+                return Err(crate::Error::new_parse(&format!(
+                    "{}:{}: Record {} is not in the canonical order",
+                    self.path.display(),
+                    self.line_idx,
+                    name
+                )));
+            }
         }
-        if !is_equal {
-            // TODO
-            Self::record_type_change(name, tokens, other_tokens, changes);
+
+        // Check that the type has only one variant. Which variant each export uses is recorded
+        // only by the `F#` records at the end of the file, so multiple variants can't be paired
+        // without loading the whole file.
+        if let Some((next_name, next_tokens)) = self.next_record()? {
+            if next_name == name {
+                return Err(crate::Error::new_parse(&format!(
+                    concat!(
+                        "{}:{}: Type {} has multiple variants, which the streaming comparison ",
+                        "doesn't support"
+                    ),
+                    self.path.display(),
+                    self.line_idx,
+                    name
+                )));
+            }
+            self.pending = Some((next_name, next_tokens));
         }
+
+        self.last_name = Some(name.clone());
+        Ok(Some((name, tokens)))
     }
+}
 
-    pub fn compare_with(&self, other: &SymCorpus, num_workers: i32) {
-        let works: Vec<_> = self.exports.iter().collect();
-        let next_work_idx = AtomicUsize::new(0);
+/// A renderer of differences between two variants of a type.
+pub trait DiffRenderer {
+    /// Renders the differences between the `old` and `new` tokens of a given symbol and returns
+    /// them as a [`Vec`] of [`String`] lines.
+    fn render(&self, symbol: &str, old: &Tokens, new: &Tokens)
+        -> Result<Vec<String>, crate::Error>;
+}
 
-        let changes = Mutex::new(TypeChanges::new());
+/// A renderer which shows the symbol name followed by a unified diff of its pretty-formatted
+/// variants.
+pub struct UnifiedRenderer {
+    context: usize,
+    word_diff: bool,
+}
 
-        thread::scope(|s| {
-            for _ in 0..num_workers {
-                s.spawn(|| loop {
-                    let work_idx = next_work_idx.fetch_add(1, Ordering::Relaxed);
-                    if work_idx >= works.len() {
-                        break;
-                    }
-                    let (name, file_idx) = works[work_idx];
-
-                    let file = &self.files[*file_idx];
-                    match other.exports.get(name) {
-                        Some(other_file_idx) => {
-                            let other_file = &other.files[*other_file_idx];
-                            let mut processed = HashSet::new();
-                            self.compare_types(
-                                other,
-                                file,
-                                other_file,
-                                name,
-                                &mut processed,
-                                &changes,
-                            );
-                        }
-                        None => {
-                            println!("Export {} is present in A but not in B", name);
-                        }
-                    }
-                });
-            }
-        });
+impl UnifiedRenderer {
+    /// Creates a new renderer with the default number of context lines.
+    pub fn new() -> Self {
+        Self::with_context(crate::diff::DEFAULT_CONTEXT)
+    }
 
-        // Check for symbols in B and not in A.
-        for (other_name, _other_file_idx) in &other.exports {
-            match self.exports.get(other_name) {
-                Some(_file_idx) => {}
-                None => {
-                    println!("Export {} is present in B but not in A", other_name);
-                }
-            }
+    /// Creates a new renderer which shows up to `context` lines of unchanged context around each
+    /// change.
+    pub fn with_context(context: usize) -> Self {
+        Self {
+            context,
+            word_diff: false,
         }
+    }
 
-        let changes = changes.into_inner().unwrap();
-        for (name, variants) in changes {
-            for (tokens, other_tokens) in variants {
-                println!("{}", name);
-                for line in get_type_diff(tokens, other_tokens) {
-                    println!("{}", line);
-                }
-            }
+    /// Creates a new renderer which shows up to `context` lines of unchanged context around each
+    /// change and highlights the changed words in replaced lines.
+    pub fn with_word_diff(context: usize) -> Self {
+        Self {
+            context,
+            word_diff: true,
         }
     }
 }
 
+impl Default for UnifiedRenderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DiffRenderer for UnifiedRenderer {
+    fn render(
+        &self,
+        symbol: &str,
+        old: &Tokens,
+        new: &Tokens,
+    ) -> Result<Vec<String>, crate::Error> {
+        let mut res = vec![symbol.to_string()];
+        res.extend(get_type_diff(old, new, self.context, self.word_diff)?);
+        Ok(res)
+    }
+}
+
+/// A renderer which shows the symbol name followed by its pretty-formatted variants placed side by
+/// side.
+pub struct SideBySideRenderer;
+
+impl DiffRenderer for SideBySideRenderer {
+    fn render(
+        &self,
+        symbol: &str,
+        old: &Tokens,
+        new: &Tokens,
+    ) -> Result<Vec<String>, crate::Error> {
+        let pretty = pretty_format_type(old);
+        let other_pretty = pretty_format_type(new);
+        let mut res = vec![symbol.to_string()];
+        res.extend(crate::diff::side_by_side(&pretty, &other_pretty)?);
+        Ok(res)
+    }
+}
+
 /// Processes tokens describing a type and produces its pretty-formatted version as a [`Vec`] of
 /// [`String`] lines.
 fn pretty_format_type(tokens: &Tokens) -> Vec<String> {
@@ -1070,10 +5206,78 @@ fn pretty_format_type(tokens: &Tokens) -> Vec<String> {
     res
 }
 
-/// Formats a unified diff between two supposedly different types and returns them as a [`Vec`] of
-/// [`String`] lines.
-fn get_type_diff(tokens: &Tokens, other_tokens: &Tokens) -> Vec<String> {
+/// Returns the range of tokens between the outermost `{` and its matching `}`, exclusively.
+fn find_body(tokens: &Tokens) -> Option<(usize, usize)> {
+    let start = tokens.iter().position(|token| token.as_str() == "{")?;
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate().skip(start) {
+        match token.as_str() {
+            "{" => depth += 1,
+            "}" => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some((start + 1, i));
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Checks whether a struct change consists solely of new members appended after the last
+/// original member.
+///
+/// The tokens outside the struct body must be identical, the original members must be an exact
+/// prefix of the new members, and the new body must add at least one complete member.
+fn is_tail_append(tokens: &Tokens, other_tokens: &Tokens) -> bool {
+    if tokens.first().map(Token::as_str) != Some("struct") {
+        return false;
+    }
+
+    let (start, end) = match find_body(tokens) {
+        Some(body) => body,
+        None => return false,
+    };
+    let (other_start, other_end) = match find_body(other_tokens) {
+        Some(body) => body,
+        None => return false,
+    };
+
+    // Check that the tokens around the body are the same.
+    if tokens[..start] != other_tokens[..other_start] || tokens[end..] != other_tokens[other_end..]
+    {
+        return false;
+    }
+
+    // Check that the original members form a prefix of the new ones, ending on a member boundary,
+    // and that the appended part is formed only by complete members.
+    let body = &tokens[start..end];
+    let other_body = &other_tokens[other_start..other_end];
+    if other_body.len() <= body.len() || !other_body.starts_with(body) {
+        return false;
+    }
+    if body.last().is_some_and(|token| token.as_str() != ";") {
+        return false;
+    }
+    other_body.last().map(Token::as_str) == Some(";")
+}
+
+/// Formats a unified diff between two supposedly different types, with up to `context` lines of
+/// unchanged context around each change, and returns them as a [`Vec`] of [`String`] lines.
+///
+/// If `word_diff` is set, the changed words in replaced lines are highlighted.
+fn get_type_diff(
+    tokens: &Tokens,
+    other_tokens: &Tokens,
+    context: usize,
+    word_diff: bool,
+) -> Result<Vec<String>, crate::Error> {
     let pretty = pretty_format_type(tokens);
     let other_pretty = pretty_format_type(other_tokens);
-    crate::diff::unified(&pretty, &other_pretty)
+    if word_diff {
+        crate::diff::unified_words_with(&pretty, &other_pretty, context)
+    } else {
+        crate::diff::unified_with(&pretty, &other_pretty, context)
+    }
 }
@@ -99,6 +99,72 @@ fn format_union() {
     );
 }
 
+#[test]
+fn format_enum_values() {
+    // Check pretty-formatting of an enum declaration with explicit values, which are kept on the
+    // lines of their enumerators.
+    let pretty = pretty_format_type(&vec![
+        Token::new_atom("enum"),
+        Token::new_atom("test"),
+        Token::new_atom("{"),
+        Token::new_atom("VALUE1"),
+        Token::new_atom("="),
+        Token::new_atom("3"),
+        Token::new_atom(","),
+        Token::new_atom("VALUE2"),
+        Token::new_atom("="),
+        Token::new_atom("("),
+        Token::new_atom("1"),
+        Token::new_atom("<<"),
+        Token::new_atom("2"),
+        Token::new_atom(")"),
+        Token::new_atom(","),
+        Token::new_atom("VALUE3"),
+        Token::new_atom("}"),
+    ]);
+    assert_eq!(
+        pretty,
+        crate::string_vec!(
+            "enum test {",
+            "\tVALUE1 = 3,",
+            "\tVALUE2 = ( 1 << 2 ),",
+            "\tVALUE3",
+            "}" //
+        )
+    );
+}
+
+#[test]
+fn format_bitfield() {
+    // Check pretty-formatting of a struct declaration with bitfields, the bitfield widths are kept
+    // on the lines of their members.
+    let pretty = pretty_format_type(&vec![
+        Token::new_atom("struct"),
+        Token::new_atom("test"),
+        Token::new_atom("{"),
+        Token::new_atom("unsigned"),
+        Token::new_atom("int"),
+        Token::new_atom("flag"),
+        Token::new_atom(":"),
+        Token::new_atom("1"),
+        Token::new_atom(";"),
+        Token::new_atom("int"),
+        Token::new_atom(":"),
+        Token::new_atom("0"),
+        Token::new_atom(";"),
+        Token::new_atom("}"),
+    ]);
+    assert_eq!(
+        pretty,
+        crate::string_vec!(
+            "struct test {",
+            "\tunsigned int flag : 1;",
+            "\tint : 0;",
+            "}" //
+        )
+    );
+}
+
 #[test]
 fn format_enum_constant() {
     // Check pretty-formatting of an enum constant declaration.
@@ -223,10 +289,14 @@ fn format_removal() {
             Token::new_atom(";"),
             Token::new_atom("}"),
         ],
-    );
+        3,
+        false,
+    )
+    .unwrap();
     assert_eq!(
         diff,
         crate::string_vec!(
+            "@@ -1,4 +1,3 @@",
             " struct test {",
             " \tint ivalue1;",
             "-\tint ivalue2;",
@@ -260,10 +330,14 @@ fn format_addition() {
             Token::new_atom(";"),
             Token::new_atom("}"),
         ],
-    );
+        3,
+        false,
+    )
+    .unwrap();
     assert_eq!(
         diff,
         crate::string_vec!(
+            "@@ -1,3 +1,4 @@",
             " struct test {",
             " \tint ivalue1;",
             "+\tint ivalue2;",
@@ -294,10 +368,14 @@ fn format_modification() {
             Token::new_atom(";"),
             Token::new_atom("}"),
         ],
-    );
+        3,
+        false,
+    )
+    .unwrap();
     assert_eq!(
         diff,
         crate::string_vec!(
+            "@@ -1,3 +1,3 @@",
             " struct test {",
             "-\tint ivalue1;",
             "+\tint ivalue2;",
@@ -305,3 +383,653 @@ fn format_modification() {
         )
     );
 }
+
+#[test]
+fn format_word_modification() {
+    // Check that the changed word of a replaced member is highlighted.
+    let diff = get_type_diff(
+        &vec![
+            Token::new_atom("struct"),
+            Token::new_atom("test"),
+            Token::new_atom("{"),
+            Token::new_atom("int"),
+            Token::new_atom("ivalue1"),
+            Token::new_atom(";"),
+            Token::new_atom("}"),
+        ],
+        &vec![
+            Token::new_atom("struct"),
+            Token::new_atom("test"),
+            Token::new_atom("{"),
+            Token::new_atom("long"),
+            Token::new_atom("ivalue1"),
+            Token::new_atom(";"),
+            Token::new_atom("}"),
+        ],
+        3,
+        true,
+    )
+    .unwrap();
+    assert_eq!(
+        diff,
+        crate::string_vec!(
+            "@@ -1,3 +1,3 @@",
+            " struct test {",
+            "-\t[-int-] ivalue1;",
+            "+\t{+long+} ivalue1;",
+            " }" //
+        )
+    );
+}
+
+#[test]
+fn format_context() {
+    // Check that only the specified number of unchanged lines is shown around a change of a large
+    // struct.
+    let make_struct = |changed: &str| {
+        let mut tokens = vec![
+            Token::new_atom("struct"),
+            Token::new_atom("test"),
+            Token::new_atom("{"),
+        ];
+        for i in 0..20 {
+            let name = if i == 10 {
+                changed.to_string()
+            } else {
+                format!("ivalue{}", i)
+            };
+            tokens.push(Token::new_atom("int"));
+            tokens.push(Token::new_atom(name));
+            tokens.push(Token::new_atom(";"));
+        }
+        tokens.push(Token::new_atom("}"));
+        tokens
+    };
+    let diff = get_type_diff(&make_struct("ivalue10"), &make_struct("changed"), 2, false).unwrap();
+    assert_eq!(
+        diff,
+        crate::string_vec!(
+            "@@ -10,5 +10,5 @@",
+            " \tint ivalue8;",
+            " \tint ivalue9;",
+            "-\tint ivalue10;",
+            "+\tint changed;",
+            " \tint ivalue11;",
+            " \tint ivalue12;" //
+        )
+    );
+}
+
+#[test]
+fn compare_typedef_retarget() {
+    // Check that a change of a typedef target is reported for the typedef itself by default, and
+    // in the bodies of its users when typedefs are expanded.
+    let mut syms = SymCorpus::new();
+    syms.load_buffer(
+        Path::new("test.symtypes"),
+        concat!(
+            "t#foo typedef int foo\n",
+            "s#bar struct bar { t#foo a ; }\n",
+            "baz int baz ( s#bar * )\n", //
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+    let mut syms2 = SymCorpus::new();
+    syms2
+        .load_buffer(
+            Path::new("test.symtypes"),
+            concat!(
+                "t#foo typedef long foo\n",
+                "s#bar struct bar { t#foo a ; }\n",
+                "baz int baz ( s#bar * )\n", //
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+
+    let result = syms
+        .get_changes(&syms2, &CompareOptions::default(), 1)
+        .unwrap();
+    let mut changed = result.changes.keys().copied().collect::<Vec<_>>();
+    changed.sort();
+    assert_eq!(changed, vec!["t#foo"]);
+
+    let options = CompareOptions {
+        expand_typedefs: true,
+        ..Default::default()
+    };
+    let result = syms.get_changes(&syms2, &options, 1).unwrap();
+    let mut changed = result.changes.keys().copied().collect::<Vec<_>>();
+    changed.sort();
+    assert_eq!(changed, vec!["s#bar"]);
+    let change = &result.changes["s#bar"][0];
+    assert_eq!(
+        get_type_diff(&change.tokens, &change.other_tokens, 3, false).unwrap(),
+        crate::string_vec!(
+            "@@ -1,3 +1,3 @@",
+            " struct bar {",
+            "-\tint a;",
+            "+\tlong a;",
+            " }" //
+        )
+    );
+}
+
+#[test]
+fn tail_append() {
+    // Check that a struct change which only appends a member at the end is recognized as a tail
+    // append.
+    assert!(is_tail_append(
+        &vec![
+            Token::new_atom("struct"),
+            Token::new_atom("test"),
+            Token::new_atom("{"),
+            Token::new_atom("int"),
+            Token::new_atom("ivalue1"),
+            Token::new_atom(";"),
+            Token::new_atom("}"),
+        ],
+        &vec![
+            Token::new_atom("struct"),
+            Token::new_atom("test"),
+            Token::new_atom("{"),
+            Token::new_atom("int"),
+            Token::new_atom("ivalue1"),
+            Token::new_atom(";"),
+            Token::new_atom("int"),
+            Token::new_atom("ivalue2"),
+            Token::new_atom(";"),
+            Token::new_atom("}"),
+        ],
+    ));
+}
+
+#[test]
+fn tail_append_mid_insert() {
+    // Check that a struct change which inserts a member in the middle is not recognized as a tail
+    // append.
+    assert!(!is_tail_append(
+        &vec![
+            Token::new_atom("struct"),
+            Token::new_atom("test"),
+            Token::new_atom("{"),
+            Token::new_atom("int"),
+            Token::new_atom("ivalue1"),
+            Token::new_atom(";"),
+            Token::new_atom("}"),
+        ],
+        &vec![
+            Token::new_atom("struct"),
+            Token::new_atom("test"),
+            Token::new_atom("{"),
+            Token::new_atom("int"),
+            Token::new_atom("ivalue2"),
+            Token::new_atom(";"),
+            Token::new_atom("int"),
+            Token::new_atom("ivalue1"),
+            Token::new_atom(";"),
+            Token::new_atom("}"),
+        ],
+    ));
+}
+
+#[test]
+fn format_side_by_side() {
+    // Check that the side-by-side renderer shows the symbol name followed by both variants placed
+    // next to each other.
+    let lines = SideBySideRenderer
+        .render(
+            "s#test",
+            &vec![
+                Token::new_atom("struct"),
+                Token::new_atom("test"),
+                Token::new_atom("{"),
+                Token::new_atom("int"),
+                Token::new_atom("ivalue1"),
+                Token::new_atom(";"),
+                Token::new_atom("}"),
+            ],
+            &vec![
+                Token::new_atom("struct"),
+                Token::new_atom("test"),
+                Token::new_atom("{"),
+                Token::new_atom("long"),
+                Token::new_atom("ivalue1"),
+                Token::new_atom(";"),
+                Token::new_atom("int"),
+                Token::new_atom("ivalue2"),
+                Token::new_atom(";"),
+                Token::new_atom("}"),
+            ],
+        )
+        .unwrap();
+    assert_eq!(
+        lines,
+        crate::string_vec!(
+            "s#test",
+            "struct test {      struct test {",
+            "    int ivalue1; |     long ivalue1;",
+            "                 >     int ivalue2;",
+            "}                  }" //
+        )
+    );
+}
+
+#[test]
+fn compare_anon_names() {
+    // Check that references to anonymous types which differ only in their generated numeric
+    // suffix are considered equal when anonymous names are ignored.
+    let mut syms = SymCorpus::new();
+    syms.load_buffer(
+        Path::new("test.symtypes"),
+        concat!(
+            "s#__anon_1 struct { int a ; }\n",
+            "bar int bar ( s#__anon_1 * )\n", //
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+    let mut syms2 = SymCorpus::new();
+    syms2
+        .load_buffer(
+            Path::new("test.symtypes"),
+            concat!(
+                "s#__anon_7 struct { int a ; }\n",
+                "bar int bar ( s#__anon_7 * )\n", //
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+
+    let result = syms
+        .get_changes(&syms2, &CompareOptions::default(), 1)
+        .unwrap();
+    let changed = result.changes.keys().copied().collect::<Vec<_>>();
+    assert_eq!(changed, vec!["bar"]);
+
+    let options = CompareOptions {
+        anon_name_prefixes: CompareOptions::default_anon_name_prefixes(),
+        ..Default::default()
+    };
+    let result = syms.get_changes(&syms2, &options, 1).unwrap();
+    assert!(result.changes.is_empty());
+}
+
+#[test]
+fn compare_affected_files() {
+    // Check that a changed type records all files in which it is reached from an export.
+    let mut syms = SymCorpus::new();
+    syms.load_buffer(
+        Path::new("test.symtypes"),
+        concat!(
+            "s#foo struct foo { int a ; }\n",
+            "bar int bar ( s#foo )\n", //
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+    syms.load_buffer(
+        Path::new("test2.symtypes"),
+        concat!(
+            "s#foo struct foo { int a ; }\n",
+            "baz int baz ( s#foo )\n", //
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+    let mut syms2 = SymCorpus::new();
+    syms2
+        .load_buffer(
+            Path::new("test.symtypes"),
+            concat!(
+                "s#foo struct foo { long a ; }\n",
+                "bar int bar ( s#foo )\n",
+                "baz int baz ( s#foo )\n", //
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+
+    let result = syms
+        .get_changes(&syms2, &CompareOptions::default(), 1)
+        .unwrap();
+    assert_eq!(result.changes.len(), 1);
+    let changes = &result.changes["s#foo"];
+    assert_eq!(changes.len(), 1);
+    assert_eq!(
+        changes[0].files.iter().copied().collect::<Vec<_>>(),
+        vec![Path::new("test.symtypes"), Path::new("test2.symtypes")]
+    );
+}
+
+#[test]
+fn remove_file_reindex() {
+    // Check that exports resolve to their files after a file is removed from the corpus.
+    let mut syms = SymCorpus::new();
+    for (path, export) in [
+        ("test.symtypes", "foo"),
+        ("test2.symtypes", "bar"),
+        ("test3.symtypes", "baz"),
+    ] {
+        syms.load_buffer(
+            Path::new(path),
+            format!("{} int {} ( )\n", export, export).as_bytes(),
+        )
+        .unwrap();
+    }
+
+    assert!(syms.remove_file(Path::new("test2.symtypes")));
+    assert!(!syms.remove_file(Path::new("test2.symtypes")));
+    assert_eq!(syms.num_files(), 2);
+    assert_eq!(syms.num_exports(), 2);
+    for (name, &file_idx) in &syms.exports {
+        assert!(syms.files[file_idx].records.contains_key(name));
+    }
+    assert_eq!(
+        syms.files[syms.exports["baz"]].path,
+        Path::new("test3.symtypes")
+    );
+}
+
+#[test]
+fn compare_ptr_array_equiv() {
+    // Check that a trailing flexible array member is equal to a pointer member with the
+    // `ptr_array_equiv` option.
+    let mut syms = SymCorpus::new();
+    syms.load_buffer(
+        Path::new("test.symtypes"),
+        concat!(
+            "s#foo struct foo { int a ; int b [ ] ; }\n",
+            "bar int bar ( s#foo )\n", //
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+    let mut syms2 = SymCorpus::new();
+    syms2
+        .load_buffer(
+            Path::new("test.symtypes"),
+            concat!(
+                "s#foo struct foo { int a ; int * b ; }\n",
+                "bar int bar ( s#foo )\n", //
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+
+    let result = syms
+        .get_changes(&syms2, &CompareOptions::default(), 1)
+        .unwrap();
+    assert_eq!(result.changes.len(), 1);
+
+    let options = CompareOptions {
+        ptr_array_equiv: true,
+        ..Default::default()
+    };
+    let result = syms.get_changes(&syms2, &options, 1).unwrap();
+    assert!(result.changes.is_empty());
+}
+
+#[test]
+fn compare_ignore_reordering() {
+    // Check that reordered enumerators with explicit values are equal with the
+    // `ignore_reordering` option, while reordered enumerators with implicit values and reordered
+    // struct members are still reported.
+    let mut syms = SymCorpus::new();
+    syms.load_buffer(
+        Path::new("test.symtypes"),
+        concat!(
+            "e#foo enum foo { A = 1 , B = ( 1 << 2 ) , C = 8 }\n",
+            "e#baz enum baz { X , Y }\n",
+            "s#qux struct qux { int a ; long b ; }\n",
+            "bar int bar ( e#foo , e#baz , s#qux )\n", //
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+    let mut syms2 = SymCorpus::new();
+    syms2
+        .load_buffer(
+            Path::new("test.symtypes"),
+            concat!(
+                "e#foo enum foo { C = 8 , A = 1 , B = ( 1 << 2 ) }\n",
+                "e#baz enum baz { Y , X }\n",
+                "s#qux struct qux { long b ; int a ; }\n",
+                "bar int bar ( e#foo , e#baz , s#qux )\n", //
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+
+    let result = syms
+        .get_changes(&syms2, &CompareOptions::default(), 1)
+        .unwrap();
+    let mut names = result.changes.keys().copied().collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(names, vec!["e#baz", "e#foo", "s#qux"]);
+
+    let options = CompareOptions {
+        ignore_reordering: true,
+        ..Default::default()
+    };
+    let result = syms.get_changes(&syms2, &options, 1).unwrap();
+    let mut names = result.changes.keys().copied().collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(names, vec!["e#baz", "s#qux"]);
+}
+
+#[test]
+fn compare_decls_only() {
+    // Check that the `decls_only` option ignores changes in type bodies but still detects changes
+    // in export declarations.
+    let mut syms = SymCorpus::new();
+    syms.load_buffer(
+        Path::new("test.symtypes"),
+        concat!(
+            "s#foo struct foo { int a ; }\n",
+            "s#baz struct baz { int b ; }\n",
+            "bar int bar ( s#foo )\n",
+            "qux int qux ( s#foo )\n", //
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+    let mut syms2 = SymCorpus::new();
+    syms2
+        .load_buffer(
+            Path::new("test.symtypes"),
+            concat!(
+                "s#foo struct foo { long a ; }\n",
+                "s#baz struct baz { int b ; }\n",
+                "bar int bar ( s#foo )\n",
+                "qux int qux ( s#baz )\n", //
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+
+    let result = syms
+        .get_changes(&syms2, &CompareOptions::default(), 1)
+        .unwrap();
+    let mut names = result.changes.keys().copied().collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(names, vec!["qux", "s#foo"]);
+
+    let options = CompareOptions {
+        decls_only: true,
+        ..Default::default()
+    };
+    let result = syms.get_changes(&syms2, &options, 1).unwrap();
+    let names = result.changes.keys().copied().collect::<Vec<_>>();
+    assert_eq!(names, vec!["qux"]);
+}
+
+#[test]
+fn compare_shallow() {
+    // Check that the `shallow` option compares exports and their directly referenced types, but
+    // not types referenced deeper, and that it differs from the default and `decls_only` modes.
+    let mut syms = SymCorpus::new();
+    syms.load_buffer(
+        Path::new("test.symtypes"),
+        concat!(
+            "s#inner struct inner { int a ; }\n",
+            "s#foo struct foo { s#inner a ; }\n",
+            "s#baz struct baz { int b ; }\n",
+            "bar int bar ( s#foo )\n",
+            "baz int baz ( s#baz )\n",
+            "qux int qux ( int )\n", //
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+    let mut syms2 = SymCorpus::new();
+    syms2
+        .load_buffer(
+            Path::new("test.symtypes"),
+            concat!(
+                "s#inner struct inner { long a ; }\n",
+                "s#foo struct foo { s#inner a ; }\n",
+                "s#baz struct baz { long b ; }\n",
+                "bar int bar ( s#foo )\n",
+                "baz int baz ( s#baz )\n",
+                "qux int qux ( long )\n", //
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+
+    let get_names = |options: &CompareOptions| {
+        let result = syms.get_changes(&syms2, options, 1).unwrap();
+        let mut names = result.changes.keys().copied().collect::<Vec<_>>();
+        names.sort();
+        names
+    };
+    assert_eq!(
+        get_names(&CompareOptions::default()),
+        vec!["qux", "s#baz", "s#inner"]
+    );
+    let options = CompareOptions {
+        shallow: true,
+        ..Default::default()
+    };
+    assert_eq!(get_names(&options), vec!["qux", "s#baz"]);
+    let options = CompareOptions {
+        decls_only: true,
+        ..Default::default()
+    };
+    assert_eq!(get_names(&options), vec!["qux"]);
+}
+
+#[test]
+fn intern_tokens() {
+    // Check that identical strings resolve to the same interned copy.
+    let mut interner = Interner::default();
+    let a = interner.intern(&Arc::from("int"));
+    let b = interner.intern(&Arc::from("int"));
+    let c = interner.intern(&Arc::from("long"));
+    assert!(Arc::ptr_eq(&a, &b));
+    assert!(!Arc::ptr_eq(&a, &c));
+    assert_eq!(interner.strings.len(), 2);
+
+    // Check that tokens of types loaded from different files share storage, and that the corpus
+    // still reads and writes the same data.
+    let mut syms = SymCorpus::new();
+    syms.load_buffer(
+        Path::new("test.symtypes"),
+        concat!(
+            "s#foo struct foo { int a ; }\n",
+            "bar int bar ( s#foo )\n", //
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+    syms.load_buffer(
+        Path::new("test2.symtypes"),
+        concat!(
+            "s#foo struct foo { int a ; }\n",
+            "baz int baz ( s#foo , int )\n", //
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+    let foo = &syms.types["s#foo"][0];
+    let bar = &syms.types["bar"][0];
+    let baz = &syms.types["baz"][0];
+    match (&foo[3], &bar[0], &baz[0], &baz[5]) {
+        (Token::Atom(a), Token::Atom(b), Token::Atom(c), Token::Atom(d)) => {
+            assert_eq!(&**a, "int");
+            assert!(Arc::ptr_eq(a, b) && Arc::ptr_eq(a, c) && Arc::ptr_eq(a, d));
+        }
+        tokens => panic!("unexpected tokens {:?}", tokens),
+    }
+    match (&bar[3], &baz[3]) {
+        (Token::TypeRef(a), Token::TypeRef(b)) => assert!(Arc::ptr_eq(a, b)),
+        tokens => panic!("unexpected tokens {:?}", tokens),
+    }
+    assert_eq!(
+        syms.to_consolidated_string().unwrap(),
+        concat!(
+            "s#foo struct foo { int a ; }\n",
+            "bar int bar ( s#foo )\n",
+            "baz int baz ( s#foo , int )\n",
+            "F#test.symtypes bar\n",
+            "F#test2.symtypes baz\n", //
+        )
+    );
+}
+
+#[test]
+fn check_memory_usage() {
+    // Check that the memory limit is enforced exactly at its boundary.
+    let max_memory = 64 * 1024 * 1024;
+    assert!(SymCorpus::check_memory_usage(max_memory - 1024, max_memory).is_ok());
+    assert!(SymCorpus::check_memory_usage(max_memory, max_memory).is_ok());
+    let result = SymCorpus::check_memory_usage(max_memory + 1024, max_memory);
+    match result {
+        Err(crate::Error::Parse(desc)) => assert_eq!(
+            desc,
+            "Resident memory of 65 MB exceeds the limit of 64 MB, raise the limit to load the input"
+        ),
+        result => panic!("assertion failed: {:?} is not a memory limit error", result),
+    }
+}
+
+#[test]
+fn collect_symfiles_skip_errors() {
+    // Check that a directory which cannot be read fails the collection, unless errors are skipped,
+    // in which case the directory is reported as a diagnostic. A path below a regular file is
+    // unreadable regardless of the permissions of the user.
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml/sub");
+    let mut symfiles = Vec::new();
+    let result = SymCorpus::collect_symfiles(&path, &path, &LoadOptions::default(), &mut symfiles);
+    match result {
+        Err(crate::Error::IO { desc, .. }) => assert_eq!(
+            desc,
+            format!("Failed to read directory '{}'", path.display())
+        ),
+        result => panic!("assertion failed: {:?} is not an I/O error", result),
+    }
+
+    let diagnostics = Arc::new(Mutex::new(Vec::new()));
+    let options = LoadOptions {
+        skip_errors: true,
+        diagnostic_callback: Some({
+            let diagnostics = Arc::clone(&diagnostics);
+            Arc::new(move |diagnostic: &Diagnostic| {
+                diagnostics.lock().unwrap().push(diagnostic.clone())
+            })
+        }),
+        ..Default::default()
+    };
+    SymCorpus::collect_symfiles(&path, &path, &options, &mut symfiles).unwrap();
+    assert!(symfiles.is_empty());
+    let diagnostics = diagnostics.lock().unwrap();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].severity, Severity::Warning);
+    assert_eq!(diagnostics[0].code, "SKIPPED_ENTRY");
+    assert!(diagnostics[0]
+        .message
+        .starts_with("Failed to read directory: "));
+    assert_eq!(diagnostics[0].file.as_deref(), Some(path.as_path()));
+}
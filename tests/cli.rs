@@ -0,0 +1,86 @@
+// Copyright (C) 2024 SUSE LLC <petr.pavlu@suse.com>
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+fn create_test_dir(name: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+    if dir.exists() {
+        fs::remove_dir_all(&dir).unwrap();
+    }
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn ksymtypes(args: &[&str], current_dir: &Path) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_ksymtypes"))
+        .args(args)
+        .current_dir(current_dir)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn compare_tail_append_status() {
+    // Check that a struct change which only appends members fails the comparison, unless the
+    // --allow-tail-append option classifies it as additive.
+    let test_dir = create_test_dir("cli_compare_tail_append_status");
+    fs::write(
+        test_dir.join("a.symtypes"),
+        "s#foo struct foo { int a ; }\nbar int bar ( s#foo )\n",
+    )
+    .unwrap();
+    fs::write(
+        test_dir.join("b.symtypes"),
+        "s#foo struct foo { int a ; int b ; }\nbar int bar ( s#foo )\n",
+    )
+    .unwrap();
+
+    let output = ksymtypes(&["compare", "a.symtypes", "b.symtypes"], &test_dir);
+    assert_eq!(output.status.code(), Some(1));
+
+    let output = ksymtypes(
+        &["compare", "--allow-tail-append", "a.symtypes", "b.symtypes"],
+        &test_dir,
+    );
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("Type s#foo is only extended by appended members\n"));
+}
+
+#[test]
+fn compare_reverse() {
+    // Check that the --reverse option swaps the roles of the two compare sources.
+    let test_dir = create_test_dir("cli_compare_reverse");
+    fs::write(test_dir.join("a.symtypes"), "foo int foo ( )\n").unwrap();
+    fs::write(
+        test_dir.join("b.symtypes"),
+        "foo int foo ( )\nbar int bar ( )\n",
+    )
+    .unwrap();
+
+    let output = ksymtypes(&["compare", "a.symtypes", "b.symtypes"], &test_dir);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        concat!(
+            "Export bar is present in B but not in A\n",
+            "1 exports added, 0 exports removed, 0 types changed\n", //
+        )
+    );
+
+    let output = ksymtypes(
+        &["compare", "--reverse", "a.symtypes", "b.symtypes"],
+        &test_dir,
+    );
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        concat!(
+            "Export bar is present in A but not in B\n",
+            "0 exports added, 1 exports removed, 0 types changed\n", //
+        )
+    );
+}
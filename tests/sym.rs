@@ -1,8 +1,19 @@
 // Copyright (C) 2024 SUSE LLC <petr.pavlu@suse.com>
 // SPDX-License-Identifier: GPL-2.0-or-later
 
-use ksymtypes::sym::SymCorpus;
-use std::path::Path;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use ksymtypes::sym::{
+    format_record, parse_record, Change, ChangeSeverity, CompareOptions, CompareSummary,
+    ConsolidateOptions, ConsolidateStats, ConsolidatedFile, Diagnostic, InputFormat, LoadOptions,
+    Severity, SortOrder, SymCorpus, Token, TypeKind, UnifiedRenderer,
+};
+use serde_json::json;
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::{fs, io};
 
 macro_rules! assert_parse_err {
     ($result:expr, $exp_desc:expr) => {
@@ -16,6 +27,51 @@ macro_rules! assert_parse_err {
     };
 }
 
+/// Creates an empty directory for a given test in the temporary directory of the integration tests.
+fn create_test_dir(name: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+    if dir.exists() {
+        fs::remove_dir_all(&dir).unwrap();
+    }
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn parse_format_record() {
+    // Check that a single record is parsed into its tokens and formatted back in the normalized
+    // form.
+    let line = "t#u32   typedef unsigned int u32";
+    let (name, tokens) = parse_record(line).unwrap();
+    assert_eq!(name, "t#u32");
+    assert_eq!(
+        tokens,
+        vec![
+            Token::Atom("typedef".into()),
+            Token::Atom("unsigned".into()),
+            Token::Atom("int".into()),
+            Token::Atom("u32".into()),
+        ]
+    );
+    assert_eq!(
+        format_record(&name, &tokens),
+        "t#u32 typedef unsigned int u32"
+    );
+
+    let line = "s#foo@1 struct foo { t#u32 a ; }";
+    let (name, tokens) = parse_record(line).unwrap();
+    assert_eq!(name, "s#foo@1");
+    assert_eq!(tokens[3], Token::TypeRef("t#u32".into()));
+    assert_eq!(format_record(&name, &tokens), line);
+
+    // Check that malformed records are rejected.
+    assert_parse_err!(parse_record("  "), "Expected a record name");
+    assert_parse_err!(
+        parse_record("F#test.symtypes bar"),
+        "Expected a type or export record, found file record 'F#test.symtypes'"
+    );
+}
+
 #[test]
 fn read_empty_record() {
     // Check that empty records are rejected when reading a file.
@@ -58,16 +114,79 @@ fn read_duplicate_file_record() {
     );
 }
 
+#[test]
+fn read_merged_file_records() {
+    // Check that F# records with duplicate names are merged when requested.
+    let input = concat!(
+        "s#foo@0 struct foo { int a ; }\n",
+        "s#foo@1 struct foo { long a ; }\n",
+        "bar int bar ( s#foo )\n",
+        "baz int baz ( )\n",
+        "qux int qux ( s#foo )\n",
+        "F#test.symtypes s#foo@0 bar\n",
+        "F#test.symtypes baz\n",
+        "F#test2.symtypes s#foo@1 qux\n", //
+    );
+    let options = LoadOptions {
+        merge_file_records: true,
+        ..Default::default()
+    };
+    let mut syms = SymCorpus::new();
+    syms.load_buffer_with(
+        Path::new("file.symtypes"),
+        input.as_bytes(),
+        InputFormat::Auto,
+        &options,
+    )
+    .unwrap();
+    assert_eq!(
+        syms.to_consolidated_string().unwrap(),
+        concat!(
+            "s#foo@0 struct foo { int a ; }\n",
+            "s#foo@1 struct foo { long a ; }\n",
+            "bar int bar ( s#foo )\n",
+            "baz int baz ( )\n",
+            "qux int qux ( s#foo )\n",
+            "F#test.symtypes s#foo@0 bar baz\n",
+            "F#test2.symtypes s#foo@1 qux\n", //
+        )
+    );
+
+    // Check that the merged records cannot reference different variants of the same type.
+    let input = concat!(
+        "s#foo@0 struct foo { int a ; }\n",
+        "s#foo@1 struct foo { long a ; }\n",
+        "bar int bar ( s#foo )\n",
+        "baz int baz ( s#foo )\n",
+        "F#test.symtypes s#foo@0 bar\n",
+        "F#test.symtypes s#foo@1 baz\n", //
+    );
+    let mut syms = SymCorpus::new();
+    let result = syms.load_buffer_with(
+        Path::new("file.symtypes"),
+        input.as_bytes(),
+        InputFormat::Auto,
+        &options,
+    );
+    assert_parse_err!(
+        result,
+        "file.symtypes:6: Type s#foo has a different variant in another record of file 'test.symtypes'"
+    );
+}
+
 #[test]
 fn read_invalid_file_record_ref() {
-    // Check that an F# record referencing a type in form '<base_name>' is rejected if the type is
-    // not known.
+    // Check that an F# record referencing an export in form '<base_name>' is rejected if the export
+    // is not declared.
     let input = concat!(
         "F#test.symtypes bar\n", //
     );
     let mut syms = SymCorpus::new();
     let result = syms.load_buffer(&Path::new("file.symtypes"), input.as_bytes());
-    assert_parse_err!(result, "file.symtypes:1: Type bar is not known");
+    assert_parse_err!(
+        result,
+        "file.symtypes:1: Export bar listed by file 'test.symtypes' is not declared"
+    );
 }
 
 #[test]
@@ -82,6 +201,30 @@ fn read_invalid_file_record_ref2() {
     assert_parse_err!(result, "file.symtypes:1: Type bar@0 is not known");
 }
 
+#[test]
+fn read_undeclared_file_record_export() {
+    // Check that an F# record listing an export without a declaration is rejected, while an
+    // unknown type is reported as such.
+    let input = concat!(
+        "bar int bar ( )\n",
+        "F#test.symtypes bar baz\n", //
+    );
+    let mut syms = SymCorpus::new();
+    let result = syms.load_buffer(Path::new("file.symtypes"), input.as_bytes());
+    assert_parse_err!(
+        result,
+        "file.symtypes:2: Export baz listed by file 'test.symtypes' is not declared"
+    );
+
+    let input = concat!(
+        "bar int bar ( )\n",
+        "F#test.symtypes bar s#baz\n", //
+    );
+    let mut syms = SymCorpus::new();
+    let result = syms.load_buffer(Path::new("file.symtypes"), input.as_bytes());
+    assert_parse_err!(result, "file.symtypes:2: Type s#baz is not known");
+}
+
 #[test]
 fn read_invalid_file_record_ref3() {
     // Check that an F# record referencing a type in form '<base_name>@<variant_idx>' is rejected if
@@ -181,7 +324,98 @@ fn read_write_differing_struct() {
     assert_eq!(
         String::from_utf8(out).unwrap(),
         concat!(
-            "s#foo@0 struct foo { int a ; }\n",
+            "s#foo@0 struct foo { UNKNOWN }\n",
+            "s#foo@1 struct foo { int a ; }\n",
+            "bar int bar ( s#foo )\n",
+            "baz int baz ( s#foo )\n",
+            "F#test.symtypes s#foo@1 bar\n",
+            "F#test2.symtypes s#foo@0 baz\n", //
+        )
+    );
+
+    // Check the statistics of the deduplication.
+    let stats = syms.consolidate_stats();
+    assert_eq!(
+        stats,
+        ConsolidateStats {
+            num_types: 3,
+            num_variants: 4,
+            num_multi_variant_types: 1,
+            num_input_records: 4,
+        }
+    );
+    assert_eq!(stats.ratio(), 1.0);
+    assert_eq!(
+        stats.to_string(),
+        concat!(
+            "Types: 3\n",
+            "Unique type variants: 4\n",
+            "Types with multiple variants: 1\n",
+            "Records: 4 input, 4 output, ratio 1.00\n", //
+        )
+    );
+}
+
+#[test]
+fn multi_variant_types() {
+    // Check that types with multiple variants are listed with their variant count, sorted by the
+    // count in descending order.
+    let mut syms = SymCorpus::new();
+    syms.load_buffer(
+        Path::new("test.symtypes"),
+        concat!(
+            "s#foo struct foo { int a ; }\n",
+            "s#bar struct bar { int a ; }\n",
+            "baz int baz ( s#foo , s#bar )\n", //
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+    syms.load_buffer(
+        Path::new("test2.symtypes"),
+        concat!(
+            "s#foo struct foo { UNKNOWN }\n",
+            "s#bar struct bar { int a ; }\n",
+            "qux int qux ( s#foo , s#bar )\n", //
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+    syms.load_buffer(
+        Path::new("test3.symtypes"),
+        concat!(
+            "s#foo struct foo { long a ; }\n",
+            "s#bar struct bar { long a ; }\n",
+            "quux int quux ( s#foo , s#bar )\n", //
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+    assert_eq!(syms.multi_variant_types(), vec![("s#foo", 3), ("s#bar", 2)]);
+}
+
+#[test]
+fn read_write_consolidated_identity() {
+    // Check that a consolidated file with multiple variants and implicit references reads back
+    // identically.
+    let input = concat!(
+        "s#foo@0 struct foo { s#bar a ; }\n",
+        "s#foo@1 struct foo { UNKNOWN }\n",
+        "s#bar struct bar { int a ; }\n",
+        "bar int bar ( s#foo )\n",
+        "baz int baz ( s#foo )\n",
+        "F#test.symtypes s#foo@0 bar\n",
+        "F#test2.symtypes s#foo@1 baz\n", //
+    );
+    let mut syms = SymCorpus::new();
+    syms.load_buffer(Path::new("file.symtypes"), input.as_bytes())
+        .unwrap();
+    let output = syms.to_consolidated_string().unwrap();
+    assert_eq!(
+        output,
+        concat!(
+            "s#bar struct bar { int a ; }\n",
+            "s#foo@0 struct foo { s#bar a ; }\n",
             "s#foo@1 struct foo { UNKNOWN }\n",
             "bar int bar ( s#foo )\n",
             "baz int baz ( s#foo )\n",
@@ -189,4 +423,3324 @@ fn read_write_differing_struct() {
             "F#test2.symtypes s#foo@1 baz\n", //
         )
     );
+
+    let mut syms2 = SymCorpus::new();
+    syms2
+        .load_buffer(Path::new("file.symtypes"), output.as_bytes())
+        .unwrap();
+    assert_eq!(syms2.to_consolidated_string().unwrap(), output);
+}
+
+#[test]
+fn write_include_orphans() {
+    // Check that types not reachable from any export are written only when requested.
+    let mut syms = SymCorpus::new();
+    syms.load_buffer(
+        Path::new("test.symtypes"),
+        concat!(
+            "s#foo struct foo { int a ; }\n",
+            "s#orphan struct orphan { int a ; }\n",
+            "bar int bar ( s#foo )\n", //
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+    let mut out = Vec::new();
+    syms.write_consolidated(&mut out).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        concat!(
+            "s#foo struct foo { int a ; }\n",
+            "bar int bar ( s#foo )\n",
+            "F#test.symtypes bar\n", //
+        )
+    );
+
+    let options = ConsolidateOptions {
+        include_orphans: true,
+        ..Default::default()
+    };
+    let mut out = Vec::new();
+    syms.write_consolidated_with(&mut out, &options).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        concat!(
+            "s#foo struct foo { int a ; }\n",
+            "s#orphan struct orphan { int a ; }\n",
+            "bar int bar ( s#foo )\n",
+            "F#test.symtypes bar\n", //
+        )
+    );
+}
+
+#[test]
+fn read_forced_consolidated() {
+    // Check that data without any F# record is treated as a consolidated file when the format is
+    // forced.
+    let input = concat!(
+        "s#foo@0 struct foo { int a ; }\n",
+        "bar int bar ( s#foo )\n", //
+    );
+    let mut syms = SymCorpus::new();
+    syms.load_buffer_as(
+        Path::new("file.symtypes"),
+        input.as_bytes(),
+        InputFormat::Consolidated,
+    )
+    .unwrap();
+    assert_eq!(syms.num_files(), 0);
+    assert_eq!(syms.num_exports(), 0);
+    assert_eq!(syms.num_types(), 2);
+    assert_eq!(syms.to_consolidated_string().unwrap(), "");
+
+    // Check that the same data is treated as a single file when detected automatically.
+    let mut syms = SymCorpus::new();
+    syms.load_buffer(Path::new("file.symtypes"), input.as_bytes())
+        .unwrap();
+    assert_eq!(syms.num_files(), 1);
+    assert_eq!(syms.num_exports(), 1);
+}
+
+#[test]
+fn read_stdin_buffer() {
+    // Check that data read through the stdin reader path is recorded under the `<stdin>` file, both
+    // for a single and a consolidated input.
+    let input = concat!(
+        "s#foo struct foo { int a ; }\n",
+        "bar int bar ( s#foo )\n", //
+    );
+    let mut syms = SymCorpus::new();
+    syms.load_buffer_as(Path::new("<stdin>"), input.as_bytes(), InputFormat::Auto)
+        .unwrap();
+    let output = syms.to_consolidated_string().unwrap();
+    assert_eq!(
+        output,
+        concat!(
+            "s#foo struct foo { int a ; }\n",
+            "bar int bar ( s#foo )\n",
+            "F#<stdin> bar\n", //
+        )
+    );
+
+    let mut syms = SymCorpus::new();
+    syms.load_buffer_as(Path::new("<stdin>"), output.as_bytes(), InputFormat::Auto)
+        .unwrap();
+    assert_eq!(syms.num_files(), 1);
+    assert_eq!(syms.to_consolidated_string().unwrap(), output);
+}
+
+#[test]
+fn check_max_variants() {
+    // Check that a type with more variants than the specified limit is reported.
+    let mut syms = SymCorpus::new();
+    syms.load_buffer(
+        Path::new("test.symtypes"),
+        concat!(
+            "s#foo struct foo { int a ; }\n",
+            "bar int bar ( s#foo )\n", //
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+    syms.load_buffer(
+        Path::new("test2.symtypes"),
+        concat!(
+            "s#foo struct foo { UNKNOWN }\n",
+            "baz int baz ( s#foo )\n", //
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+    assert!(syms.check_max_variants(2).is_ok());
+    let result = syms.check_max_variants(1);
+    assert_parse_err!(
+        result,
+        "1 type(s) exceed the limit of 1 variant(s): s#foo (2)"
+    );
+}
+
+#[test]
+fn check_single_variant() {
+    // Check that all types which differ between files are reported.
+    let mut syms = SymCorpus::new();
+    syms.load_buffer(
+        Path::new("test.symtypes"),
+        concat!(
+            "s#foo struct foo { int a ; }\n",
+            "s#bar struct bar { int a ; }\n",
+            "bar int bar ( s#foo , s#bar )\n", //
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+    assert!(syms.check_single_variant().is_ok());
+    syms.load_buffer(
+        Path::new("test2.symtypes"),
+        concat!(
+            "s#foo struct foo { long a ; }\n",
+            "s#bar struct bar { long a ; }\n",
+            "baz int baz ( s#foo , s#bar )\n", //
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+    let result = syms.check_single_variant();
+    assert_parse_err!(result, "2 type(s) have multiple variants: s#bar, s#foo");
+}
+
+#[test]
+fn write_lenient_dangling_ref() {
+    // Check that a reference to an unknown type is skipped in the lenient mode and the resolvable
+    // types are still written.
+    let mut syms = SymCorpus::new();
+    syms.load_buffer(
+        Path::new("test.symtypes"),
+        concat!(
+            "s#foo struct foo { s#missing * a ; }\n",
+            "bar int bar ( s#foo )\n",
+            "baz int baz ( int )\n", //
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+    let diagnostics = Arc::new(Mutex::new(Vec::new()));
+    let diagnostics_clone = diagnostics.clone();
+    let options = ConsolidateOptions {
+        lenient: true,
+        diagnostic_callback: Some(Arc::new(move |diagnostic: &Diagnostic| {
+            diagnostics_clone.lock().unwrap().push(diagnostic.clone())
+        })),
+        ..Default::default()
+    };
+    let mut out = Vec::new();
+    syms.write_consolidated_with(&mut out, &options).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        concat!(
+            "s#foo struct foo { s#missing * a ; }\n",
+            "bar int bar ( s#foo )\n",
+            "baz int baz ( int )\n",
+            "F#test.symtypes bar baz\n", //
+        )
+    );
+
+    // Check that the skipped reference is reported as a diagnostic.
+    assert_eq!(
+        *diagnostics.lock().unwrap(),
+        vec![Diagnostic {
+            severity: Severity::Warning,
+            code: "DANGLING_REF",
+            message: "Type s#missing is not known, referenced via bar -> s#foo".to_string(),
+            file: Some(PathBuf::from("test.symtypes")),
+        }]
+    );
+}
+
+#[test]
+fn read_manifest() {
+    // Check that fragments listed for a logical file in a manifest are merged into one file.
+    let dir = create_test_dir("read_manifest");
+    fs::write(
+        dir.join("a.symtypes"),
+        concat!(
+            "s#foo struct foo { int a ; }\n",
+            "bar int bar ( s#foo )\n", //
+        ),
+    )
+    .unwrap();
+    fs::write(
+        dir.join("b.symtypes"),
+        concat!(
+            "s#foo struct foo { int a ; }\n",
+            "baz int baz ( s#foo )\n", //
+        ),
+    )
+    .unwrap();
+    fs::write(
+        dir.join("manifest"),
+        concat!(
+            "# Test manifest\n",
+            "test.symtypes: a.symtypes, b.symtypes\n", //
+        ),
+    )
+    .unwrap();
+
+    let mut syms = SymCorpus::new();
+    syms.load_manifest(&dir.join("manifest")).unwrap();
+    assert_eq!(
+        syms.to_consolidated_string().unwrap(),
+        concat!(
+            "s#foo struct foo { int a ; }\n",
+            "bar int bar ( s#foo )\n",
+            "baz int baz ( s#foo )\n",
+            "F#test.symtypes bar baz\n", //
+        )
+    );
+}
+
+#[test]
+fn read_manifest_duplicate_name() {
+    // Check that a logical file name listed multiple times in a manifest is rejected.
+    let dir = create_test_dir("read_manifest_duplicate_name");
+    fs::write(dir.join("a.symtypes"), "bar int bar ( )\n").unwrap();
+    fs::write(dir.join("b.symtypes"), "baz int baz ( )\n").unwrap();
+    fs::write(
+        dir.join("manifest"),
+        concat!(
+            "test.symtypes: a.symtypes\n",
+            "test.symtypes: b.symtypes\n", //
+        ),
+    )
+    .unwrap();
+
+    let mut syms = SymCorpus::new();
+    let result = syms.load_manifest(&dir.join("manifest"));
+    assert_parse_err!(
+        result,
+        format!(
+            "{}:2: Logical file name 'test.symtypes' is already listed on line 1",
+            dir.join("manifest").display()
+        )
+    );
+}
+
+#[test]
+fn read_write_odd_names() {
+    // Check that exports with unusual names are classified as exports and round-trip through
+    // consolidation unchanged.
+    let mut syms = SymCorpus::new();
+    syms.load_buffer(
+        Path::new("test.symtypes"),
+        concat!(
+            "s#foo struct foo { int a ; }\n",
+            ".foo int .foo ( s#foo )\n",
+            "$bar int $bar ( )\n",
+            "_ int _ ( )\n",
+            "a# int a# ( )\n", //
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+    assert_eq!(syms.num_exports(), 4);
+
+    let output = syms.to_consolidated_string().unwrap();
+    assert_eq!(
+        output,
+        concat!(
+            "s#foo struct foo { int a ; }\n",
+            "$bar int $bar ( )\n",
+            ".foo int .foo ( s#foo )\n",
+            "_ int _ ( )\n",
+            "a# int a# ( )\n",
+            "F#test.symtypes $bar .foo _ a#\n", //
+        )
+    );
+
+    let mut syms2 = SymCorpus::new();
+    syms2
+        .load_buffer(Path::new("file.symtypes"), output.as_bytes())
+        .unwrap();
+    assert_eq!(syms2.num_exports(), 4);
+    assert_eq!(syms2.to_consolidated_string().unwrap(), output);
+}
+
+#[test]
+fn write_records_json() {
+    // Check that the records of files in a consolidated input, including the implicit ones, are
+    // dumped as JSON.
+    let input = concat!(
+        "s#foo@0 struct foo { int a ; }\n",
+        "s#foo@1 struct foo { long a ; }\n",
+        "s#baz struct baz { int b ; }\n",
+        "bar int bar ( s#foo s#baz )\n",
+        "qux int qux ( s#foo s#baz )\n",
+        "F#test.symtypes s#foo@0 bar\n",
+        "F#test2.symtypes s#foo@1 qux\n", //
+    );
+    let syms =
+        SymCorpus::from_reader(Path::new("consolidated.symtypes"), input.as_bytes()).unwrap();
+    let mut out = Vec::new();
+    let result = syms.write_records_json(&mut out);
+    assert!(result.is_ok());
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        format!(
+            "{:#}\n",
+            json!([
+                {
+                    "path": "test.symtypes",
+                    "records": [
+                        { "name": "bar", "variant": 0 },
+                        { "name": "s#baz", "variant": 0 },
+                        { "name": "s#foo", "variant": 0 },
+                    ],
+                },
+                {
+                    "path": "test2.symtypes",
+                    "records": [
+                        { "name": "qux", "variant": 0 },
+                        { "name": "s#baz", "variant": 0 },
+                        { "name": "s#foo", "variant": 1 },
+                    ],
+                },
+            ])
+        )
+    );
+}
+
+#[test]
+fn write_canonical() {
+    // Check that differently ordered loads of the same files produce identical output after
+    // canonicalization, with variants numbered by their content.
+    let files = [
+        (
+            "test.symtypes",
+            concat!(
+                "s#foo struct foo { long a ; }\n",
+                "bar int bar ( s#foo )\n", //
+            ),
+        ),
+        (
+            "test2.symtypes",
+            concat!(
+                "s#foo struct foo { int a ; }\n",
+                "baz int baz ( s#foo )\n", //
+            ),
+        ),
+    ];
+    let options = ConsolidateOptions::default();
+
+    let mut outputs = Vec::new();
+    for order in [[0, 1], [1, 0]] {
+        let mut syms = SymCorpus::new();
+        for i in order {
+            let (path, input) = files[i];
+            syms.load_buffer(Path::new(path), input.as_bytes()).unwrap();
+        }
+        syms.canonicalize();
+
+        let mut out = Vec::new();
+        syms.write_consolidated_with(&mut out, &options).unwrap();
+        syms.write_records_json(&mut out).unwrap();
+        outputs.push(String::from_utf8(out).unwrap());
+    }
+    assert_eq!(outputs[0], outputs[1]);
+    assert!(outputs[0].starts_with(concat!(
+        "s#foo@0 struct foo { int a ; }\n",
+        "s#foo@1 struct foo { long a ; }\n",
+        "bar int bar ( s#foo )\n",
+        "baz int baz ( s#foo )\n",
+        "F#test.symtypes s#foo@1 bar\n",
+        "F#test2.symtypes s#foo@0 baz\n", //
+    )));
+}
+
+#[test]
+fn write_variant_numbering_file_reordering() {
+    // Check that the default variant numbers, which are based on the content of the variants, don't
+    // change when the files are renamed so that their order changes and an unrelated file is added,
+    // while the first-use numbering follows the order of the files.
+    let write = |files: &[(&str, &str)], first_use_numbering| {
+        let mut syms = SymCorpus::new();
+        for (path, input) in files {
+            syms.load_buffer(Path::new(path), input.as_bytes()).unwrap();
+        }
+        let options = ConsolidateOptions {
+            first_use_numbering,
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        syms.write_consolidated_with(&mut out, &options).unwrap();
+        String::from_utf8(out).unwrap()
+    };
+    let foo_long = concat!(
+        "s#foo struct foo { long a ; }\n",
+        "bar int bar ( s#foo )\n", //
+    );
+    let foo_int = concat!(
+        "s#foo struct foo { int a ; }\n",
+        "baz int baz ( s#foo )\n", //
+    );
+    let files = [("a.symtypes", foo_long), ("b.symtypes", foo_int)];
+    let reordered_files = [
+        ("0.symtypes", "qux int qux ( )\n"),
+        ("c.symtypes", foo_long),
+        ("b.symtypes", foo_int),
+    ];
+
+    let types = concat!(
+        "s#foo@0 struct foo { int a ; }\n",
+        "s#foo@1 struct foo { long a ; }\n", //
+    );
+    assert!(write(&files, false).starts_with(types));
+    assert!(write(&reordered_files, false).starts_with(types));
+
+    assert!(write(&files, true).starts_with(concat!(
+        "s#foo@0 struct foo { long a ; }\n",
+        "s#foo@1 struct foo { int a ; }\n", //
+    )));
+    assert!(write(&reordered_files, true).starts_with(types));
+}
+
+#[test]
+fn transform_merge_variants() {
+    // Check that type variants which become identical after a transformation are merged.
+    let mut syms = SymCorpus::new();
+    syms.load_buffer(
+        Path::new("test.symtypes"),
+        concat!(
+            "s#foo struct foo { int a ; }\n",
+            "bar int bar ( s#foo )\n", //
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+    syms.load_buffer(
+        Path::new("test2.symtypes"),
+        concat!(
+            "s#foo struct foo { long a ; }\n",
+            "baz int baz ( s#foo )\n", //
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+    assert_eq!(syms.num_type_variants(), 4);
+
+    syms.transform(|_name, tokens| {
+        for token in tokens.iter_mut() {
+            if token.as_str() == "long" {
+                *token = Token::Atom("int".into());
+            }
+        }
+    });
+    assert_eq!(syms.num_type_variants(), 3);
+    assert_eq!(
+        syms.to_consolidated_string().unwrap(),
+        concat!(
+            "s#foo struct foo { int a ; }\n",
+            "bar int bar ( s#foo )\n",
+            "baz int baz ( s#foo )\n",
+            "F#test.symtypes bar\n",
+            "F#test2.symtypes baz\n", //
+        )
+    );
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn read_max_memory() {
+    // Check that loading fails when the resident memory exceeds the limit and succeeds when it
+    // stays below it. The exact boundary is checked by a unit test, the limits here keep a margin
+    // because other tests running in parallel change the resident memory of the process.
+    let rss = fs::read_to_string("/proc/self/status")
+        .unwrap()
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))
+        .and_then(|value| value.trim().strip_suffix("kB"))
+        .map(|value| value.trim().parse::<u64>().unwrap() * 1024)
+        .unwrap();
+    let test_dir = create_test_dir("read_max_memory");
+    let mut symfiles = Vec::new();
+    for i in 0..4 {
+        let path = test_dir.join(format!("test{}.symtypes", i));
+        let mut input = String::new();
+        for j in 0..100 {
+            input.push_str(&format!("s#foo{} struct foo{} {{ int a{} ; }}\n", j, j, i));
+        }
+        fs::write(&path, input).unwrap();
+        symfiles.push(path);
+    }
+
+    let options = LoadOptions {
+        max_memory: Some(rss / 2),
+        ..Default::default()
+    };
+    let mut syms = SymCorpus::new();
+    let result = syms.load_multiple_with(&symfiles, 2, &options);
+    match result {
+        Err(ksymtypes::Error::Parse(desc)) => assert!(
+            desc.starts_with("Resident memory of ")
+                && desc.ends_with(" MB, raise the limit to load the input"),
+            "unexpected error '{}'",
+            desc
+        ),
+        result => panic!("assertion failed: {:?} is not a memory limit error", result),
+    }
+
+    let options = LoadOptions {
+        max_memory: Some(rss + 256 * 1024 * 1024),
+        ..Default::default()
+    };
+    let mut syms = SymCorpus::new();
+    let result = syms.load_multiple_with(&symfiles, 2, &options);
+    assert!(result.is_ok());
+    assert_eq!(syms.num_files(), 4);
+}
+
+/// A writer which accepts only a limited number of bytes and fails afterwards.
+struct LimitedWriter {
+    remaining: usize,
+}
+
+impl io::Write for LimitedWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.remaining == 0 {
+            return Err(io::Error::new(io::ErrorKind::BrokenPipe, "limit reached"));
+        }
+        let len = buf.len().min(self.remaining);
+        self.remaining -= len;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn write_consolidated_error() {
+    // Check that a failure to write the consolidated output is reported.
+    let syms = SymCorpus::try_from(concat!(
+        "s#foo struct foo { int a ; }\n",
+        "bar int bar ( s#foo )\n", //
+    ))
+    .unwrap();
+    let result = syms.write_consolidated(LimitedWriter { remaining: 10 });
+    match result {
+        Err(ksymtypes::Error::IO { desc, io_err }) => {
+            assert_eq!(desc, "Failed to write the consolidated data");
+            assert_eq!(io_err.kind(), io::ErrorKind::BrokenPipe);
+        }
+        result => panic!("assertion failed: {:?} is not an I/O error", result),
+    }
+}
+
+#[test]
+fn write_consolidated_files() {
+    // Check that the description of written file records matches the consolidated output.
+    let mut syms = SymCorpus::new();
+    syms.load_buffer(
+        Path::new("test.symtypes"),
+        concat!(
+            "s#foo struct foo { int a ; }\n",
+            "s#baz struct baz { s#foo f ; }\n",
+            "bar int bar ( s#baz )\n",
+            "qux int qux ( )\n", //
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+    syms.load_buffer(
+        Path::new("test2.symtypes"),
+        concat!(
+            "s#foo struct foo { long a ; }\n",
+            "quux int quux ( s#foo )\n", //
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+
+    let mut out = Vec::new();
+    let consolidated_files = syms
+        .write_consolidated_with(&mut out, &ConsolidateOptions::default())
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        concat!(
+            "s#baz struct baz { s#foo f ; }\n",
+            "s#foo@0 struct foo { int a ; }\n",
+            "s#foo@1 struct foo { long a ; }\n",
+            "bar int bar ( s#baz )\n",
+            "quux int quux ( s#foo )\n",
+            "qux int qux ( )\n",
+            "F#test.symtypes s#foo@0 bar qux\n",
+            "F#test2.symtypes s#foo@1 quux\n", //
+        )
+    );
+    assert_eq!(
+        consolidated_files,
+        vec![
+            ConsolidatedFile {
+                path: PathBuf::from("test.symtypes"),
+                num_types: 2,
+                num_exports: 2,
+            },
+            ConsolidatedFile {
+                path: PathBuf::from("test2.symtypes"),
+                num_types: 1,
+                num_exports: 1,
+            },
+        ]
+    );
+}
+
+#[test]
+fn compare_with_expected() {
+    // Check comparison of exports with their expected definitions in a directory.
+    let test_dir = create_test_dir("compare_with_expected");
+    fs::write(
+        test_dir.join("bar"),
+        concat!(
+            "s#foo struct foo { int a ; }\n",
+            "bar int bar ( s#foo )\n",
+            "F#bar bar\n", //
+        ),
+    )
+    .unwrap();
+    fs::write(
+        test_dir.join("baz"),
+        concat!(
+            "s#foo struct foo { int a ; }\n",
+            "baz int baz ( s#foo )\n",
+            "F#baz baz\n", //
+        ),
+    )
+    .unwrap();
+    fs::write(test_dir.join("old"), "old int old ( )\nF#old old\n").unwrap();
+
+    let mut syms = SymCorpus::new();
+    syms.load_buffer(
+        Path::new("test.symtypes"),
+        concat!(
+            "s#foo struct foo { int a ; }\n",
+            "bar int bar ( s#foo )\n", //
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+    syms.load_buffer(
+        Path::new("test2.symtypes"),
+        concat!(
+            "s#foo struct foo { long a ; }\n",
+            "baz int baz ( s#foo )\n",
+            "qux int qux ( )\n", //
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+
+    let lines = syms.compare_with_expected(&test_dir).unwrap();
+    assert_eq!(
+        lines,
+        vec![
+            format!(
+                "Export baz differs from its expected file '{}'",
+                test_dir.join("baz").display()
+            ),
+            "@@ -1,3 +1,3 @@".to_string(),
+            "-s#foo struct foo { int a ; }".to_string(),
+            "+s#foo struct foo { long a ; }".to_string(),
+            " baz int baz ( s#foo )".to_string(),
+            " F#baz baz".to_string(),
+            format!(
+                "Export qux is new, no expected file '{}'",
+                test_dir.join("qux").display()
+            ),
+            format!(
+                "Export old is removed, found only its expected file '{}'",
+                test_dir.join("old").display()
+            ),
+        ]
+    );
+}
+
+#[test]
+fn write_exclude_kinds() {
+    // Check that records of excluded type kinds are omitted while the types that they reference
+    // are still written.
+    let mut syms = SymCorpus::new();
+    syms.load_buffer(
+        Path::new("test.symtypes"),
+        concat!(
+            "s#foo struct foo { int a ; }\n",
+            "t#foo_t typedef s#foo foo_t\n",
+            "e#mode enum mode { A , B }\n",
+            "bar int bar ( t#foo_t , e#mode )\n", //
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+
+    let options = ConsolidateOptions {
+        exclude_kinds: vec![TypeKind::Enum, TypeKind::Typedef],
+        ..Default::default()
+    };
+    let mut out = Vec::new();
+    let result = syms.write_consolidated_with(&mut out, &options);
+    assert!(result.is_ok());
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        concat!(
+            "s#foo struct foo { int a ; }\n",
+            "bar int bar ( t#foo_t , e#mode )\n",
+            "F#test.symtypes bar\n", //
+        )
+    );
+}
+
+#[test]
+fn compare_streaming() {
+    // Check that the streaming comparison of consolidated files gives the same result as the
+    // comparison of loaded corpuses.
+    let input = concat!(
+        "s#foo struct foo { s#inner a ; }\n",
+        "s#inner struct inner { int a ; }\n",
+        "s#same struct same { int a ; }\n",
+        "bar int bar ( s#foo , s#same )\n",
+        "baz int baz ( )\n",
+        "F#test.symtypes bar baz\n", //
+    );
+    let input2 = concat!(
+        "s#foo struct foo { s#inner a ; }\n",
+        "s#inner struct inner { long a ; }\n",
+        "s#same struct same { int a ; }\n",
+        "bar int bar ( s#foo , s#same )\n",
+        "qux int qux ( )\n",
+        "F#test.symtypes bar qux\n", //
+    );
+
+    let mut out = Vec::new();
+    let summary = SymCorpus::compare_streaming(
+        Path::new("a.symtypes"),
+        input.as_bytes(),
+        Path::new("b.symtypes"),
+        input2.as_bytes(),
+        &UnifiedRenderer::new(),
+        &mut out,
+    )
+    .unwrap();
+
+    let syms = SymCorpus::from_reader(Path::new("a.symtypes"), input.as_bytes()).unwrap();
+    let syms2 = SymCorpus::from_reader(Path::new("b.symtypes"), input2.as_bytes()).unwrap();
+    let mut out2 = Vec::new();
+    let summary2 = syms
+        .write_comparison(
+            &syms2,
+            &CompareOptions::default(),
+            &UnifiedRenderer::new(),
+            1,
+            &mut out2,
+        )
+        .unwrap();
+
+    // The streaming comparison doesn't track which files use a changed type, so it reports the
+    // same output except for the lists of affected files.
+    assert_eq!(summary, summary2);
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        String::from_utf8(out2)
+            .unwrap()
+            .lines()
+            .filter(|line| !line.starts_with("Affected files: "))
+            .map(|line| format!("{}\n", line))
+            .collect::<String>()
+    );
+}
+
+#[test]
+fn compare_streaming_multiple_variants() {
+    // Check that the streaming comparison rejects types with multiple variants, which can't be
+    // paired without the F# records. Here, bar switches from one variant of s#foo to the other
+    // one, while both variants are still present in both files.
+    let input = concat!(
+        "s#foo@0 struct foo { int a ; }\n",
+        "s#foo@1 struct foo { long a ; }\n",
+        "bar int bar ( s#foo )\n",
+        "baz int baz ( s#foo )\n",
+        "F#a.symtypes s#foo@0 bar\n",
+        "F#b.symtypes s#foo@1 baz\n", //
+    );
+    let input2 = concat!(
+        "s#foo@0 struct foo { int a ; }\n",
+        "s#foo@1 struct foo { long a ; }\n",
+        "bar int bar ( s#foo )\n",
+        "baz int baz ( s#foo )\n",
+        "F#a.symtypes s#foo@1 bar\n",
+        "F#b.symtypes s#foo@0 baz\n", //
+    );
+
+    let result = SymCorpus::compare_streaming(
+        Path::new("a.symtypes"),
+        input.as_bytes(),
+        Path::new("b.symtypes"),
+        input2.as_bytes(),
+        &UnifiedRenderer::new(),
+        io::sink(),
+    );
+    assert_parse_err!(
+        result,
+        "a.symtypes:2: Type s#foo has multiple variants, which the streaming comparison doesn't support"
+    );
+
+    // The comparison of loaded corpuses detects the change.
+    let syms = SymCorpus::from_reader(Path::new("a.symtypes"), input.as_bytes()).unwrap();
+    let syms2 = SymCorpus::from_reader(Path::new("b.symtypes"), input2.as_bytes()).unwrap();
+    let summary = syms
+        .write_comparison(
+            &syms2,
+            &CompareOptions::default(),
+            &UnifiedRenderer::new(),
+            1,
+            io::sink(),
+        )
+        .unwrap();
+    assert_eq!(summary.changed_types, 1);
+}
+
+#[test]
+fn compare_streaming_unsorted() {
+    // Check that the streaming comparison rejects records which are not sorted.
+    let input = concat!(
+        "bar int bar ( )\n",
+        "s#foo struct foo { int a ; }\n",
+        "F#test.symtypes bar\n", //
+    );
+    let result = SymCorpus::compare_streaming(
+        Path::new("a.symtypes"),
+        input.as_bytes(),
+        Path::new("b.symtypes"),
+        input.as_bytes(),
+        &UnifiedRenderer::new(),
+        io::sink(),
+    );
+    assert_parse_err!(
+        result,
+        "a.symtypes:2: Record s#foo is not in the canonical order"
+    );
+}
+
+#[test]
+fn max_depth_chain() {
+    // Check that the longest chain of type references is found, without following cycles.
+    let mut syms = SymCorpus::new();
+    syms.load_buffer(
+        Path::new("test.symtypes"),
+        concat!(
+            "s#list struct list { s#list * next ; s#node * node ; }\n",
+            "s#node struct node { t#u64 value ; }\n",
+            "t#u64 typedef unsigned long long u64\n",
+            "bar int bar ( s#list * )\n",
+            "baz int baz ( t#u64 )\n", //
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+    assert_eq!(
+        syms.max_depth_chain().unwrap(),
+        vec!["bar", "s#list", "s#node", "t#u64"]
+    );
+    assert!(SymCorpus::new().max_depth_chain().unwrap().is_empty());
+}
+
+#[test]
+fn max_depth_chain_cycle() {
+    // Check that the depth of a type in a cycle doesn't depend on the order in which the exports
+    // reach it. The walk from bar finds s#a only as the end of the cycle s#b -> s#a, which must not
+    // limit the depth of s#a when it is reached from foo.
+    let types = concat!(
+        "s#a struct a { s#b * b ; }\n",
+        "s#b struct b { s#a * a ; }\n",
+        "s#x struct x { s#a * a ; }\n", //
+    );
+    for other_export in ["bar", "zed"] {
+        let syms = SymCorpus::try_from(
+            format!(
+                "{}{} int {} ( s#b )\nfoo int foo ( s#x )\n",
+                types, other_export, other_export
+            )
+            .as_str(),
+        )
+        .unwrap();
+        assert_eq!(
+            syms.max_depth_chain().unwrap(),
+            vec!["foo", "s#x", "s#a", "s#b"]
+        );
+    }
+}
+
+#[test]
+fn max_depth_chain_limit() {
+    // Check that a chain deeper than the maximum nesting depth is reported as an error instead of
+    // overflowing the stack.
+    let mut input = String::new();
+    for i in 0..100000 {
+        input.push_str(&format!(
+            "s#t{} struct t{} {{ s#t{} * next ; }}\n",
+            i,
+            i,
+            i + 1
+        ));
+    }
+    input.push_str("s#t100000 struct t100000 { int a ; }\n");
+    input.push_str("bar int bar ( s#t0 )\n");
+    let syms = SymCorpus::try_from(input.as_str()).unwrap();
+    assert_parse_err!(
+        syms.max_depth_chain(),
+        "<literal>: Type s#t255 exceeds the maximum nesting depth of 256, referenced via bar -> s#t0 -> s#t1 -> ..."
+    );
+}
+
+#[test]
+fn check_diagnostics() {
+    // Check that each integrity check reports its diagnostic code.
+    let mut syms = SymCorpus::new();
+    syms.load_buffer(
+        Path::new("test.symtypes"),
+        concat!(
+            "s#foo union foo { int a ; }\n",
+            "bar int bar ( s#foo , s#missing )\n", //
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+    let options = LoadOptions {
+        allow_duplicate_exports: true,
+        ..Default::default()
+    };
+    syms.load_buffer_with(
+        Path::new("test2.symtypes"),
+        "bar int bar ( )\n".as_bytes(),
+        InputFormat::Auto,
+        &options,
+    )
+    .unwrap();
+    syms.load_buffer(Path::new("test3.symtypes"), "".as_bytes())
+        .unwrap();
+    syms.load_buffer(
+        Path::new("test4.symtypes"),
+        "s#baz struct baz { int a ; }\n".as_bytes(),
+    )
+    .unwrap();
+    assert!(syms.remove_file(Path::new("test4.symtypes")));
+
+    let diagnostics = syms.check();
+    let codes = diagnostics
+        .iter()
+        .map(|diagnostic| (diagnostic.severity, diagnostic.code))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        codes,
+        vec![
+            (Severity::Error, "KIND_MISMATCH"),
+            (Severity::Warning, "ORPHAN_TYPE"),
+            (Severity::Error, "DANGLING_REF"),
+            (Severity::Error, "DUPLICATE_EXPORT"),
+            (Severity::Warning, "EMPTY_FILE"),
+        ]
+    );
+    assert_eq!(
+        diagnostics[2].to_string(),
+        "test.symtypes: error: Type s#missing is not known, referenced by bar [DANGLING_REF]"
+    );
+    assert_eq!(
+        diagnostics[3],
+        Diagnostic {
+            severity: Severity::Error,
+            code: "DUPLICATE_EXPORT",
+            message: "Export bar is already declared in 'test.symtypes'".to_string(),
+            file: Some(PathBuf::from("test2.symtypes")),
+        }
+    );
+    assert_eq!(
+        diagnostics[4].to_json(),
+        json!({
+            "severity": "warning",
+            "code": "EMPTY_FILE",
+            "message": "File has no records",
+            "file": "test3.symtypes",
+        })
+    );
+}
+
+#[test]
+fn read_num_workers() {
+    // Check that loading with different numbers of workers produces identical corpuses.
+    let test_dir = create_test_dir("read_num_workers");
+    for i in 0..8 {
+        fs::write(
+            test_dir.join(format!("test{}.symtypes", i)),
+            format!(
+                "s#foo struct foo {{ int a{} ; }}\nbar{} int bar{} ( s#foo )\n",
+                i % 3,
+                i,
+                i
+            ),
+        )
+        .unwrap();
+    }
+
+    let mut outputs = Vec::new();
+    for num_workers in [1, 4] {
+        let mut syms = SymCorpus::new();
+        syms.load(&test_dir, num_workers).unwrap();
+        syms.canonicalize();
+        let options = ConsolidateOptions::default();
+        let mut out = Vec::new();
+        syms.write_consolidated_with(&mut out, &options).unwrap();
+        outputs.push(String::from_utf8(out).unwrap());
+    }
+    assert_eq!(outputs[0], outputs[1]);
+}
+
+#[test]
+fn write_split() {
+    // Check that splitting a consolidated file and consolidating the split files again gives the
+    // same output.
+    let input = concat!(
+        "s#baz struct baz { int b ; }\n",
+        "s#foo@0 struct foo { int a ; }\n",
+        "s#foo@1 struct foo { long a ; }\n",
+        "bar int bar ( s#foo s#baz )\n",
+        "qux int qux ( s#foo )\n",
+        "F#dir/test.symtypes s#foo@0 bar\n",
+        "F#test2.symtypes s#foo@1 qux\n", //
+    );
+    let syms =
+        SymCorpus::from_reader(Path::new("consolidated.symtypes"), input.as_bytes()).unwrap();
+
+    let test_dir = create_test_dir("write_split");
+    let result = syms.write_split(&test_dir);
+    assert!(result.is_ok());
+    assert_eq!(
+        fs::read_to_string(test_dir.join("dir/test.symtypes")).unwrap(),
+        concat!(
+            "s#baz struct baz { int b ; }\n",
+            "s#foo struct foo { int a ; }\n",
+            "bar int bar ( s#foo s#baz )\n", //
+        )
+    );
+
+    let mut syms2 = SymCorpus::new();
+    for path in ["dir/test.symtypes", "test2.symtypes"] {
+        let file = fs::File::open(test_dir.join(path)).unwrap();
+        syms2.load_buffer(Path::new(path), file).unwrap();
+    }
+    assert_eq!(syms2.to_consolidated_string().unwrap(), input);
+}
+
+#[test]
+fn merge_corpuses() {
+    // Check that merging two corpuses unifies the identical type variants and keeps the different
+    // ones as separate variants.
+    let input = concat!(
+        "s#foo struct foo { int a ; }\n",
+        "s#baz struct baz { int b ; }\n",
+        "bar int bar ( s#foo s#baz )\n",
+        "F#x86/test.symtypes bar\n", //
+    );
+    let mut syms = SymCorpus::from_reader(Path::new("x86.symtypes"), input.as_bytes()).unwrap();
+    let input = concat!(
+        "s#foo struct foo { int a ; }\n",
+        "s#baz struct baz { long b ; }\n",
+        "qux int qux ( s#foo s#baz )\n",
+        "F#arm64/test.symtypes qux\n", //
+    );
+    let other = SymCorpus::from_reader(Path::new("arm64.symtypes"), input.as_bytes()).unwrap();
+
+    syms.merge(other).unwrap();
+    assert_eq!(
+        syms.to_consolidated_string().unwrap(),
+        concat!(
+            "s#baz@0 struct baz { int b ; }\n",
+            "s#baz@1 struct baz { long b ; }\n",
+            "s#foo struct foo { int a ; }\n",
+            "bar int bar ( s#foo s#baz )\n",
+            "qux int qux ( s#foo s#baz )\n",
+            "F#arm64/test.symtypes s#baz@1 qux\n",
+            "F#x86/test.symtypes s#baz@0 bar\n", //
+        )
+    );
+
+    // Check that an export declared in different files is reported as a conflict.
+    let input = concat!(
+        "bar int bar ( )\n",
+        "F#other.symtypes bar\n", //
+    );
+    let other = SymCorpus::from_reader(Path::new("other.symtypes"), input.as_bytes()).unwrap();
+    let result = syms.merge(other);
+    assert_parse_err!(
+        result,
+        "Export bar is declared in both 'x86/test.symtypes' and 'other.symtypes'"
+    );
+}
+
+#[test]
+fn merge_corpuses_same_path() {
+    // Check that a file present in both corpuses is kept once if its records are identical and is
+    // otherwise rejected, unless merging of file records is enabled.
+    let input = concat!(
+        "s#foo struct foo { int a ; }\n",
+        "bar int bar ( s#foo )\n",
+        "F#test.symtypes bar\n", //
+    );
+    let input2 = concat!(
+        "s#foo struct foo { int a ; }\n",
+        "baz int baz ( s#foo )\n",
+        "F#test.symtypes baz\n", //
+    );
+    let mut syms = SymCorpus::from_reader(Path::new("a.symtypes"), input.as_bytes()).unwrap();
+    let other = SymCorpus::from_reader(Path::new("b.symtypes"), input.as_bytes()).unwrap();
+    syms.merge(other).unwrap();
+    assert_eq!(syms.num_files(), 1);
+    assert_eq!(
+        syms.to_consolidated_string().unwrap(),
+        SymCorpus::from_reader(Path::new("a.symtypes"), input.as_bytes())
+            .unwrap()
+            .to_consolidated_string()
+            .unwrap()
+    );
+
+    let other = SymCorpus::from_reader(Path::new("b.symtypes"), input2.as_bytes()).unwrap();
+    let result = syms.merge(other);
+    assert_parse_err!(
+        result,
+        "File 'test.symtypes' is present in both corpuses with different records"
+    );
+
+    let options = LoadOptions {
+        merge_file_records: true,
+        ..Default::default()
+    };
+    let mut syms = SymCorpus::from_reader(Path::new("a.symtypes"), input.as_bytes()).unwrap();
+    let other = SymCorpus::from_reader(Path::new("b.symtypes"), input2.as_bytes()).unwrap();
+    syms.merge_with(other, &options).unwrap();
+    assert_eq!(syms.num_files(), 1);
+    assert_eq!(
+        syms.to_consolidated_string().unwrap(),
+        concat!(
+            "s#foo struct foo { int a ; }\n",
+            "bar int bar ( s#foo )\n",
+            "baz int baz ( s#foo )\n",
+            "F#test.symtypes bar baz\n", //
+        )
+    );
+
+    // Check that the merged records must not reference different variants of the same type.
+    let input3 = concat!(
+        "s#foo struct foo { long a ; }\n",
+        "qux int qux ( s#foo )\n",
+        "F#test.symtypes qux\n", //
+    );
+    let other = SymCorpus::from_reader(Path::new("c.symtypes"), input3.as_bytes()).unwrap();
+    let result = syms.merge_with(other, &options);
+    assert_parse_err!(
+        result,
+        "Type s#foo has a different variant in file 'test.symtypes' of both corpuses"
+    );
+}
+
+#[test]
+fn merge_corpuses_duplicate_exports() {
+    // Check that an export declared in different files is accepted when duplicate exports are
+    // allowed.
+    let input = concat!(
+        "bar int bar ( )\n",
+        "F#test.symtypes bar\n", //
+    );
+    let input2 = concat!(
+        "bar int bar ( )\n",
+        "F#test2.symtypes bar\n", //
+    );
+    let mut syms = SymCorpus::from_reader(Path::new("a.symtypes"), input.as_bytes()).unwrap();
+    let other = SymCorpus::from_reader(Path::new("b.symtypes"), input2.as_bytes()).unwrap();
+    let options = LoadOptions {
+        allow_duplicate_exports: true,
+        ..Default::default()
+    };
+    syms.merge_with(other, &options).unwrap();
+    assert_eq!(syms.num_files(), 2);
+    assert_eq!(syms.num_exports(), 1);
+}
+
+#[test]
+fn verify_consolidated() {
+    // Check that a consolidated file in the canonical form verifies without any differences,
+    // while a file with reordered records is reported.
+    let input = concat!(
+        "s#baz struct baz { int b ; }\n",
+        "s#foo struct   foo { int a ; }\n",
+        "bar int bar ( s#foo s#baz )\n",
+        "F#test.symtypes bar\n", //
+    );
+    let result = SymCorpus::verify_consolidated(Path::new("consolidated.symtypes"), input);
+    assert_eq!(result.unwrap(), Vec::<String>::new());
+
+    let input = concat!(
+        "bar int bar ( s#foo s#baz )\n",
+        "s#baz struct baz { int b ; }\n",
+        "s#foo struct foo { int a ; }\n",
+        "F#test.symtypes bar\n", //
+    );
+    let result = SymCorpus::verify_consolidated(Path::new("consolidated.symtypes"), input);
+    assert_eq!(
+        result.unwrap(),
+        vec![
+            "@@ -1,4 +1,4 @@",
+            "-bar int bar ( s#foo s#baz )",
+            " s#baz struct baz { int b ; }",
+            " s#foo struct foo { int a ; }",
+            "+bar int bar ( s#foo s#baz )",
+            " F#test.symtypes bar",
+        ]
+    );
+}
+
+#[test]
+fn compare_summary() {
+    // Check that the comparison summary indicates whether any differences were found.
+    let input = concat!(
+        "s#foo struct foo { int a ; }\n",
+        "bar int bar ( s#foo )\n", //
+    );
+    let input2 = concat!(
+        "s#foo struct foo { long a ; }\n",
+        "bar int bar ( s#foo )\n", //
+    );
+    let syms = SymCorpus::try_from(input).unwrap();
+    let syms2 = SymCorpus::try_from(input2).unwrap();
+
+    let summary = syms
+        .write_comparison(
+            &syms2,
+            &CompareOptions::default(),
+            &UnifiedRenderer::new(),
+            1,
+            io::sink(),
+        )
+        .unwrap();
+    assert!(summary.has_differences());
+    assert_eq!(summary.changed_types, 1);
+
+    let summary = syms
+        .write_comparison(
+            &syms,
+            &CompareOptions::default(),
+            &UnifiedRenderer::new(),
+            1,
+            io::sink(),
+        )
+        .unwrap();
+    assert!(!summary.has_differences());
+}
+
+#[test]
+fn compare_summary_counts() {
+    // Check that the summary counts each kind of the changes and formats them on a single line.
+    let input = concat!(
+        "s#foo struct foo { int a ; }\n",
+        "s#baz struct baz { int b ; }\n",
+        "bar int bar ( s#foo s#baz )\n",
+        "qux int qux ( )\n", //
+    );
+    let input2 = concat!(
+        "s#foo struct foo { long a ; }\n",
+        "s#baz struct baz { long b ; }\n",
+        "bar int bar ( s#foo s#baz )\n",
+        "abc int abc ( )\n",
+        "def int def ( )\n", //
+    );
+    let syms = SymCorpus::try_from(input).unwrap();
+    let syms2 = SymCorpus::try_from(input2).unwrap();
+
+    let changes = syms
+        .compare_with(
+            &syms2,
+            &CompareOptions::default(),
+            &UnifiedRenderer::new(),
+            1,
+        )
+        .unwrap();
+    let summary = CompareSummary::from_changes(&changes);
+    assert_eq!(
+        summary,
+        CompareSummary {
+            removed_exports: 1,
+            added_exports: 2,
+            changed_types: 2,
+            breaking_changes: 3,
+        }
+    );
+    assert_eq!(
+        summary.to_string(),
+        "2 exports added, 1 exports removed, 2 types changed"
+    );
+}
+
+#[test]
+fn compare_changes() {
+    // Check that the comparison returns the changes sorted by their kind and name.
+    let input = concat!(
+        "s#foo struct foo { int a ; }\n",
+        "bar int bar ( s#foo )\n",
+        "baz int baz ( )\n",
+        "qux int qux ( )\n", //
+    );
+    let input2 = concat!(
+        "s#foo struct foo { long a ; }\n",
+        "bar int bar ( s#foo )\n",
+        "abc int abc ( )\n", //
+    );
+    let syms = SymCorpus::try_from(input).unwrap();
+    let syms2 = SymCorpus::try_from(input2).unwrap();
+
+    let options = CompareOptions::default();
+    let changes = syms
+        .compare_with(&syms2, &options, &UnifiedRenderer::new(), 1)
+        .unwrap();
+    assert_eq!(changes.len(), 4);
+    assert_eq!(changes[0], Change::ExportRemoved("baz".to_string()));
+    assert_eq!(changes[1], Change::ExportRemoved("qux".to_string()));
+    assert_eq!(changes[2], Change::ExportAdded("abc".to_string()));
+    assert!(matches!(&changes[3], Change::TypeChanged { name, .. } if name == "s#foo"));
+
+    let lines = syms.format_changes(&syms2, &changes, &options);
+    assert_eq!(lines[0], "Export baz is present in A but not in B");
+    assert_eq!(lines[1], "Export qux is present in A but not in B");
+    assert_eq!(lines[2], "Export abc is present in B but not in A");
+}
+
+/// Creates symtypes data with an export referencing a chain of `depth` nested structs.
+fn deep_chain_input(depth: usize, member_type: &str) -> String {
+    let mut input = String::new();
+    for i in 0..depth {
+        if i + 1 < depth {
+            input.push_str(&format!("s#s{} struct s{} {{ s#s{} a ; }}\n", i, i, i + 1));
+        } else {
+            input.push_str(&format!(
+                "s#s{} struct s{} {{ {} a ; }}\n",
+                i, i, member_type
+            ));
+        }
+    }
+    input.push_str("bar int bar ( s#s0 )\n");
+    input
+}
+
+#[test]
+fn deep_type_chain() {
+    // Check that a deep, but still reasonable, chain of type references is processed.
+    let syms = SymCorpus::try_from(deep_chain_input(250, "int").as_str()).unwrap();
+    let syms2 = SymCorpus::try_from(deep_chain_input(250, "long").as_str()).unwrap();
+    assert!(syms.to_consolidated_string().is_ok());
+    let changes = syms
+        .compare_with(
+            &syms2,
+            &CompareOptions::default(),
+            &UnifiedRenderer::new(),
+            1,
+        )
+        .unwrap();
+    assert_eq!(changes.len(), 1);
+
+    // Check that an excessively deep chain is reported as an error.
+    let syms = SymCorpus::try_from(deep_chain_input(300, "int").as_str()).unwrap();
+    let syms2 = SymCorpus::try_from(deep_chain_input(300, "long").as_str()).unwrap();
+    let result = syms.to_consolidated_string();
+    assert_parse_err!(
+        result,
+        "<literal>: Type s#s255 exceeds the maximum nesting depth of 256, referenced via bar -> s#s0 -> s#s1 -> ..."
+    );
+    let result = syms.compare_with(
+        &syms2,
+        &CompareOptions::default(),
+        &UnifiedRenderer::new(),
+        1,
+    );
+    assert_parse_err!(
+        result,
+        "<literal>: Type s#s255 exceeds the maximum nesting depth of 256, referenced via bar -> s#s0 -> s#s1 -> ..."
+    );
+}
+
+#[test]
+fn deep_type_chain_limit() {
+    // Check that the maximum nesting depth can be raised to process a deeper valid chain.
+    let syms = SymCorpus::try_from(deep_chain_input(500, "int").as_str()).unwrap();
+    let syms2 = SymCorpus::try_from(deep_chain_input(500, "long").as_str()).unwrap();
+    let consolidate_options = ConsolidateOptions {
+        max_type_depth: Some(512),
+        ..Default::default()
+    };
+    let mut out = Vec::new();
+    syms.write_consolidated_with(&mut out, &consolidate_options)
+        .unwrap();
+    let compare_options = CompareOptions {
+        max_type_depth: Some(512),
+        ..Default::default()
+    };
+    let changes = syms
+        .compare_with(&syms2, &compare_options, &UnifiedRenderer::new(), 1)
+        .unwrap();
+    assert_eq!(changes.len(), 1);
+
+    // Check that the chain is rejected with a lower limit.
+    let compare_options = CompareOptions {
+        max_type_depth: Some(100),
+        ..Default::default()
+    };
+    let result = syms.compare_with(&syms2, &compare_options, &UnifiedRenderer::new(), 1);
+    assert_parse_err!(
+        result,
+        "<literal>: Type s#s99 exceeds the maximum nesting depth of 100, referenced via bar -> s#s0 -> s#s1 -> ..."
+    );
+}
+
+#[test]
+fn compare_name_only() {
+    // Check that only the sorted names of the changed symbols are listed with name_only.
+    let input = concat!(
+        "s#foo struct foo { int a ; }\n",
+        "bar int bar ( s#foo )\n",
+        "qux int qux ( )\n", //
+    );
+    let input2 = concat!(
+        "s#foo struct foo { long a ; }\n",
+        "bar int bar ( s#foo )\n",
+        "abc int abc ( )\n", //
+    );
+    let syms = SymCorpus::try_from(input).unwrap();
+    let syms2 = SymCorpus::try_from(input2).unwrap();
+
+    let options = CompareOptions {
+        name_only: true,
+        ..Default::default()
+    };
+    let changes = syms
+        .compare_with(&syms2, &options, &UnifiedRenderer::new(), 1)
+        .unwrap();
+    assert_eq!(
+        syms.format_changes(&syms2, &changes, &options),
+        vec!["abc", "qux", "s#foo"]
+    );
+}
+
+#[test]
+fn compare_defining_files() {
+    // Check that a changed export names the files which define it in both corpuses.
+    let mut syms = SymCorpus::new();
+    syms.load_buffer(
+        Path::new("fs/ext4.symtypes"),
+        "bar int bar ( int )\n".as_bytes(),
+    )
+    .unwrap();
+    let mut syms2 = SymCorpus::new();
+    syms2
+        .load_buffer(
+            Path::new("fs/ext4/super.symtypes"),
+            "bar int bar ( long )\n".as_bytes(),
+        )
+        .unwrap();
+
+    let options = CompareOptions::default();
+    let changes = syms
+        .compare_with(&syms2, &options, &UnifiedRenderer::new(), 1)
+        .unwrap();
+    assert_eq!(changes.len(), 1);
+    assert!(matches!(&changes[0], Change::TypeChanged { defined_in, .. }
+        if *defined_in == Some((PathBuf::from("fs/ext4.symtypes"), PathBuf::from("fs/ext4/super.symtypes")))));
+
+    let lines = syms.format_changes(&syms2, &changes, &options);
+    assert_eq!(
+        lines[0],
+        "Export bar is defined in 'fs/ext4.symtypes' in A and 'fs/ext4/super.symtypes' in B"
+    );
+}
+
+#[test]
+fn compare_affected_files() {
+    // Check that a changed type lists all files through which it is reached.
+    let mut syms = SymCorpus::new();
+    syms.load_buffer(
+        Path::new("b.symtypes"),
+        concat!(
+            "s#foo struct foo { int a ; }\n",
+            "bar int bar ( s#foo )\n", //
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+    syms.load_buffer(
+        Path::new("a.symtypes"),
+        concat!(
+            "s#foo struct foo { int a ; }\n",
+            "baz int baz ( s#foo )\n", //
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+    let mut syms2 = SymCorpus::new();
+    syms2
+        .load_buffer(
+            Path::new("b.symtypes"),
+            concat!(
+                "s#foo struct foo { long a ; }\n",
+                "bar int bar ( s#foo )\n", //
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+    syms2
+        .load_buffer(
+            Path::new("a.symtypes"),
+            concat!(
+                "s#foo struct foo { long a ; }\n",
+                "baz int baz ( s#foo )\n", //
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+
+    let options = CompareOptions::default();
+    let changes = syms
+        .compare_with(&syms2, &options, &UnifiedRenderer::new(), 1)
+        .unwrap();
+    assert_eq!(changes.len(), 1);
+    assert!(
+        matches!(&changes[0], Change::TypeChanged { name, files, .. }
+        if name == "s#foo" && *files == vec![PathBuf::from("a.symtypes"), PathBuf::from("b.symtypes")])
+    );
+
+    let lines = syms.format_changes(&syms2, &changes, &options);
+    assert_eq!(
+        lines.last().unwrap(),
+        "Affected files: a.symtypes, b.symtypes"
+    );
+}
+
+#[test]
+fn compare_ignored_symbols() {
+    // Check that removals and changes of ignored symbols are not reported, while other changes
+    // still are.
+    let input = concat!(
+        "s#foo struct foo { int a ; }\n",
+        "s#baz struct baz { int b ; }\n",
+        "bar int bar ( s#foo s#baz )\n",
+        "qux int qux ( )\n", //
+    );
+    let input2 = concat!(
+        "s#foo struct foo { long a ; }\n",
+        "s#baz struct baz { long b ; }\n",
+        "bar int bar ( s#foo s#baz )\n",
+        "abc int abc ( )\n", //
+    );
+    let syms = SymCorpus::try_from(input).unwrap();
+    let syms2 = SymCorpus::try_from(input2).unwrap();
+
+    let ignore_list = concat!(
+        "# Tolerated changes\n",
+        "s#foo\n",
+        "\n",
+        "  qux\n",
+        "abc\n", //
+    );
+    let options = CompareOptions {
+        ignored_symbols: CompareOptions::read_ignore_list(
+            Path::new("ignore.txt"),
+            ignore_list.as_bytes(),
+        )
+        .unwrap(),
+        ..Default::default()
+    };
+    let changes = syms
+        .compare_with(&syms2, &options, &UnifiedRenderer::new(), 1)
+        .unwrap();
+    assert_eq!(changes.len(), 2);
+    assert_eq!(changes[0], Change::ExportAdded("abc".to_string()));
+    assert!(matches!(&changes[1], Change::TypeChanged { name, .. } if name == "s#baz"));
+
+    let result = CompareOptions::read_ignore_list(Path::new("ignore.txt"), "foo bar\n".as_bytes());
+    assert_parse_err!(result, "ignore.txt:1: Expected a single symbol name");
+}
+
+#[test]
+fn read_gzip() {
+    // Check that gzip-compressed symtypes are decompressed transparently.
+    let input = concat!(
+        "s#foo struct foo { int a ; }\n",
+        "bar int bar ( s#foo )\n", //
+    );
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(input.as_bytes()).unwrap();
+    let compressed = encoder.finish().unwrap();
+
+    let mut syms = SymCorpus::new();
+    syms.load_buffer(Path::new("test.symtypes.gz"), compressed.as_slice())
+        .unwrap();
+    let mut out = Vec::new();
+    syms.write_consolidated(&mut out).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        concat!(
+            "s#foo struct foo { int a ; }\n",
+            "bar int bar ( s#foo )\n",
+            "F#test.symtypes.gz bar\n", //
+        )
+    );
+
+    // Check that compressed files are found when loading a directory.
+    let test_dir = create_test_dir("read_gzip");
+    fs::write(test_dir.join("test.symtypes.gz"), &compressed).unwrap();
+    fs::write(test_dir.join("test.gz"), &compressed).unwrap();
+    let mut syms = SymCorpus::new();
+    syms.load(&test_dir, 1).unwrap();
+    let syms2 = SymCorpus::try_from(input).unwrap();
+    let changes = syms
+        .compare_with(
+            &syms2,
+            &CompareOptions::default(),
+            &UnifiedRenderer::new(),
+            1,
+        )
+        .unwrap();
+    assert!(changes.is_empty());
+}
+
+#[test]
+fn validate_dangling_ref() {
+    // Check that references to unknown types are reported by the validation, including when
+    // loading with the check enabled.
+    let input = concat!(
+        "s#foo struct foo { s#missing * a ; }\n",
+        "bar int bar ( s#foo )\n", //
+    );
+    let syms = SymCorpus::try_from(input).unwrap();
+    assert_parse_err!(
+        syms.validate(),
+        "Found 1 unresolved type reference(s):\n<literal>: Type s#missing is not known, referenced by s#foo"
+    );
+
+    let test_dir = create_test_dir("validate_dangling_ref");
+    fs::write(test_dir.join("test.symtypes"), input).unwrap();
+    let mut syms = SymCorpus::new();
+    let options = LoadOptions {
+        check_refs: true,
+        ..Default::default()
+    };
+    assert!(syms.load_with(&test_dir, 1, &options).is_err());
+
+    let syms = SymCorpus::try_from("bar int bar ( int )\n").unwrap();
+    assert!(syms.validate().is_ok());
+}
+
+#[test]
+fn read_relative_paths() {
+    // Check that files loaded from a directory are recorded relative to it and a single file by
+    // its base name.
+    let test_dir = create_test_dir("read_relative_paths");
+    fs::create_dir(test_dir.join("fs")).unwrap();
+    fs::write(test_dir.join("fs/ext4.symtypes"), "bar int bar ( )\n").unwrap();
+    fs::write(test_dir.join("main.symtypes"), "baz int baz ( )\n").unwrap();
+
+    let mut syms = SymCorpus::new();
+    syms.load(&test_dir, 1).unwrap();
+    assert_eq!(
+        syms.to_consolidated_string().unwrap(),
+        concat!(
+            "bar int bar ( )\n",
+            "baz int baz ( )\n",
+            "F#fs/ext4.symtypes bar\n",
+            "F#main.symtypes baz\n", //
+        )
+    );
+
+    let mut syms = SymCorpus::new();
+    syms.load(&test_dir.join("fs/ext4.symtypes"), 1).unwrap();
+    assert_eq!(
+        syms.to_consolidated_string().unwrap(),
+        concat!(
+            "bar int bar ( )\n",
+            "F#ext4.symtypes bar\n", //
+        )
+    );
+
+    let mut syms = SymCorpus::new();
+    syms.load_multiple(
+        &vec![
+            test_dir.join("fs/ext4.symtypes"),
+            test_dir.join("main.symtypes"),
+        ],
+        1,
+    )
+    .unwrap();
+    assert_eq!(
+        syms.to_consolidated_string().unwrap(),
+        concat!(
+            "bar int bar ( )\n",
+            "baz int baz ( )\n",
+            "F#fs/ext4.symtypes bar\n",
+            "F#main.symtypes baz\n", //
+        )
+    );
+}
+
+#[test]
+fn write_normalized_paths() {
+    // Check that paths in F# records are written with forward slashes and sorted in this form.
+    let mut syms = SymCorpus::new();
+    for (path, export) in [
+        ("dir\\sub/b.symtypes", "bar"),
+        ("dir/a.symtypes", "baz"),
+        ("dir-x.symtypes", "qux"),
+    ] {
+        syms.load_buffer(
+            Path::new(path),
+            format!("{} int {} ( )\n", export, export).as_bytes(),
+        )
+        .unwrap();
+    }
+    assert_eq!(
+        syms.to_consolidated_string().unwrap(),
+        concat!(
+            "bar int bar ( )\n",
+            "baz int baz ( )\n",
+            "qux int qux ( )\n",
+            "F#dir-x.symtypes qux\n",
+            "F#dir/a.symtypes baz\n",
+            "F#dir/sub/b.symtypes bar\n", //
+        )
+    );
+}
+
+#[test]
+fn read_extensions() {
+    // Check that files with configured extensions are collected when loading a directory.
+    let test_dir = create_test_dir("read_extensions");
+    fs::write(test_dir.join("a.symref"), "bar int bar ( )\n").unwrap();
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(b"baz int baz ( )\n").unwrap();
+    fs::write(test_dir.join("b.SYMREF.gz"), encoder.finish().unwrap()).unwrap();
+    fs::write(test_dir.join("c.symtypes"), "qux int qux ( )\n").unwrap();
+    fs::write(test_dir.join("d"), "abc int abc ( )\n").unwrap();
+
+    let mut syms = SymCorpus::new();
+    syms.load(&test_dir, 1).unwrap();
+    assert_eq!(syms.export_names().collect::<Vec<_>>(), vec!["qux"]);
+
+    let mut syms = SymCorpus::new();
+    let options = LoadOptions {
+        extensions: vec!["symref".to_string()],
+        ..Default::default()
+    };
+    syms.load_with(&test_dir, 1, &options).unwrap();
+    assert_eq!(syms.export_names().collect::<Vec<_>>(), vec!["bar", "baz"]);
+
+    let mut syms = SymCorpus::new();
+    let options = LoadOptions {
+        extensions: vec!["".to_string(), "symtypes".to_string()],
+        ..Default::default()
+    };
+    syms.load_with(&test_dir, 1, &options).unwrap();
+    assert_eq!(syms.export_names().collect::<Vec<_>>(), vec!["abc", "qux"]);
+}
+
+#[test]
+fn read_skip_errors() {
+    // Check that an unreadable directory is skipped with the `skip_errors` option.
+    let test_dir = create_test_dir("read_skip_errors");
+    fs::write(test_dir.join("a.symtypes"), "bar int bar ( )\n").unwrap();
+    let sub_dir = test_dir.join("sub");
+    fs::create_dir(&sub_dir).unwrap();
+    fs::write(sub_dir.join("b.symtypes"), "baz int baz ( )\n").unwrap();
+    fs::set_permissions(&sub_dir, fs::Permissions::from_mode(0o000)).unwrap();
+
+    // The check is not possible if the permissions are not enforced, for instance for root. The
+    // skipping itself is covered independently of the permissions by a unit test of
+    // collect_symfiles().
+    if fs::read_dir(&sub_dir).is_ok() {
+        fs::set_permissions(&sub_dir, fs::Permissions::from_mode(0o755)).unwrap();
+        eprintln!("Skipping read_skip_errors: directory permissions are not enforced");
+        return;
+    }
+
+    let mut syms = SymCorpus::new();
+    let result = syms.load_with(&test_dir, 1, &LoadOptions::default());
+    assert!(result.is_err());
+
+    let diagnostics = Arc::new(Mutex::new(Vec::new()));
+    let mut syms = SymCorpus::new();
+    let options = LoadOptions {
+        skip_errors: true,
+        diagnostic_callback: Some({
+            let diagnostics = Arc::clone(&diagnostics);
+            Arc::new(move |diagnostic: &Diagnostic| {
+                diagnostics.lock().unwrap().push(diagnostic.clone())
+            })
+        }),
+        ..Default::default()
+    };
+    let result = syms.load_with(&test_dir, 1, &options);
+    fs::set_permissions(&sub_dir, fs::Permissions::from_mode(0o755)).unwrap();
+    result.unwrap();
+    let diagnostics = diagnostics.lock().unwrap();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].code, "SKIPPED_ENTRY");
+    assert_eq!(diagnostics[0].file.as_deref(), Some(sub_dir.as_path()));
+    assert_eq!(syms.export_names().collect::<Vec<_>>(), vec!["bar"]);
+}
+
+#[test]
+fn read_combined_options() {
+    // Check that several non-default load options apply together when loading a directory tree.
+    let test_dir = create_test_dir("read_combined_options");
+    fs::write(test_dir.join("a.symref"), "bar int bar ( s#foo )\n").unwrap();
+    fs::write(
+        test_dir.join("b.symref"),
+        concat!(
+            "s#foo struct foo { int a ; }\n",
+            "baz int baz ( s#foo )\n", //
+        ),
+    )
+    .unwrap();
+    fs::write(test_dir.join("c.symtypes"), "qux int qux ( )\n").unwrap();
+
+    let options = LoadOptions {
+        extensions: vec!["symref".to_string()],
+        check_refs: true,
+        ..Default::default()
+    };
+    let mut syms = SymCorpus::new();
+    assert_parse_err!(
+        syms.load_with(&test_dir, 2, &options),
+        "Found 1 unresolved type reference(s):\na.symref: Type s#foo is not known, referenced by bar"
+    );
+
+    let options = LoadOptions {
+        extensions: vec!["symref".to_string()],
+        exclude: vec!["a.*".to_string()],
+        check_refs: true,
+        ..Default::default()
+    };
+    let mut syms = SymCorpus::new();
+    syms.load_with(&test_dir, 2, &options).unwrap();
+    assert_eq!(syms.export_names().collect::<Vec<_>>(), vec!["baz"]);
+}
+
+#[test]
+fn write_dangling_ref_error() {
+    // Check that writing a corpus with a reference to an unknown type fails with an error.
+    let syms = SymCorpus::try_from(concat!(
+        "s#foo struct foo { s#missing * a ; }\n",
+        "bar int bar ( s#foo )\n", //
+    ))
+    .unwrap();
+    assert_parse_err!(
+        syms.write_consolidated(io::sink()),
+        "Type s#missing is not known in file '<literal>'"
+    );
+}
+
+#[test]
+fn compare_dangling_ref_error() {
+    // Check that comparing a corpus with a reference to an unknown type fails with an error.
+    let syms = SymCorpus::try_from(concat!(
+        "s#foo struct foo { s#missing * a ; }\n",
+        "bar int bar ( s#foo )\n", //
+    ))
+    .unwrap();
+    let syms2 = SymCorpus::try_from(concat!(
+        "s#foo struct foo { s#missing * a ; }\n",
+        "s#missing struct missing { int a ; }\n",
+        "bar int bar ( s#foo )\n", //
+    ))
+    .unwrap();
+    for num_workers in [1, 4] {
+        assert_parse_err!(
+            syms.compare_with(
+                &syms2,
+                &CompareOptions::default(),
+                &UnifiedRenderer::new(),
+                num_workers
+            ),
+            "Type s#missing is not known in file '<literal>'"
+        );
+    }
+}
+
+#[test]
+fn compare_expand_typedefs_cycle() {
+    // Check that expanding typedefs which reference each other fails with an error instead of
+    // recursing endlessly.
+    let syms = SymCorpus::try_from(concat!(
+        "t#x typedef t#y x\n",
+        "t#y typedef t#x y\n",
+        "foo int foo ( t#x )\n", //
+    ))
+    .unwrap();
+    let syms2 = SymCorpus::try_from(concat!(
+        "t#x typedef t#y x\n",
+        "t#y typedef t#x y\n",
+        "foo long foo ( t#x )\n", //
+    ))
+    .unwrap();
+    let options = CompareOptions {
+        expand_typedefs: true,
+        ..Default::default()
+    };
+    assert_parse_err!(
+        syms.compare_with(&syms2, &options, &UnifiedRenderer::new(), 1),
+        "<literal>: Typedef t#x references itself via t#x -> t#y -> t#x"
+    );
+
+    // Check that a long chain of typedefs is limited by the maximum nesting depth.
+    let mut input = String::new();
+    for i in 0..10 {
+        input.push_str(&format!("t#t{} typedef t#t{} t{}\n", i, i + 1, i));
+    }
+    input.push_str("t#t10 typedef int t10\n");
+    let syms = SymCorpus::try_from(format!("{}foo int foo ( t#t0 )\n", input).as_str()).unwrap();
+    let syms2 = SymCorpus::try_from(format!("{}foo long foo ( t#t0 )\n", input).as_str()).unwrap();
+    let options = CompareOptions {
+        expand_typedefs: true,
+        max_type_depth: Some(5),
+        ..Default::default()
+    };
+    assert_parse_err!(
+        syms.compare_with(&syms2, &options, &UnifiedRenderer::new(), 1),
+        "<literal>: Type t#t5 exceeds the maximum nesting depth of 5, referenced via t#t0 -> t#t1 -> t#t2 -> ..."
+    );
+    let options = CompareOptions {
+        expand_typedefs: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        syms.compare_with(&syms2, &options, &UnifiedRenderer::new(), 1)
+            .unwrap()
+            .len(),
+        1
+    );
+}
+
+#[test]
+fn export_names() {
+    // Check that the export names are listed sorted.
+    let syms = SymCorpus::try_from(concat!(
+        "s#foo struct foo { int a ; }\n",
+        "qux int qux ( s#foo )\n",
+        "bar int bar ( )\n", //
+    ))
+    .unwrap();
+    assert_eq!(syms.export_names().collect::<Vec<_>>(), vec!["bar", "qux"]);
+    assert!(syms.contains_export("qux"));
+    assert!(!syms.contains_export("s#foo"));
+    assert!(!syms.contains_export("baz"));
+}
+
+#[test]
+fn format_type() {
+    // Check that a type is shown together with all types that it references.
+    let syms = SymCorpus::try_from(concat!(
+        "s#baz struct baz { int b ; }\n",
+        "s#foo struct foo { int a ; s#baz * b ; }\n",
+        "bar int bar ( s#foo )\n", //
+    ))
+    .unwrap();
+    assert_eq!(
+        syms.format_type("bar").unwrap(),
+        vec![
+            "Found type bar in <literal>:",
+            "struct baz {",
+            "\tint b;",
+            "}",
+            "",
+            "struct foo {",
+            "\tint a;",
+            "\ts#baz * b;",
+            "}",
+            "",
+            "int bar ( s#foo )",
+        ]
+    );
+    let result = syms.format_type("qux");
+    assert_parse_err!(result, "Type qux is not known");
+}
+
+#[test]
+fn type_names() {
+    // Check that the type names, including exports, are listed sorted.
+    let syms = SymCorpus::try_from(concat!(
+        "s#foo struct foo { int a ; }\n",
+        "qux int qux ( s#foo )\n",
+        "bar int bar ( )\n", //
+    ))
+    .unwrap();
+    assert_eq!(
+        syms.type_names().collect::<Vec<_>>(),
+        vec!["bar", "qux", "s#foo"]
+    );
+}
+
+#[test]
+fn format_type_depth() {
+    // Check that referenced types are expanded only up to the given depth.
+    let syms = SymCorpus::try_from(concat!(
+        "s#baz struct baz { int b ; }\n",
+        "s#foo struct foo { int a ; s#baz * b ; }\n",
+        "bar int bar ( s#foo )\n", //
+    ))
+    .unwrap();
+    assert_eq!(
+        syms.format_type_with("s#foo", Some(1)).unwrap(),
+        vec![
+            "Found type s#foo in <literal>:",
+            "struct foo {",
+            "\tint a;",
+            "\ts#baz * b;",
+            "}",
+        ]
+    );
+    assert_eq!(
+        syms.format_type_with("bar", Some(2)).unwrap(),
+        vec![
+            "Found type bar in <literal>:",
+            "struct foo {",
+            "\tint a;",
+            "\ts#baz * b;",
+            "}",
+            "",
+            "int bar ( s#foo )",
+        ]
+    );
+    assert_eq!(
+        syms.format_type_with("bar", None).unwrap(),
+        syms.format_type("bar").unwrap()
+    );
+
+    // Check that a type reachable through chains of different lengths is expanded according to
+    // the shortest one.
+    let syms = SymCorpus::try_from(concat!(
+        "s#c struct c { int c ; }\n",
+        "s#b struct b { s#c * c ; }\n",
+        "s#a struct a { s#b * b ; }\n",
+        "bar int bar ( s#a , s#b )\n", //
+    ))
+    .unwrap();
+    assert_eq!(
+        syms.format_type_with("bar", Some(3)).unwrap(),
+        vec![
+            "Found type bar in <literal>:",
+            "struct c {",
+            "\tint c;",
+            "}",
+            "",
+            "struct b {",
+            "\ts#c * c;",
+            "}",
+            "",
+            "struct a {",
+            "\ts#b * b;",
+            "}",
+            "",
+            "int bar ( s#a,",
+            "s#b )",
+        ]
+    );
+}
+
+#[test]
+fn find_duplicate_types() {
+    // Check that differently named types with the same declaration are grouped together. Only the
+    // declared name of a type is ignored, not a member which happens to have the same name.
+    let syms = SymCorpus::try_from(concat!(
+        "s#foo struct foo { int a ; s#foo * next ; }\n",
+        "s#bar struct bar { int a ; s#foo * next ; }\n",
+        "s#baz struct baz { long a ; }\n",
+        "u#qux union qux { int a ; s#foo * next ; }\n",
+        "t#t1 typedef long t1\n",
+        "t#t2 typedef long t2\n",
+        "s#a struct a { int a ; }\n",
+        "s#b struct b { int b ; }\n",
+        "t#t3 typedef struct { int t3 ; } t3\n",
+        "t#t4 typedef struct { int t4 ; } t4\n",
+        "E#A 1\n",
+        "E#B 1\n",
+        "fn1 int fn1 ( s#foo , s#bar , s#baz , u#qux , t#t1 , t#t2 , E#A , E#B )\n",
+        "fn2 int fn2 ( s#a , s#b , t#t3 , t#t4 )\n", //
+    ))
+    .unwrap();
+    assert_eq!(
+        syms.find_duplicate_types(),
+        vec![vec!["s#bar", "s#foo"], vec!["t#t1", "t#t2"]]
+    );
+}
+
+#[test]
+fn diff_type() {
+    // Check that a single type is compared between two corpuses.
+    let syms = SymCorpus::try_from(concat!(
+        "s#foo struct foo { int a ; }\n",
+        "s#bar struct bar { int b ; }\n",
+        "baz int baz ( s#foo , s#bar )\n", //
+    ))
+    .unwrap();
+    let syms2 = SymCorpus::try_from(concat!(
+        "s#foo struct foo { int a ; }\n",
+        "s#bar struct bar { long b ; }\n",
+        "baz int baz ( s#foo , s#bar )\n", //
+    ))
+    .unwrap();
+
+    // Unchanged type.
+    assert_eq!(syms.diff_type(&syms2, "s#foo").unwrap(), None);
+    assert_eq!(syms.diff_type(&syms2, "baz").unwrap(), None);
+
+    // Changed type.
+    assert_eq!(
+        syms.diff_type(&syms2, "s#bar").unwrap(),
+        Some(vec![
+            "@@ -1,3 +1,3 @@".to_string(),
+            " struct bar {".to_string(),
+            "-\tint b;".to_string(),
+            "+\tlong b;".to_string(),
+            " }".to_string(),
+        ])
+    );
+
+    // Type missing on one side.
+    let syms3 = SymCorpus::try_from("baz int baz ( )\n").unwrap();
+    assert_eq!(syms.diff_type(&syms3, "s#foo").unwrap(), None);
+    assert_eq!(syms3.diff_type(&syms, "s#foo").unwrap(), None);
+}
+
+#[test]
+fn names_matching() {
+    // Check that names are filtered by a glob pattern.
+    let syms = SymCorpus::try_from(concat!(
+        "s#vfs_file struct vfs_file { int a ; }\n",
+        "vfs_read int vfs_read ( s#vfs_file )\n",
+        "vfs_write int vfs_write ( s#vfs_file )\n",
+        "kmalloc void * kmalloc ( )\n", //
+    ))
+    .unwrap();
+    assert_eq!(
+        syms.export_names_matching("vfs_*").collect::<Vec<_>>(),
+        ["vfs_read", "vfs_write"]
+    );
+    assert_eq!(
+        syms.type_names_matching("*vfs_?ile").collect::<Vec<_>>(),
+        ["s#vfs_file"]
+    );
+    assert_eq!(syms.export_names_matching("ext4_*").count(), 0);
+    assert_eq!(syms.export_names_matching("kmalloc").count(), 1);
+}
+
+#[test]
+fn type_definition() {
+    // Check that the definition of a type is returned only if the type has a single variant.
+    let mut syms = SymCorpus::new();
+    syms.load_buffer(
+        Path::new("test.symtypes"),
+        concat!(
+            "s#foo struct foo { int a ; }\n",
+            "s#bar struct bar { int b ; }\n",
+            "baz int baz ( s#foo , s#bar )\n", //
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+    syms.load_buffer(
+        Path::new("test2.symtypes"),
+        concat!(
+            "s#foo struct foo { long a ; }\n",
+            "qux int qux ( s#foo )\n", //
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+    assert_eq!(
+        syms.type_definition("s#bar"),
+        Some("struct bar { int b ; }".to_string())
+    );
+    assert_eq!(
+        syms.type_definition("baz"),
+        Some("int baz ( s#foo , s#bar )".to_string())
+    );
+    assert_eq!(syms.type_definition("s#missing"), None);
+    assert_eq!(syms.type_definition("s#foo"), None);
+}
+
+#[test]
+fn compare_changes_json() {
+    // Check the JSON representation of the changes found by a comparison.
+    let input = concat!(
+        "s#foo struct foo { int a ; }\n",
+        "bar int bar ( s#foo )\n",
+        "baz int baz ( )\n", //
+    );
+    let input2 = concat!(
+        "s#foo struct foo { long a ; }\n",
+        "bar int bar ( s#foo )\n",
+        "qux int qux ( )\n", //
+    );
+    let syms = SymCorpus::try_from(input).unwrap();
+    let syms2 = SymCorpus::try_from(input2).unwrap();
+
+    let changes = syms
+        .compare_with(
+            &syms2,
+            &CompareOptions::default(),
+            &UnifiedRenderer::new(),
+            1,
+        )
+        .unwrap();
+    let json = changes.iter().map(Change::to_json).collect::<Vec<_>>();
+    assert_eq!(
+        json,
+        vec![
+            json!({ "kind": "export_removed", "name": "baz" }),
+            json!({ "kind": "export_added", "name": "qux" }),
+            json!({
+                "kind": "type_changed",
+                "name": "s#foo",
+                "diff": ["s#foo", "@@ -1,3 +1,3 @@", " struct foo {", "-\tint a;", "+\tlong a;", " }"],
+                "files": ["<literal>"],
+                "additive": false,
+            }),
+        ]
+    );
+}
+
+#[test]
+fn read_duplicate_export() {
+    // Check that an export declared in two files is an error, unless duplicates are allowed.
+    let mut syms = SymCorpus::new();
+    syms.load_buffer(Path::new("test.symtypes"), "bar int bar ( )\n".as_bytes())
+        .unwrap();
+    assert_parse_err!(
+        syms.load_buffer(Path::new("test2.symtypes"), "bar int bar ( )\n".as_bytes()),
+        "Export bar is declared in both 'test.symtypes' and 'test2.symtypes'"
+    );
+
+    let mut syms = SymCorpus::new();
+    let options = LoadOptions {
+        allow_duplicate_exports: true,
+        ..Default::default()
+    };
+    for path in ["test.symtypes", "test2.symtypes"] {
+        syms.load_buffer_with(
+            Path::new(path),
+            "bar int bar ( )\n".as_bytes(),
+            InputFormat::Auto,
+            &options,
+        )
+        .unwrap();
+    }
+    assert_eq!(syms.num_files(), 2);
+    assert_eq!(syms.num_exports(), 1);
+}
+
+#[test]
+fn read_parallel_exports() {
+    // Check that when many files are loaded in parallel, every export is attributed to the file
+    // which declares it.
+    let test_dir = create_test_dir("read_parallel_exports");
+    for i in 0..64 {
+        fs::write(
+            test_dir.join(format!("test{}.symtypes", i)),
+            format!(
+                "s#foo struct foo {{ int a{} ; }}\nbar{} int bar{} ( s#foo )\n",
+                i, i, i
+            ),
+        )
+        .unwrap();
+    }
+
+    let mut syms = SymCorpus::new();
+    syms.load(&test_dir, 8).unwrap();
+    assert_eq!(syms.num_exports(), 64);
+    for i in 0..64 {
+        let name = format!("bar{}", i);
+        let closure = syms.extract_closure(&name).unwrap();
+        assert_eq!(
+            closure.type_definition(&name),
+            Some(format!("int {} ( s#foo )", name))
+        );
+        assert_eq!(
+            closure.type_definition("s#foo"),
+            Some(format!("struct foo {{ int a{} ; }}", i))
+        );
+    }
+}
+
+/// A reader that returns at most a few bytes on each read call.
+struct ChunkedReader<'a> {
+    data: &'a [u8],
+    chunk_size: usize,
+}
+
+impl io::Read for ChunkedReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.chunk_size.min(buf.len()).min(self.data.len());
+        buf[..len].copy_from_slice(&self.data[..len]);
+        self.data = &self.data[len..];
+        Ok(len)
+    }
+}
+
+#[test]
+fn read_large_streaming() {
+    // Check that a large consolidated input is loaded the same when streamed, regardless of how
+    // the input is split into reads, as when it is collected first through the cache, and that it
+    // round-trips through the writer.
+    let test_dir = create_test_dir("read_large_streaming");
+    const NUM_FILES: usize = 2000;
+    let mut input = String::new();
+    for i in 0..NUM_FILES {
+        input.push_str(&format!("s#foo@{} struct foo {{ int a{} ; }}\n", i, i));
+    }
+    for i in 0..NUM_FILES {
+        input.push_str(&format!("bar{} int bar{} ( s#foo )\n", i, i));
+    }
+    for i in 0..NUM_FILES {
+        input.push_str(&format!("F#test{}.symtypes s#foo@{} bar{}\n", i, i, i));
+    }
+
+    let mut syms = SymCorpus::new();
+    syms.load_buffer(Path::new("large.symtypes"), input.as_bytes())
+        .unwrap();
+    assert_eq!(syms.export_names().count(), NUM_FILES);
+
+    let mut syms2 = SymCorpus::new();
+    syms2
+        .load_buffer(
+            Path::new("large.symtypes"),
+            ChunkedReader {
+                data: input.as_bytes(),
+                chunk_size: 7,
+            },
+        )
+        .unwrap();
+
+    let output = syms.to_consolidated_string().unwrap();
+    assert_eq!(syms2.to_consolidated_string().unwrap(), output);
+
+    // Load the input twice with the cache enabled, the second load is served from the cache.
+    let input_path = test_dir.join("large.symtypes");
+    fs::write(&input_path, &input).unwrap();
+    let load_options = LoadOptions {
+        cache_dir: Some(test_dir.join("cache")),
+        ..Default::default()
+    };
+    for _ in 0..2 {
+        let mut syms_cached = SymCorpus::new();
+        syms_cached
+            .load_with(&input_path, 1, &load_options)
+            .unwrap();
+        assert_eq!(syms_cached.to_consolidated_string().unwrap(), output);
+    }
+
+    let mut syms3 = SymCorpus::new();
+    syms3
+        .load_buffer(Path::new("large.symtypes"), output.as_bytes())
+        .unwrap();
+    assert_eq!(syms3.to_consolidated_string().unwrap(), output);
+}
+
+#[test]
+fn progress_callback() {
+    // Check that the progress callback is called once for each loaded file and compared export.
+    let test_dir = create_test_dir("progress_callback");
+    for i in 0..5 {
+        fs::write(
+            test_dir.join(format!("test{}.symtypes", i)),
+            format!("foo{} int foo{} ( )\n", i, i),
+        )
+        .unwrap();
+    }
+
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let load_calls = calls.clone();
+    let load_options = LoadOptions {
+        progress_callback: Some(Arc::new(move |done, total| {
+            load_calls.lock().unwrap().push((done, total))
+        })),
+        ..Default::default()
+    };
+    let mut syms = SymCorpus::new();
+    syms.load_with(&test_dir, 2, &load_options).unwrap();
+    let mut load_reports = calls.lock().unwrap().clone();
+    load_reports.sort();
+    assert_eq!(load_reports, (1..=5).map(|i| (i, 5)).collect::<Vec<_>>());
+
+    calls.lock().unwrap().clear();
+    let compare_calls = calls.clone();
+    let options = CompareOptions {
+        progress_callback: Some(Arc::new(move |done, total| {
+            compare_calls.lock().unwrap().push((done, total))
+        })),
+        ..Default::default()
+    };
+    let changes = syms
+        .compare_with(&syms, &options, &UnifiedRenderer::new(), 2)
+        .unwrap();
+    assert!(changes.is_empty());
+    let mut compare_reports = calls.lock().unwrap().clone();
+    compare_reports.sort();
+    assert_eq!(compare_reports, (1..=5).map(|i| (i, 5)).collect::<Vec<_>>());
+}
+
+#[test]
+fn load_multiple_observer() {
+    // Check that the observer is called once for each loaded file with its path and result.
+    let test_dir = create_test_dir("load_multiple_observer");
+    let mut symfiles = Vec::new();
+    for i in 0..4 {
+        let path = test_dir.join(format!("test{}.symtypes", i));
+        fs::write(&path, format!("foo{} int foo{} ( )\n", i, i)).unwrap();
+        symfiles.push(path);
+    }
+
+    let mut observed = Vec::new();
+    let mut syms = SymCorpus::new();
+    syms.load_multiple_with_observer(&symfiles, 2, &LoadOptions::default(), |path, result| {
+        observed.push((path.to_path_buf(), result.is_ok()))
+    })
+    .unwrap();
+    observed.sort();
+    assert_eq!(
+        observed,
+        symfiles
+            .iter()
+            .map(|path| (path.clone(), true))
+            .collect::<Vec<_>>()
+    );
+
+    // Check that the observer receives the error of a malformed file.
+    let bad_path = test_dir.join("bad.symtypes");
+    fs::write(&bad_path, "foo int foo ( )\nfoo int foo ( )\n").unwrap();
+    let mut errors = Vec::new();
+    let mut syms = SymCorpus::new();
+    let result = syms.load_multiple_with_observer(
+        &vec![bad_path.clone()],
+        1,
+        &LoadOptions::default(),
+        |path, result| errors.push((path.to_path_buf(), result.unwrap_err().to_string())),
+    );
+    let exp_desc = format!("{}:2: Duplicate record 'foo'", bad_path.display());
+    assert_parse_err!(result, exp_desc);
+    assert_eq!(errors, [(bad_path, exp_desc)]);
+}
+
+#[test]
+fn read_dir_malformed_file() {
+    // Check that an error in one file of a directory tree fails the whole load, regardless of the
+    // number of workers.
+    let test_dir = create_test_dir("read_dir_malformed_file");
+    for i in 0..8 {
+        fs::write(
+            test_dir.join(format!("test{}.symtypes", i)),
+            format!("foo{} int foo{} ( )\n", i, i),
+        )
+        .unwrap();
+    }
+    fs::write(test_dir.join("bad.symtypes"), "bar int bar ( s#baz )\n\n").unwrap();
+
+    for num_workers in [1, 4] {
+        let mut syms = SymCorpus::new();
+        let result = syms.load(&test_dir, num_workers);
+        assert_parse_err!(
+            result,
+            format!(
+                "{}:2: Expected a record name",
+                test_dir.join("bad.symtypes").display()
+            )
+        );
+    }
+}
+
+#[test]
+fn compare_consolidated_with_dir() {
+    // Check that a consolidated file compares equal to the directory tree which it was created
+    // from, in both directions.
+    let test_dir = create_test_dir("compare_consolidated_with_dir");
+    let tree_dir = test_dir.join("tree");
+    fs::create_dir_all(tree_dir.join("sub")).unwrap();
+    fs::write(
+        tree_dir.join("test.symtypes"),
+        concat!(
+            "s#foo struct foo { int a ; }\n",
+            "t#bar typedef s#foo bar\n",
+            "baz int baz ( t#bar )\n", //
+        ),
+    )
+    .unwrap();
+    fs::write(
+        tree_dir.join("sub/test2.symtypes"),
+        concat!(
+            "s#foo struct foo { UNKNOWN }\n",
+            "t#bar typedef s#foo bar\n",
+            "qux int qux ( t#bar , s#foo )\n", //
+        ),
+    )
+    .unwrap();
+    fs::write(
+        tree_dir.join("sub/test3.symtypes"),
+        "e#state enum state { A , B }\nquux int quux ( e#state )\n",
+    )
+    .unwrap();
+
+    let mut syms = SymCorpus::new();
+    syms.load(&tree_dir, 2).unwrap();
+    let consolidated_path = test_dir.join("consolidated.symtypes");
+    syms.write_consolidated(fs::File::create(&consolidated_path).unwrap())
+        .unwrap();
+
+    let mut syms2 = SymCorpus::new();
+    syms2.load(&consolidated_path, 1).unwrap();
+
+    for (a, b) in [(&syms, &syms2), (&syms2, &syms)] {
+        let changes = a
+            .compare_with(b, &CompareOptions::default(), &UnifiedRenderer::new(), 2)
+            .unwrap();
+        assert_eq!(changes, []);
+    }
+}
+
+#[test]
+fn symbol_hash() {
+    // Check that the hash of an export depends only on the closure of its types.
+    let syms = SymCorpus::try_from(concat!(
+        "s#foo struct foo { int a ; }\n",
+        "s#bar struct bar { long b ; }\n",
+        "foo int foo ( s#foo )\n",
+        "bar int bar ( s#bar )\n", //
+    ))
+    .unwrap();
+    let syms2 = SymCorpus::try_from(concat!(
+        "s#foo struct foo { int a ; }\n",
+        "s#bar struct bar { long b ; int c ; }\n",
+        "foo int foo ( s#foo )\n",
+        "bar int bar ( s#bar )\n", //
+    ))
+    .unwrap();
+
+    let hash = syms.symbol_hash("foo").unwrap();
+    assert_eq!(syms2.symbol_hash("foo"), Some(hash));
+    assert_ne!(syms.symbol_hash("bar"), syms2.symbol_hash("bar"));
+    assert_ne!(syms.symbol_hash("bar"), Some(hash));
+    assert_eq!(syms.symbol_hash("baz"), None);
+    assert_eq!(
+        syms.compare_hashes(&syms2),
+        [("bar", syms.symbol_hash("bar"), syms2.symbol_hash("bar"))]
+    );
+
+    // Check that the hash is stable when the same data is loaded in a different way.
+    let test_dir = create_test_dir("symbol_hash");
+    fs::write(
+        test_dir.join("test.symtypes"),
+        "s#foo struct foo { int a ; }\nfoo int foo ( s#foo )\n",
+    )
+    .unwrap();
+    fs::write(
+        test_dir.join("test2.symtypes"),
+        "s#bar struct bar { long b ; }\nbar int bar ( s#bar )\n",
+    )
+    .unwrap();
+    for num_workers in [1, 4] {
+        let mut syms3 = SymCorpus::new();
+        syms3.load(&test_dir, num_workers).unwrap();
+        assert_eq!(syms3.symbol_hash("foo"), Some(hash));
+        assert_eq!(syms3.symbol_hash("bar"), syms.symbol_hash("bar"));
+    }
+}
+
+#[test]
+fn compare_tail_append() {
+    // Check that a struct change which only appends members is classified as additive and
+    // compatible with the allow_tail_append option, and as breaking otherwise.
+    let syms = SymCorpus::try_from(concat!(
+        "s#foo struct foo { int a ; }\n",
+        "bar int bar ( s#foo )\n", //
+    ))
+    .unwrap();
+    let syms2 = SymCorpus::try_from(concat!(
+        "s#foo struct foo { int a ; int b ; }\n",
+        "bar int bar ( s#foo )\n", //
+    ))
+    .unwrap();
+
+    let changes = syms
+        .compare_with(
+            &syms2,
+            &CompareOptions::default(),
+            &UnifiedRenderer::new(),
+            1,
+        )
+        .unwrap();
+    assert_eq!(changes.len(), 1);
+    assert!(matches!(
+        &changes[0],
+        Change::TypeChanged {
+            additive: false,
+            ..
+        }
+    ));
+    assert!(changes[0].is_breaking());
+    assert!(CompareSummary::from_changes(&changes).has_breaking_differences());
+
+    let options = CompareOptions {
+        allow_tail_append: true,
+        ..Default::default()
+    };
+    let changes = syms
+        .compare_with(&syms2, &options, &UnifiedRenderer::new(), 1)
+        .unwrap();
+    assert_eq!(changes.len(), 1);
+    assert!(
+        matches!(&changes[0], Change::TypeChanged { name, additive: true, .. }
+        if name == "s#foo")
+    );
+    assert_eq!(changes[0].severity(), ChangeSeverity::Compatible);
+    assert_eq!(changes[0].to_json()["additive"], json!(true));
+    let summary = CompareSummary::from_changes(&changes);
+    assert_eq!(summary.changed_types, 1);
+    assert!(!summary.has_breaking_differences());
+    assert_eq!(
+        syms.format_changes(&syms2, &changes, &options)[0],
+        "Type s#foo is only extended by appended members"
+    );
+
+    // The change is filtered out when only breaking changes are requested.
+    let options = CompareOptions {
+        allow_tail_append: true,
+        min_severity: ChangeSeverity::Breaking,
+        ..Default::default()
+    };
+    let changes = syms
+        .compare_with(&syms2, &options, &UnifiedRenderer::new(), 1)
+        .unwrap();
+    assert_eq!(changes, []);
+}
+
+#[test]
+fn compare_breaking_changes() {
+    // Check that only removed exports and changed types are classified as breaking.
+    let syms = SymCorpus::try_from("foo int foo ( )\n").unwrap();
+    let syms2 = SymCorpus::try_from("foo int foo ( )\nbar int bar ( )\n").unwrap();
+
+    // An add-only difference is not breaking.
+    let changes = syms
+        .compare_with(
+            &syms2,
+            &CompareOptions::default(),
+            &UnifiedRenderer::new(),
+            1,
+        )
+        .unwrap();
+    assert_eq!(changes, [Change::ExportAdded("bar".to_string())]);
+    assert!(!changes[0].is_breaking());
+    let summary = CompareSummary::from_changes(&changes);
+    assert!(summary.has_differences());
+    assert!(!summary.has_breaking_differences());
+
+    // A removal is always breaking.
+    let changes = syms2
+        .compare_with(
+            &syms,
+            &CompareOptions::default(),
+            &UnifiedRenderer::new(),
+            1,
+        )
+        .unwrap();
+    assert_eq!(changes, [Change::ExportRemoved("bar".to_string())]);
+    assert!(changes[0].is_breaking());
+    assert!(CompareSummary::from_changes(&changes).has_breaking_differences());
+}
+
+#[test]
+fn change_severity() {
+    // Check the severity assigned to each kind of change.
+    assert_eq!(
+        Change::ExportRemoved("foo".to_string()).severity(),
+        ChangeSeverity::Breaking
+    );
+    assert_eq!(
+        Change::ExportAdded("foo".to_string()).severity(),
+        ChangeSeverity::Compatible
+    );
+    let type_changed = Change::TypeChanged {
+        name: "s#foo".to_string(),
+        diff: Vec::new(),
+        files: vec![PathBuf::from("test.symtypes")],
+        defined_in: None,
+        additive: false,
+    };
+    assert_eq!(type_changed.severity(), ChangeSeverity::Breaking);
+    assert_eq!(
+        "breaking".parse::<ChangeSeverity>().unwrap(),
+        ChangeSeverity::Breaking
+    );
+    assert_parse_err!(
+        "fatal".parse::<ChangeSeverity>(),
+        "Unknown severity 'fatal'"
+    );
+
+    // Check that changes below the minimum severity are omitted.
+    let syms = SymCorpus::try_from(concat!(
+        "s#foo struct foo { int a ; }\n",
+        "foo int foo ( s#foo )\n",
+        "bar int bar ( )\n", //
+    ))
+    .unwrap();
+    let syms2 = SymCorpus::try_from(concat!(
+        "s#foo struct foo { long a ; }\n",
+        "foo int foo ( s#foo )\n",
+        "baz int baz ( )\n", //
+    ))
+    .unwrap();
+    let options = CompareOptions {
+        min_severity: ChangeSeverity::Breaking,
+        ..Default::default()
+    };
+    let changes = syms
+        .compare_with(&syms2, &options, &UnifiedRenderer::new(), 1)
+        .unwrap();
+    assert_eq!(changes.len(), 2);
+    assert_eq!(changes[0], Change::ExportRemoved("bar".to_string()));
+    assert!(matches!(&changes[1], Change::TypeChanged { name, .. } if name == "s#foo"));
+}
+
+#[test]
+fn export_diff() {
+    // Check that the added and removed exports are listed without comparing their types.
+    let syms = SymCorpus::try_from(concat!(
+        "foo int foo ( )\n",
+        "bar int bar ( )\n", //
+    ))
+    .unwrap();
+    let syms2 = SymCorpus::try_from(concat!(
+        "foo long foo ( )\n",
+        "baz int baz ( )\n", //
+    ))
+    .unwrap();
+    assert_eq!(syms.export_diff(&syms2), (vec!["bar"], vec!["baz"]));
+    assert_eq!(syms2.export_diff(&syms), (vec!["baz"], vec!["bar"]));
+    assert_eq!(syms.export_diff(&syms), (vec![], vec![]));
+}
+
+#[test]
+fn compare_reversed() {
+    // Check that swapping the corpuses inverts added and removed exports and keeps the changed
+    // types.
+    let syms = SymCorpus::try_from(concat!(
+        "s#foo struct foo { int a ; }\n",
+        "foo int foo ( s#foo )\n",
+        "bar int bar ( )\n", //
+    ))
+    .unwrap();
+    let syms2 = SymCorpus::try_from(concat!(
+        "s#foo struct foo { long a ; }\n",
+        "foo int foo ( s#foo )\n",
+        "baz int baz ( )\n", //
+    ))
+    .unwrap();
+
+    let classify = |changes: Vec<Change>| {
+        changes
+            .into_iter()
+            .map(|change| match change {
+                Change::ExportRemoved(name) => ("removed", name),
+                Change::ExportAdded(name) => ("added", name),
+                Change::TypeChanged { name, .. } => ("changed", name),
+            })
+            .collect::<Vec<_>>()
+    };
+    let options = CompareOptions::default();
+    let renderer = UnifiedRenderer::new();
+    let changes = classify(syms.compare_with(&syms2, &options, &renderer, 1).unwrap());
+    let reversed = classify(syms2.compare_with(&syms, &options, &renderer, 1).unwrap());
+    assert_eq!(
+        changes,
+        [
+            ("removed", "bar".to_string()),
+            ("added", "baz".to_string()),
+            ("changed", "s#foo".to_string())
+        ]
+    );
+    assert_eq!(
+        reversed,
+        [
+            ("removed", "baz".to_string()),
+            ("added", "bar".to_string()),
+            ("changed", "s#foo".to_string())
+        ]
+    );
+}
+
+#[test]
+fn read_include_exclude() {
+    // Check that files of a directory tree are filtered by the include and exclude patterns
+    // matched against their relative paths.
+    let test_dir = create_test_dir("read_include_exclude");
+    for (i, rel_path) in [
+        "drivers/gpu/drm/drm.symtypes",
+        "drivers/gpu/i915.symtypes",
+        "drivers/net/net.symtypes",
+        "fs/ext4/inode.symtypes",
+        "kernel.symtypes",
+    ]
+    .iter()
+    .enumerate()
+    {
+        let path = test_dir.join(rel_path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, format!("foo{} int foo{} ( )\n", i, i)).unwrap();
+    }
+
+    let load = |include: &[&str], exclude: &[&str]| {
+        let options = LoadOptions {
+            include: include.iter().map(|pattern| pattern.to_string()).collect(),
+            exclude: exclude.iter().map(|pattern| pattern.to_string()).collect(),
+            ..Default::default()
+        };
+        let mut syms = SymCorpus::new();
+        syms.load_with(&test_dir, 1, &options).unwrap();
+        syms.export_names()
+            .map(|name| name.to_string())
+            .collect::<Vec<_>>()
+    };
+
+    // An exclude pattern removes a subtree.
+    assert_eq!(load(&[], &["drivers/gpu/**"]), ["foo2", "foo3", "foo4"]);
+
+    // An include pattern whitelists a subset, exclude patterns still apply.
+    assert_eq!(load(&["drivers/**"], &[]), ["foo0", "foo1", "foo2"]);
+    assert_eq!(
+        load(&["drivers/**", "*.symtypes"], &["**/drm/*"]),
+        ["foo1", "foo2", "foo4"]
+    );
+}
+
+#[test]
+fn read_cache() {
+    // Check that a repeated load of an unchanged directory tree reuses the cached records and that
+    // a changed file is parsed again.
+    let test_dir = create_test_dir("read_cache");
+    let tree_dir = test_dir.join("tree");
+    let cache_dir = test_dir.join("cache");
+    fs::create_dir_all(&tree_dir).unwrap();
+    fs::write(
+        tree_dir.join("test.symtypes"),
+        "s#foo struct foo { int a ; }\nbar int bar ( s#foo )\n",
+    )
+    .unwrap();
+    fs::write(
+        tree_dir.join("test2.symtypes"),
+        "s#foo struct foo { UNKNOWN }\nbaz int baz ( s#foo )\n",
+    )
+    .unwrap();
+
+    let parsed = Arc::new(Mutex::new(Vec::new()));
+    let load = || {
+        let parse_calls = parsed.clone();
+        let options = LoadOptions {
+            cache_dir: Some(cache_dir.clone()),
+            parse_callback: Some(Arc::new(move |path| {
+                parse_calls.lock().unwrap().push(path.to_path_buf())
+            })),
+            ..Default::default()
+        };
+        let mut syms = SymCorpus::new();
+        syms.load_with(&tree_dir, 2, &options).unwrap();
+        let mut parsed = parsed.lock().unwrap();
+        parsed.sort();
+        let result = (syms.to_consolidated_string().unwrap(), parsed.clone());
+        parsed.clear();
+        result
+    };
+
+    let (output, parsed_files) = load();
+    assert_eq!(
+        parsed_files,
+        [
+            tree_dir.join("test.symtypes"),
+            tree_dir.join("test2.symtypes")
+        ]
+    );
+    let (cached_output, parsed_files) = load();
+    assert_eq!(cached_output, output);
+    assert_eq!(parsed_files, [] as [PathBuf; 0]);
+
+    // Change one file, its size differs so the change is detected regardless of the resolution of
+    // the modification time.
+    fs::write(
+        tree_dir.join("test2.symtypes"),
+        "s#foo struct foo { long a ; }\nbaz int baz ( s#foo )\n",
+    )
+    .unwrap();
+    let (output, parsed_files) = load();
+    assert_eq!(parsed_files, [tree_dir.join("test2.symtypes")]);
+    assert_eq!(
+        output,
+        concat!(
+            "s#foo@0 struct foo { int a ; }\n",
+            "s#foo@1 struct foo { long a ; }\n",
+            "bar int bar ( s#foo )\n",
+            "baz int baz ( s#foo )\n",
+            "F#test.symtypes s#foo@0 bar\n",
+            "F#test2.symtypes s#foo@1 baz\n", //
+        )
+    );
+}
+
+#[test]
+fn write_explicit_variants() {
+    // Check that the explicit form suffixes all records and lists all types in F# records, and
+    // that it reloads to the same corpus as the implicit form.
+    let mut syms = SymCorpus::new();
+    syms.load_buffer(
+        Path::new("test.symtypes"),
+        concat!(
+            "s#foo struct foo { s#bar a ; }\n",
+            "s#bar struct bar { int a ; }\n",
+            "bar int bar ( s#foo )\n", //
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+    syms.load_buffer(
+        Path::new("test2.symtypes"),
+        concat!(
+            "s#foo struct foo { UNKNOWN }\n",
+            "baz int baz ( s#foo )\n", //
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+
+    let options = ConsolidateOptions {
+        explicit_variants: true,
+        ..Default::default()
+    };
+    let mut out = Vec::new();
+    syms.write_consolidated_with(&mut out, &options).unwrap();
+    let explicit = String::from_utf8(out).unwrap();
+    assert_eq!(
+        explicit,
+        concat!(
+            "s#bar@0 struct bar { int a ; }\n",
+            "s#foo@0 struct foo { s#bar a ; }\n",
+            "s#foo@1 struct foo { UNKNOWN }\n",
+            "bar@0 int bar ( s#foo )\n",
+            "baz@0 int baz ( s#foo )\n",
+            "F#test.symtypes s#bar@0 s#foo@0 bar@0\n",
+            "F#test2.symtypes s#foo@1 baz@0\n", //
+        )
+    );
+
+    let implicit = syms.to_consolidated_string().unwrap();
+    let syms2 = SymCorpus::try_from(explicit.as_str()).unwrap();
+    let syms3 = SymCorpus::try_from(implicit.as_str()).unwrap();
+    assert_eq!(syms2.to_consolidated_string().unwrap(), implicit);
+    assert_eq!(syms3.to_consolidated_string().unwrap(), implicit);
+    let changes = syms2
+        .compare_with(
+            &syms3,
+            &CompareOptions::default(),
+            &UnifiedRenderer::new(),
+            1,
+        )
+        .unwrap();
+    assert!(changes.is_empty());
+}
+
+#[test]
+fn write_preserve_formatting() {
+    // Check that records with irregular spacing are written verbatim when the formatting is
+    // preserved, and in the normal form otherwise.
+    let input = concat!(
+        "s#foo struct  foo {\tint a ;  }\n",
+        "bar  int bar ( s#foo )\n",
+        "F#test.symtypes bar\n", //
+    );
+    let load_options = LoadOptions {
+        preserve_formatting: true,
+        ..Default::default()
+    };
+    let mut syms = SymCorpus::new();
+    syms.load_buffer_with(
+        Path::new("test_consolidated"),
+        input.as_bytes(),
+        InputFormat::Auto,
+        &load_options,
+    )
+    .unwrap();
+
+    let options = ConsolidateOptions {
+        preserve_formatting: true,
+        ..Default::default()
+    };
+    let mut out = Vec::new();
+    syms.write_consolidated_with(&mut out, &options).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), input);
+
+    assert_eq!(
+        syms.to_consolidated_string().unwrap(),
+        concat!(
+            "s#foo struct foo { int a ; }\n",
+            "bar int bar ( s#foo )\n",
+            "F#test.symtypes bar\n", //
+        )
+    );
+}
+
+#[test]
+fn write_sort_orders() {
+    // Check the output of each sort order and that it is stable.
+    let mut syms = SymCorpus::new();
+    syms.load_buffer(
+        Path::new("test2.symtypes"),
+        concat!(
+            "s#b struct b { int x ; }\n",
+            "t#c typedef int c\n",
+            "zed int zed ( s#b , t#c )\n", //
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+    syms.load_buffer(
+        Path::new("test.symtypes"),
+        concat!(
+            "s#a struct a { int x ; }\n",
+            "foo int foo ( s#a , t#c )\n",
+            "t#c typedef int c\n", //
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+
+    let write_sorted = |sort| {
+        let options = ConsolidateOptions {
+            sort,
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        syms.write_consolidated_with(&mut out, &options).unwrap();
+        String::from_utf8(out).unwrap()
+    };
+
+    let by_name = write_sorted(SortOrder::Name);
+    assert_eq!(by_name, syms.to_consolidated_string().unwrap());
+    assert_eq!(
+        by_name,
+        concat!(
+            "s#a struct a { int x ; }\n",
+            "s#b struct b { int x ; }\n",
+            "t#c typedef int c\n",
+            "foo int foo ( s#a , t#c )\n",
+            "zed int zed ( s#b , t#c )\n",
+            "F#test.symtypes foo\n",
+            "F#test2.symtypes zed\n", //
+        )
+    );
+
+    let by_file = write_sorted(SortOrder::File);
+    assert_eq!(
+        by_file,
+        concat!(
+            "s#a struct a { int x ; }\n",
+            "t#c typedef int c\n",
+            "foo int foo ( s#a , t#c )\n",
+            "s#b struct b { int x ; }\n",
+            "zed int zed ( s#b , t#c )\n",
+            "F#test.symtypes foo\n",
+            "F#test2.symtypes zed\n", //
+        )
+    );
+
+    let unsorted = write_sorted(SortOrder::None);
+    assert_eq!(
+        unsorted,
+        concat!(
+            "zed int zed ( s#b , t#c )\n",
+            "s#b struct b { int x ; }\n",
+            "t#c typedef int c\n",
+            "foo int foo ( s#a , t#c )\n",
+            "s#a struct a { int x ; }\n",
+            "F#test2.symtypes zed\n",
+            "F#test.symtypes foo\n", //
+        )
+    );
+
+    // Check that each order is stable and reloads to the same corpus.
+    for (sort, output) in [
+        (SortOrder::Name, &by_name),
+        (SortOrder::File, &by_file),
+        (SortOrder::None, &unsorted),
+    ] {
+        assert_eq!(&write_sorted(sort), output);
+        let syms2 = SymCorpus::try_from(output.as_str()).unwrap();
+        assert_eq!(syms2.to_consolidated_string().unwrap(), by_name);
+    }
+}
+
+#[test]
+fn write_wrapped_file_records() {
+    // Check that long F# records are wrapped and that the wrapped output reloads identically.
+    let mut syms = SymCorpus::new();
+    syms.load_buffer(
+        Path::new("test.symtypes"),
+        concat!(
+            "s#a struct a { int x ; }\n",
+            "s#b struct b { int x ; }\n",
+            "s#c struct c { int x ; }\n",
+            "s#d struct d { int x ; }\n",
+            "foo int foo ( s#a , s#b , s#c , s#d )\n", //
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+    syms.load_buffer(
+        Path::new("test2.symtypes"),
+        concat!(
+            "s#a struct a { long x ; }\n",
+            "s#b struct b { long x ; }\n",
+            "s#c struct c { long x ; }\n",
+            "s#d struct d { long x ; }\n",
+            "bar int bar ( s#a , s#b , s#c , s#d )\n", //
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+
+    let options = ConsolidateOptions {
+        wrap_file_records: Some(2),
+        ..Default::default()
+    };
+    let mut out = Vec::new();
+    syms.write_consolidated_with(&mut out, &options).unwrap();
+    let wrapped = String::from_utf8(out).unwrap();
+    assert_eq!(
+        wrapped,
+        concat!(
+            "s#a@0 struct a { int x ; }\n",
+            "s#a@1 struct a { long x ; }\n",
+            "s#b@0 struct b { int x ; }\n",
+            "s#b@1 struct b { long x ; }\n",
+            "s#c@0 struct c { int x ; }\n",
+            "s#c@1 struct c { long x ; }\n",
+            "s#d@0 struct d { int x ; }\n",
+            "s#d@1 struct d { long x ; }\n",
+            "bar int bar ( s#a , s#b , s#c , s#d )\n",
+            "foo int foo ( s#a , s#b , s#c , s#d )\n",
+            "F#test.symtypes s#a@0 s#b@0 \\\n",
+            "  s#c@0 s#d@0 \\\n",
+            "  foo\n",
+            "F#test2.symtypes s#a@1 s#b@1 \\\n",
+            "  s#c@1 s#d@1 \\\n",
+            "  bar\n", //
+        )
+    );
+
+    let syms2 = SymCorpus::try_from(wrapped.as_str()).unwrap();
+    assert_eq!(
+        syms2.to_consolidated_string().unwrap(),
+        syms.to_consolidated_string().unwrap()
+    );
+
+    // Check that an unterminated record is reported.
+    let mut syms3 = SymCorpus::new();
+    let result = syms3.load_buffer(
+        Path::new("test_consolidated"),
+        concat!(
+            "foo int foo ( )\n",
+            "F#test.symtypes foo \\\n", //
+        )
+        .as_bytes(),
+    );
+    assert_parse_err!(
+        result,
+        "test_consolidated: Unterminated file record 'F#test.symtypes'"
+    );
+}
+
+#[test]
+fn read_unbalanced_brackets() {
+    // Check that a record with unbalanced brackets is reported in the strict mode and only warned
+    // about otherwise.
+    let input = concat!(
+        "s#foo struct foo { int a ; int b\n",
+        "bar int bar ( s#foo )\n", //
+    );
+    let mut syms = SymCorpus::new();
+    let options = LoadOptions {
+        check_brackets: true,
+        ..Default::default()
+    };
+    syms.load_buffer_with(
+        Path::new("test.symtypes"),
+        input.as_bytes(),
+        InputFormat::Auto,
+        &options,
+    )
+    .unwrap();
+    assert_eq!(syms.num_exports(), 1);
+
+    let mut syms = SymCorpus::new();
+    let options = LoadOptions {
+        strict: true,
+        ..Default::default()
+    };
+    let result = syms.load_buffer_with(
+        Path::new("test.symtypes"),
+        input.as_bytes(),
+        InputFormat::Auto,
+        &options,
+    );
+    assert_parse_err!(
+        result,
+        "test.symtypes:1: Record 's#foo' has unbalanced brackets, missing '}'"
+    );
+
+    let mut syms = SymCorpus::new();
+    let result = syms.load_buffer_with(
+        Path::new("test.symtypes"),
+        "foo int foo ( )\nbar int bar ( int } )\n".as_bytes(),
+        InputFormat::Auto,
+        &options,
+    );
+    assert_parse_err!(
+        result,
+        "test.symtypes:2: Record 'bar' has unbalanced brackets, unexpected '}'"
+    );
+}
+
+#[test]
+fn compare_only_symbols() {
+    // Check that the comparison can be restricted to selected exports.
+    let syms = SymCorpus::try_from(concat!(
+        "s#foo struct foo { int a ; }\n",
+        "s#bar struct bar { int b ; }\n",
+        "foo int foo ( s#foo )\n",
+        "bar int bar ( s#bar )\n",
+        "baz int baz ( )\n", //
+    ))
+    .unwrap();
+    let syms2 = SymCorpus::try_from(concat!(
+        "s#foo struct foo { long a ; }\n",
+        "s#bar struct bar { long b ; }\n",
+        "foo int foo ( s#foo )\n",
+        "bar int bar ( s#bar )\n",
+        "qux int qux ( )\n", //
+    ))
+    .unwrap();
+
+    let compare = |only: &[&str]| {
+        let options = CompareOptions {
+            only_symbols: only.iter().map(|name| name.to_string()).collect(),
+            ..Default::default()
+        };
+        syms.compare_with(&syms2, &options, &UnifiedRenderer::new(), 1)
+    };
+    let changes = compare(&["foo"]).unwrap();
+    assert_eq!(changes.len(), 1);
+    assert!(matches!(&changes[0], Change::TypeChanged { name, .. } if name == "s#foo"));
+    assert_eq!(
+        compare(&["qux"]).unwrap(),
+        [Change::ExportAdded("qux".to_string())]
+    );
+    assert_eq!(compare(&[]).unwrap().len(), 4);
+    assert_parse_err!(
+        compare(&["foo", "missing"]),
+        "Export 'missing' is not present in either corpus"
+    );
 }